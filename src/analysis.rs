@@ -0,0 +1,319 @@
+
+use super::screen::*;
+use super::term::*;
+use super::editor::{self, Editor, HEADER_LINES, FOOTER_LINES};
+
+const BARS : [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// lines of the screen other than the two graphs: titles, axes, the gap
+// between the graphs and the line describing the selected block
+const TEXT_LINES : i32 = 6;
+
+// entropy (bits per byte) above which a block looks compressed or
+// encrypted, and below which it looks like padding
+const HIGH_ENTROPY : f64 = 7.2;
+const LOW_ENTROPY : f64 = 1.0;
+
+pub fn histogram(data : &[u8]) -> [usize; 256] {
+    let mut counts = [0; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    counts
+}
+
+// Shannon entropy in bits per byte, from 0 (one value) to 8 (uniform)
+pub fn entropy(counts : &[usize; 256], len : usize) -> f64 {
+    if len == 0 {
+        return 0.0;
+    }
+    counts.iter().filter(|&&n| n > 0).map(|&n| {
+        let p = n as f64 / len as f64;
+        p * (1.0 / p).log2()
+    }).sum()
+}
+
+// colour for a block of entropy `e`, telling apart compressed or encrypted
+// data and padding
+pub fn entropy_color(theme : &Theme, e : f64) -> ColorPair {
+    if e >= HIGH_ENTROPY {
+        theme.byte_high
+    } else if e < LOW_ENTROPY {
+        theme.byte_null
+    } else {
+        theme.byte_printable
+    }
+}
+
+// bar character for row `row` (0 at the bottom) of a bar of `height` rows
+// filled to `frac` (0 to 1)
+fn bar_char(frac : f64, height : i32, row : i32) -> char {
+    let eighths = (frac * height as f64 * 8.0).round() as i32;
+    BARS[(eighths - row * 8).clamp(0, 8) as usize]
+}
+
+// screen with a histogram of the byte values of the selection (or the
+// whole file) and a graph of the entropy of the file split in blocks;
+// choosing a block goes to it
+pub struct Analysis<'a, 'b : 'a> {
+    editor : &'a mut Editor<'b>,
+    quit : bool,
+    range : (usize, usize),
+    counts : [usize; 256],
+    block_size : usize,
+    entropies : Vec<f64>,
+    sel_index : usize,
+}
+
+impl<'a, 'b> Analysis<'a, 'b> {
+
+    // `range` is the part of the file for the histogram, end excluded
+    pub fn new(editor : &'a mut Editor<'b>, range : (usize, usize)) -> Analysis<'a, 'b> {
+        let counts = editor.cur_file().map_or([0; 256], |f| histogram(&f.data[range.0..range.1]));
+        Analysis {
+            editor,
+            quit : false,
+            range,
+            counts,
+            block_size : 1,
+            entropies : vec![],
+            sel_index : 0,
+        }
+    }
+
+    // one block per column of the graph
+    fn update_blocks(&mut self) {
+        let columns = self.graph_width().max(1) as usize;
+        let data = match self.editor.cur_file() {
+            Some(file) => &file.data,
+            None => return,
+        };
+        let block_size = data.len().div_ceil(columns).max(1);
+        if block_size == self.block_size && ! self.entropies.is_empty() {
+            return;
+        }
+        self.entropies = data.chunks(block_size).map(|c| entropy(&histogram(c), c.len())).collect();
+        self.block_size = block_size;
+        let pos = self.editor.cursor_pos();
+        self.sel_index = (pos / block_size).min(self.entropies.len().saturating_sub(1));
+    }
+
+    fn graph_width(&self) -> i32 {
+        self.editor.screen.w - 2
+    }
+
+    // rows of the histogram and of the entropy graph
+    fn graph_heights(&self) -> (i32, i32) {
+        let rows = (self.editor.screen.h - HEADER_LINES - FOOTER_LINES - TEXT_LINES).max(2);
+        (rows / 2, rows - rows / 2)
+    }
+
+    // first line of the entropy graph
+    fn entropy_top(&self) -> i32 {
+        HEADER_LINES + 1 + self.graph_heights().0 + 4
+    }
+
+    fn draw_header(&mut self) {
+        let name = self.editor.cur_file().map_or(String::new(), |f| f.short_name());
+        self.editor.screen.move_cursor(1, 1);
+        set_color_pair(self.editor.screen.theme.header);
+        print!(" Analysis: {}", name);
+        clear_eol();
+        self.editor.screen.move_cursor(self.editor.screen.w - 11, 1);
+        print!(" hedx v0.1");
+        clear_eol();
+
+        self.editor.screen.move_cursor(1, HEADER_LINES);
+        reset_color();
+        clear_eol();
+    }
+
+    #[allow(clippy::identity_op, clippy::erasing_op)]
+    fn draw_footer(&mut self) {
+        reset_color();
+        let w = editor::SHORTCUT_SPACING;
+        let h = self.editor.screen.h;
+
+        self.editor.screen.move_cursor(1, h - FOOTER_LINES + 1);
+        clear_eol();
+        self.editor.draw_key_help(1 + 0*w, h-1, "Ent", "Go To Block");
+        self.editor.draw_key_help(1 + 0*w, h-0, "^C", "Close");
+        self.editor.draw_key_help(1 + 1*w, h-1, "<-", "Prev Block");
+        self.editor.draw_key_help(1 + 1*w, h-0, "->", "Next Block");
+        self.editor.void_key_help(1 + 2*w, h-1);
+        self.editor.void_key_help(1 + 2*w, h-0);
+    }
+
+    fn draw_title(&self, y : i32, text : &str) {
+        self.editor.screen.move_cursor(1, y);
+        set_color_pair(self.editor.screen.theme.message);
+        let width = (self.editor.screen.w - 1).max(0) as usize;
+        print!(" {}", text.chars().take(width).collect::<String>());
+        clear_eol();
+        reset_color();
+    }
+
+    // axis labels at the left and right ends of a graph of `width` columns
+    fn draw_axis(&self, y : i32, width : i32, left : &str, right : &str) {
+        self.editor.screen.move_cursor(1, y);
+        let gap = (width as usize).saturating_sub(left.len() + right.len());
+        print!(" {}{:2$}{3}", left, "", gap, right);
+        clear_eol();
+    }
+
+    fn draw_histogram(&mut self) {
+        let width = self.graph_width().clamp(1, 256);
+        let (height, _) = self.graph_heights();
+        let per_column = 256usize.div_ceil(width as usize);
+        let buckets : Vec<usize> = self.counts.chunks(per_column).map(|c| c.iter().sum()).collect();
+        let max = buckets.iter().cloned().max().unwrap_or(0).max(1);
+        let len = self.range.1 - self.range.0;
+
+        let distinct = self.counts.iter().filter(|&&n| n > 0).count();
+        let common = (0..256).max_by_key(|&b| self.counts[b]).unwrap_or(0);
+        let title = format!("Bytes 0x{:x}-0x{:x}: {} values, most common 0x{:02x} ({} of {}), entropy {:.2}",
+                            self.range.0, self.range.1, distinct, common, self.counts[common], len,
+                            entropy(&self.counts, len));
+        let y = HEADER_LINES + 1;
+        self.draw_title(y, &title);
+        set_color_pair(self.editor.screen.theme.field);
+        for row in 0..height {
+            self.editor.screen.move_cursor(2, y + height - row);
+            // small counts still show as a sliver
+            let line : String = buckets.iter().map(|&n| {
+                let frac = if n > 0 { (n as f64 / max as f64).max(1.0 / (8 * height) as f64) } else { 0.0 };
+                bar_char(frac, height, row)
+            }).collect();
+            print!("{}", line);
+        }
+        reset_color();
+        let last = format!("{:02x}", buckets.len() * per_column - 1);
+        self.draw_axis(y + height + 1, buckets.len() as i32, "00", &last);
+    }
+
+    fn block_color(&self, index : usize) -> ColorPair {
+        let theme = &self.editor.screen.theme;
+        if index == self.sel_index {
+            theme.cursor
+        } else {
+            entropy_color(theme, self.entropies[index])
+        }
+    }
+
+    fn draw_entropy(&mut self) {
+        let (_, height) = self.graph_heights();
+        let y = self.entropy_top();
+        let len = self.editor.cur_file().map_or(0, |f| f.data.len());
+        let title = format!("Entropy of {} byte blocks (bits per byte, 0 to 8)", self.block_size);
+        self.draw_title(y - 1, &title);
+        for row in 0..height {
+            self.editor.screen.move_cursor(2, y + height - 1 - row);
+            for index in 0..self.entropies.len() {
+                set_color_pair(self.block_color(index));
+                print!("{}", bar_char(self.entropies[index] / 8.0, height, row));
+            }
+            reset_color();
+        }
+        self.draw_axis(y + height, self.entropies.len() as i32, "0", &format!("0x{:x}", len));
+        // the selected block may have no bar to highlight
+        self.editor.screen.move_cursor(2 + self.sel_index as i32, y + height);
+        set_color_pair(self.editor.screen.theme.cursor);
+        print!("^");
+        reset_color();
+
+        let start = self.sel_index * self.block_size;
+        let end = (start + self.block_size).min(len);
+        let e = self.entropies.get(self.sel_index).cloned().unwrap_or(0.0);
+        let kind = if e >= HIGH_ENTROPY { ", compressed or encrypted?" } else if e < LOW_ENTROPY { ", padding?" } else { "" };
+        let info = format!("Block {}: 0x{:x}-0x{:x}, entropy {:.2}{}", self.sel_index, start, end, e, kind);
+        self.draw_title(y + height + 1, &info);
+    }
+
+    fn draw(&mut self) {
+        self.update_blocks();
+        if self.editor.screen.redraw_needed {
+            clear_screen();
+            self.editor.screen.redraw_needed = false;
+        }
+        self.draw_header();
+        self.draw_histogram();
+        self.draw_entropy();
+        self.draw_footer();
+        flush_screen();
+    }
+
+    fn go_to_block(&mut self) {
+        self.editor.set_cursor_pos(self.sel_index * self.block_size);
+        self.quit = true;
+    }
+
+    fn process_mouse(&mut self, ev : MouseEvent) {
+        let (_, height) = self.graph_heights();
+        let top = self.entropy_top();
+        let index = (ev.x - 2) as usize;
+        if ev.x < 2 || index >= self.entropies.len() || ev.y < top || ev.y > top + height {
+            return;
+        }
+        match ev.action {
+            MouseAction::Press(MouseButton::Left) | MouseAction::Drag(MouseButton::Left) => self.sel_index = index,
+            MouseAction::DoubleClick(MouseButton::Left) => {
+                self.sel_index = index;
+                self.go_to_block();
+            }
+            _ => (),
+        }
+    }
+
+    fn process_input(&mut self) {
+        let last = self.entropies.len().saturating_sub(1);
+        match self.editor.read_key() {
+            Key::Ctrl('c') | Key::Esc => self.quit = true,
+            Key::Enter => self.go_to_block(),
+            Key::Special(Special::Left, MOD_NONE) if self.sel_index > 0 => self.sel_index -= 1,
+            Key::Special(Special::Right, MOD_NONE) if self.sel_index < last => self.sel_index += 1,
+            Key::Special(Special::Home, MOD_NONE) => self.sel_index = 0,
+            Key::Special(Special::End, MOD_NONE) => self.sel_index = last,
+            Key::Mouse(ev) => self.process_mouse(ev),
+            _ => (),
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.editor.screen.redraw_needed = true;
+        while ! self.quit && ! self.editor.quit {
+            self.draw();
+            self.process_input();
+        }
+        clear_screen();
+        self.editor.screen.redraw_needed = true;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entropy_of(data : &[u8]) -> f64 {
+        entropy(&histogram(data), data.len())
+    }
+
+    #[test]
+    fn entropies() {
+        assert_eq!(entropy_of(b""), 0.0);
+        assert_eq!(entropy_of(&[7; 100]), 0.0);
+        assert_eq!(entropy_of(b"abab"), 1.0);
+        let all : Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy_of(&all), 8.0);
+    }
+
+    #[test]
+    fn bars() {
+        assert_eq!(bar_char(0.0, 2, 0), ' ');
+        assert_eq!(bar_char(1.0, 2, 1), '█');
+        // half full: the bottom row is full and the top one empty
+        assert_eq!(bar_char(0.5, 2, 0), '█');
+        assert_eq!(bar_char(0.5, 2, 1), ' ');
+        assert_eq!(bar_char(0.75, 2, 1), '▄');
+    }
+}
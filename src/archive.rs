@@ -0,0 +1,631 @@
+
+use super::overlay::{Field, Overlay};
+use super::formats::{Fields, group, c_string, enum_name, check_crc};
+use super::inflate::{inflate, crc32};
+
+// entries decoded before giving up on an archive
+const MAX_ENTRIES : usize = 100_000;
+
+const ZIP_LOCAL : &[u8] = b"PK\x03\x04";
+const ZIP_CENTRAL : &[u8] = b"PK\x01\x02";
+const ZIP_END : &[u8] = b"PK\x05\x06";
+const ZIP_END_SIZE : usize = 22;
+const ZIP_MAX_COMMENT : usize = 0xffff;
+const ZIP_DATA_DESCRIPTOR : u64 = 0x8;
+const ZIP_STORED : u64 = 0;
+const ZIP_DEFLATED : u64 = 8;
+
+const GZIP_MAGIC : &[u8] = b"\x1f\x8b";
+const GZIP_FHCRC : u64 = 0x2;
+const GZIP_FEXTRA : u64 = 0x4;
+const GZIP_FNAME : u64 = 0x8;
+const GZIP_FCOMMENT : u64 = 0x10;
+
+const TAR_BLOCK : usize = 512;
+
+const ZIP_METHOD_NAMES : &[(u64, &str)] = &[
+    (0, "stored"), (8, "deflated"), (9, "deflate64"), (12, "bzip2"), (14, "LZMA"),
+    (93, "zstd"), (95, "xz"), (99, "AES encrypted"),
+];
+
+const ZIP_FLAG_NAMES : &[(u64, &str)] = &[
+    (0x1, "ENCRYPTED"), (0x8, "DATA_DESCRIPTOR"), (0x800, "UTF8"),
+];
+
+const GZIP_FLAG_NAMES : &[(u64, &str)] = &[
+    (0x1, "TEXT"), (0x2, "HCRC"), (0x4, "EXTRA"), (0x8, "NAME"), (0x10, "COMMENT"),
+];
+
+const GZIP_OS_NAMES : &[(u64, &str)] = &[
+    (0, "FAT"), (3, "Unix"), (7, "Macintosh"), (10, "NTFS"), (11, "NTFS"), (255, "unknown"),
+];
+
+const TAR_TYPE_NAMES : &[(u64, &str)] = &[
+    (b'0' as u64, "file"), (0, "file"), (b'1' as u64, "hard link"), (b'2' as u64, "symlink"),
+    (b'3' as u64, "char device"), (b'4' as u64, "block device"), (b'5' as u64, "directory"),
+    (b'6' as u64, "FIFO"), (b'x' as u64, "pax header"), (b'g' as u64, "pax global header"),
+    (b'L' as u64, "GNU long name"), (b'K' as u64, "GNU long link"),
+];
+
+pub fn detect_zip(data : &[u8]) -> Option<String> {
+    if data.starts_with(ZIP_LOCAL) || (data.starts_with(ZIP_END) && data.len() == ZIP_END_SIZE) {
+        Some("ZIP archive".to_string())
+    } else {
+        None
+    }
+}
+
+// offset of the end of central directory record, which is followed only
+// by the archive comment
+fn zip_end(data : &[u8]) -> Option<usize> {
+    let last = data.len().checked_sub(ZIP_END_SIZE)?;
+    let first = last.saturating_sub(ZIP_MAX_COMMENT);
+    (first..last+1).rev().find(|&pos| &data[pos..pos+4] == ZIP_END)
+}
+
+struct ZipEntry {
+    name : String,
+    local_offset : usize,
+    flags : u64,
+    method : u64,
+    crc : u64,
+    compressed_size : u64,
+}
+
+fn zip_central_entry(data : &[u8], pos : usize) -> Option<(Field, ZipEntry)> {
+    let mut f = Fields::new(data, pos, false);
+    f.text("signature", 4)?;
+    f.uint("version_made_by", 2)?;
+    f.uint("version_needed", 2)?;
+    let flags = f.flags("flags", 2, ZIP_FLAG_NAMES)?;
+    let method = f.named("method", 2, ZIP_METHOD_NAMES)?;
+    f.uint_with("time", 2, |v| format!("{:02}:{:02}:{:02}", v >> 11, (v >> 5) & 0x3f, (v & 0x1f) * 2))?;
+    f.uint_with("date", 2, |v| format!("{}-{:02}-{:02}", 1980 + (v >> 9), (v >> 5) & 0xf, v & 0x1f))?;
+    let crc = f.uint_with("crc32", 4, |v| format!("0x{:08x}", v))?;
+    let compressed_size = f.uint("compressed_size", 4)?;
+    f.uint("uncompressed_size", 4)?;
+    let name_len = f.uint("name_length", 2)? as usize;
+    let extra_len = f.uint("extra_length", 2)? as usize;
+    let comment_len = f.uint("comment_length", 2)? as usize;
+    f.uint("disk_number", 2)?;
+    f.uint_with("internal_attributes", 2, |v| format!("0x{:x}", v))?;
+    f.uint_with("external_attributes", 4, |v| format!("0x{:x}", v))?;
+    let local_offset = f.uint_with("local_header_offset", 4, |v| format!("0x{:x}", v))? as usize;
+    let name = String::from_utf8_lossy(f.text("name", name_len)?).into_owned();
+    if extra_len > 0 {
+        f.bytes("extra", extra_len)?;
+    }
+    if comment_len > 0 {
+        f.text("comment", comment_len)?;
+    }
+    let field = f.into_field(&name, format!("{} bytes {}", compressed_size, enum_name(method, ZIP_METHOD_NAMES)));
+    Some((field, ZipEntry { name, local_offset, flags, method, crc, compressed_size }))
+}
+
+// local header and data of an entry; sizes come from the central
+// directory when the entry has a data descriptor
+fn zip_local_entry(data : &[u8], pos : usize, central : Option<&ZipEntry>, warnings : &mut Vec<String>) -> Option<(Field, usize)> {
+    let mut f = Fields::new(data, pos, false);
+    f.text("signature", 4)?;
+    f.uint("version_needed", 2)?;
+    let mut flags = f.flags("flags", 2, ZIP_FLAG_NAMES)?;
+    let mut method = f.named("method", 2, ZIP_METHOD_NAMES)?;
+    f.uint_with("time", 2, |v| format!("{:02}:{:02}:{:02}", v >> 11, (v >> 5) & 0x3f, (v & 0x1f) * 2))?;
+    f.uint_with("date", 2, |v| format!("{}-{:02}-{:02}", 1980 + (v >> 9), (v >> 5) & 0xf, v & 0x1f))?;
+    let crc_index = f.fields.len();
+    let mut crc = f.uint_with("crc32", 4, |v| format!("0x{:08x}", v))?;
+    let mut size = f.uint("compressed_size", 4)?;
+    f.uint("uncompressed_size", 4)?;
+    let name_len = f.uint("name_length", 2)? as usize;
+    let extra_len = f.uint("extra_length", 2)? as usize;
+    let name = String::from_utf8_lossy(f.text("name", name_len)?).into_owned();
+    if extra_len > 0 {
+        f.bytes("extra", extra_len)?;
+    }
+    if let Some(entry) = central {
+        flags = entry.flags;
+        method = entry.method;
+        crc = entry.crc;
+        size = entry.compressed_size;
+    }
+    let data_start = f.pos;
+    if flags & ZIP_DATA_DESCRIPTOR != 0 && central.is_none() {
+        // the size is unknown without the central directory, unless
+        // the data can be decompressed to find its end
+        size = match method {
+            ZIP_DEFLATED => inflate(&data[data_start..]).map(|r| r.len as u64).unwrap_or(0),
+            _ => 0,
+        };
+    }
+    let size = (size as usize).min(data.len() - data_start);
+    if size > 0 {
+        f.bytes("data", size)?;
+    }
+
+    // check the data against the CRC when it can be decompressed
+    let actual = match method {
+        ZIP_STORED => Some(crc32(&data[data_start..data_start+size])),
+        ZIP_DEFLATED => inflate(&data[data_start..data_start+size]).ok().map(|r| r.crc),
+        _ => None,
+    };
+    let mut status = String::new();
+    if let Some(actual) = actual {
+        if flags & 1 == 0 {
+            status = check_crc(crc, actual, &name, warnings);
+            let value = format!("0x{:08x}{}", crc, status);
+            if let Some(field) = f.fields.get_mut(crc_index) {
+                field.value = value;
+            }
+        }
+    }
+
+    if flags & ZIP_DATA_DESCRIPTOR != 0 {
+        // optional signature, CRC and the two sizes
+        let mut desc = Fields::new(data, f.pos, false);
+        if desc.peek(desc.pos, 4) == Some(0x08074b50) {
+            desc.uint_with("signature", 4, |v| format!("0x{:08x}", v));
+        }
+        if desc.uint_with("crc32", 4, |v| format!("0x{:08x}", v)).is_some()
+            && desc.uint("compressed_size", 4).is_some()
+            && desc.uint("uncompressed_size", 4).is_some() {
+            f.pos = desc.pos;
+            f.push(desc.into_field("data_descriptor", String::new()));
+        }
+    }
+    let end = f.pos;
+    let value = format!("{} bytes {}{}", size, enum_name(method, ZIP_METHOD_NAMES), status);
+    Some((f.into_field(&name, value), end))
+}
+
+// local headers with the entries' data, followed by the central directory
+// and its end record
+pub fn decode_zip(data : &[u8]) -> Option<Overlay> {
+    detect_zip(data)?;
+    let mut fields = vec![];
+    let mut locations = vec![];
+    let mut warnings = vec![];
+
+    // the central directory lists the entries with their sizes
+    let mut central = vec![];
+    let mut entries = vec![];
+    if let Some(end_pos) = zip_end(data) {
+        let mut end = Fields::new(data, end_pos, false);
+        end.text("signature", 4)?;
+        end.uint("disk_number", 2)?;
+        end.uint("central_directory_disk", 2)?;
+        end.uint("disk_entries", 2)?;
+        let n_entries = end.uint("total_entries", 2)? as usize;
+        let cd_size = end.uint("central_directory_size", 4)? as usize;
+        let cd_offset = end.uint_with("central_directory_offset", 4, |v| format!("0x{:x}", v))? as usize;
+        let comment_len = end.uint("comment_length", 2)? as usize;
+        if comment_len > 0 {
+            end.text("comment", comment_len.min(data.len() - end.pos))?;
+        }
+        let mut pos = cd_offset;
+        while entries.len() < n_entries.min(MAX_ENTRIES) && data.get(pos..pos+4) == Some(ZIP_CENTRAL) {
+            match zip_central_entry(data, pos) {
+                Some((field, entry)) => {
+                    pos = field.start + field.len;
+                    central.push(field);
+                    entries.push(entry);
+                }
+                None => break,
+            }
+        }
+        if entries.len() < n_entries {
+            warnings.push(format!("central directory has {} of {} entries", entries.len(), n_entries));
+        }
+        let n = entries.len();
+        let mut records = vec![];
+        if cd_offset <= data.len() {
+            let mut dir = group("central_directory", cd_offset, cd_size.min(data.len() - cd_offset), central);
+            dir.value = format!("[{}]", n);
+            locations.push(("central directory".to_string(), cd_offset));
+            records.push(dir);
+        } else {
+            warnings.push(format!("central directory at 0x{:x} is past the end of the file", cd_offset));
+        }
+        locations.push(("end of central directory".to_string(), end_pos));
+        records.push(end.into_field("end_of_central_directory", String::new()));
+        central = records;
+    }
+
+    // local entries, from the central directory or one after the other
+    if ! entries.is_empty() {
+        for entry in &entries {
+            if data.get(entry.local_offset..entry.local_offset+4) != Some(ZIP_LOCAL) {
+                warnings.push(format!("no local header for {}", entry.name));
+                continue;
+            }
+            if let Some((field, _)) = zip_local_entry(data, entry.local_offset, Some(entry), &mut warnings) {
+                locations.push((format!("file {}{}", entry.name, status_suffix(&field)), field.start));
+                fields.push(field);
+            }
+        }
+    } else {
+        let mut pos = 0;
+        while fields.len() < MAX_ENTRIES && data.get(pos..pos+4) == Some(ZIP_LOCAL) {
+            match zip_local_entry(data, pos, None, &mut warnings) {
+                Some((field, end)) => {
+                    locations.push((format!("file {}{}", field.name, status_suffix(&field)), field.start));
+                    fields.push(field);
+                    pos = end;
+                }
+                None => break,
+            }
+        }
+    }
+    fields.sort_by_key(|f| f.start);
+    for (i, field) in fields.iter_mut().enumerate() {
+        field.name = format!("[{}] {}", i, field.name);
+    }
+    fields.append(&mut central);
+    fields.sort_by_key(|f| f.start);
+    locations.sort_by_key(|l| l.1);
+    let mut overlay = Overlay::new("ZIP", fields);
+    overlay.locations = locations;
+    overlay.warnings = warnings;
+    Some(overlay)
+}
+
+// " BAD CRC" for the locations of entries whose CRC didn't match
+fn status_suffix(field : &Field) -> &'static str {
+    if field.value.contains("BAD CRC") { " BAD CRC" } else { "" }
+}
+
+pub fn detect_gzip(data : &[u8]) -> Option<String> {
+    if data.starts_with(GZIP_MAGIC) && data.get(2) == Some(&8) {
+        Some("gzip compressed data".to_string())
+    } else {
+        None
+    }
+}
+
+// length of the zero terminated ISO-8859-1 string at `pos`, counting the
+// terminator
+fn latin1_len(data : &[u8], pos : usize) -> Option<usize> {
+    data.get(pos..)?.iter().position(|&b| b == 0).map(|n| n + 1)
+}
+
+// one gzip member: header, deflate data, CRC and size of the
+// uncompressed data; returns the field and the end of the member
+fn gzip_member(data : &[u8], pos : usize, index : usize, warnings : &mut Vec<String>) -> Option<(Field, usize)> {
+    let mut f = Fields::new(data, pos, false);
+    f.bytes("magic", 2)?;
+    f.named("method", 1, &[(8, "deflate")])?;
+    let flags = f.flags("flags", 1, GZIP_FLAG_NAMES)?;
+    f.uint("mtime", 4)?;
+    f.uint_with("extra_flags", 1, |v| format!("0x{:x}", v))?;
+    f.named("os", 1, GZIP_OS_NAMES)?;
+    if flags & GZIP_FEXTRA != 0 {
+        let len = f.uint("extra_length", 2)? as usize;
+        f.bytes("extra", len)?;
+    }
+    let mut name = String::new();
+    if flags & GZIP_FNAME != 0 {
+        let bytes = f.text("name", latin1_len(data, f.pos)?)?;
+        name = bytes[..bytes.len()-1].iter().map(|&b| b as char).collect();
+    }
+    if flags & GZIP_FCOMMENT != 0 {
+        f.text("comment", latin1_len(data, f.pos)?)?;
+    }
+    if flags & GZIP_FHCRC != 0 {
+        let actual = crc32(&data[pos..f.pos]) & 0xffff;
+        let stored = f.peek(f.pos, 2)?;
+        let status = check_crc(stored, actual, &format!("gzip member {} header", index), warnings);
+        f.uint_with("header_crc16", 2, |v| format!("0x{:04x}{}", v, status))?;
+    }
+
+    // the end of the compressed data is only known after decompressing it
+    let data_start = f.pos;
+    let out = match inflate(&data[data_start..]) {
+        Ok(r) => r,
+        Err(e) => {
+            warnings.push(format!("gzip member {}: {}", index, e));
+            let len = data.len() - data_start;
+            f.bytes("compressed_data", len)?;
+            let end = f.pos;
+            return Some((f.into_field(&format!("[{}] {}", index, name), "invalid".to_string()), end));
+        }
+    };
+    let len = out.len;
+    f.bytes("compressed_data", len)?;
+    f.fields.last_mut()?.value = format!("{} bytes", len);
+    let stored = f.peek(f.pos, 4);
+    let mut status = String::new();
+    if let Some(stored) = stored {
+        status = check_crc(stored, out.crc, &format!("gzip member {}", index), warnings);
+        f.uint_with("crc32", 4, |v| format!("0x{:08x}{}", v, status))?;
+    }
+    let size = f.uint("uncompressed_size", 4);
+    if size.is_some_and(|size| size != out.size as u64 & 0xffff_ffff) {
+        warnings.push(format!("gzip member {} has the wrong size", index));
+    }
+    let end = f.pos;
+    let value = format!("{} -> {} bytes{}", len, out.size, if status == " ok" { "" } else { &status });
+    Some((f.into_field(&format!("[{}] {}", index, name), value), end))
+}
+
+// members one after the other, each decompressed to find where it ends
+pub fn decode_gzip(data : &[u8]) -> Option<Overlay> {
+    detect_gzip(data)?;
+    let mut fields = vec![];
+    let mut locations = vec![];
+    let mut warnings = vec![];
+    let mut pos = 0;
+    while fields.len() < MAX_ENTRIES && data.get(pos..pos+2) == Some(GZIP_MAGIC) {
+        let (field, end) = match gzip_member(data, pos, fields.len(), &mut warnings) {
+            Some(member) => member,
+            None => break,
+        };
+        let bad = if field.value.contains("BAD CRC") { " BAD CRC" } else { "" };
+        locations.push((format!("member {}{}", field.name, bad), pos));
+        fields.push(field);
+        pos = end;
+    }
+    if pos < data.len() {
+        locations.push(("trailing data".to_string(), pos));
+        fields.push(Field::new("trailing data", format!("{} bytes", data.len() - pos), pos, data.len() - pos));
+    }
+    let mut overlay = Overlay::new("gzip", fields);
+    overlay.locations = locations;
+    overlay.warnings = warnings;
+    Some(overlay)
+}
+
+pub fn detect_tar(data : &[u8]) -> Option<String> {
+    if data.len() >= TAR_BLOCK && &data[257..262] == b"ustar" {
+        Some("tar archive".to_string())
+    } else {
+        None
+    }
+}
+
+// value of an octal number field, which may be padded with spaces and NULs
+fn octal(bytes : &[u8]) -> Option<u64> {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_matches([' ', '\0']);
+    if text.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(text, 8).ok()
+}
+
+// the header field, the file's name and size and whether the header
+// checksum is right
+fn tar_header(data : &[u8], pos : usize, warnings : &mut Vec<String>) -> Option<(Field, String, u64, bool)> {
+    let mut f = Fields::new(data, pos, false);
+    let name = c_string(&data[pos..pos+100], 0);
+    f.text("name", 100)?;
+    for &(field, len) in &[("mode", 8), ("uid", 8), ("gid", 8)] {
+        f.text(field, len)?;
+    }
+    let size = octal(f.text("size", 12)?).unwrap_or(0);
+    f.fields.last_mut()?.value = format!("{}", size);
+    f.text("mtime", 12)?;
+    let stored = octal(f.text("checksum", 8)?);
+    let ty = f.named("type", 1, TAR_TYPE_NAMES)?;
+    f.text("link_name", 100)?;
+    f.text("magic", 6)?;
+    f.text("version", 2)?;
+    f.text("user_name", 32)?;
+    f.text("group_name", 32)?;
+    f.text("dev_major", 8)?;
+    f.text("dev_minor", 8)?;
+    let prefix = c_string(&data[pos+345..pos+500], 0);
+    f.text("prefix", 155)?;
+    f.bytes("padding", 12)?;
+
+    // sum of the header bytes with the checksum field taken as spaces
+    let actual = data[pos..pos+TAR_BLOCK].iter().enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u32 } else { b as u32 })
+        .sum::<u32>();
+    let name = if ! prefix.is_empty() { format!("{}/{}", prefix, name) } else { name };
+    let ok = stored == Some(actual as u64);
+    if let Some(field) = f.fields.get_mut(6) {
+        if ok {
+            field.value.push_str(" ok");
+        } else {
+            field.value.push_str(&format!(" BAD CHECKSUM, expected {:o}", actual));
+            warnings.push(format!("bad header checksum for {}", name));
+        }
+    }
+    let header = f.into_field("header", enum_name(ty, TAR_TYPE_NAMES));
+    Some((header, name, size, ok))
+}
+
+// 512 byte headers, each followed by the file's data padded to whole
+// blocks; two zero blocks end the archive
+pub fn decode_tar(data : &[u8]) -> Option<Overlay> {
+    detect_tar(data)?;
+    let mut fields = vec![];
+    let mut locations = vec![];
+    let mut warnings = vec![];
+    let mut pos = 0;
+    while fields.len() < MAX_ENTRIES && data.len() - pos >= TAR_BLOCK {
+        if data[pos..pos+TAR_BLOCK].iter().all(|&b| b == 0) {
+            let end = (pos + 2 * TAR_BLOCK).min(data.len());
+            fields.push(Field::new("end of archive", String::new(), pos, end - pos));
+            pos = end;
+            break;
+        }
+        let (header, name, size, ok) = match tar_header(data, pos, &mut warnings) {
+            Some(header) => header,
+            None => break,
+        };
+        let data_len = (size as usize).min(data.len() - pos - TAR_BLOCK);
+        let blocks = data_len.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+        let len = (TAR_BLOCK + blocks).min(data.len() - pos);
+        let bad = if ok { "" } else { " BAD CHECKSUM" };
+        locations.push((format!("file {}{}", name, bad), pos));
+        let value = format!("{} {} bytes{}", header.value, size, bad);
+        let mut children = vec![header];
+        if data_len > 0 {
+            children.push(Field::new("data", format!("{} bytes", data_len), pos + TAR_BLOCK, data_len));
+        }
+        let mut entry = Field::new(&format!("[{}] {}", fields.len(), name), value, pos, len);
+        entry.children = children;
+        fields.push(entry);
+        pos += len;
+    }
+    if pos < data.len() {
+        fields.push(Field::new("trailing data", format!("{} bytes", data.len() - pos), pos, data.len() - pos));
+    }
+    let mut overlay = Overlay::new("tar", fields);
+    overlay.locations = locations;
+    overlay.warnings = warnings;
+    Some(overlay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // deflate stream with a single stored block
+    fn stored(data : &[u8]) -> Vec<u8> {
+        let len = data.len() as u16;
+        let mut out = vec![1];
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(! len).to_le_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn gzip(name : &[u8], contents : &[u8]) -> Vec<u8> {
+        let mut out = vec![0x1f, 0x8b, 8, GZIP_FNAME as u8, 0, 0, 0, 0, 0, 3];
+        out.extend_from_slice(name);
+        out.push(0);
+        out.extend_from_slice(&stored(contents));
+        out.extend_from_slice(&crc32(contents).to_le_bytes());
+        out.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn gzip_latin1_name() {
+        let overlay = decode_gzip(&gzip(b"caf\xe9", b"hello")).unwrap();
+        assert_eq!(overlay.warnings.len(), 0, "{:?}", overlay.warnings);
+        assert_eq!(overlay.fields[0].name, "[0] caf\u{e9}");
+        assert_eq!(overlay.fields[0].value, "10 -> 5 bytes");
+    }
+
+    #[test]
+    fn gzip_long_name() {
+        let name = vec![b'x'; 300];
+        let overlay = decode_gzip(&gzip(&name, b"hello")).unwrap();
+        assert_eq!(overlay.warnings.len(), 0, "{:?}", overlay.warnings);
+        assert_eq!(overlay.fields.len(), 1);
+    }
+
+    #[test]
+    fn gzip_bad_crc() {
+        let mut data = gzip(b"a", b"hello");
+        let n = data.len();
+        data[n - 8] ^= 1;
+        let overlay = decode_gzip(&data).unwrap();
+        assert!(overlay.warnings[0].contains("CRC"), "{:?}", overlay.warnings);
+    }
+
+    // one stored entry with its central directory
+    fn zip(name : &[u8], contents : &[u8]) -> Vec<u8> {
+        let crc = crc32(contents).to_le_bytes();
+        let size = (contents.len() as u32).to_le_bytes();
+        let name_len = (name.len() as u16).to_le_bytes();
+        let mut out = ZIP_LOCAL.to_vec();
+        out.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        out.extend_from_slice(&crc);
+        out.extend_from_slice(&size);
+        out.extend_from_slice(&size);
+        out.extend_from_slice(&name_len);
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(name);
+        out.extend_from_slice(contents);
+        let cd_offset = (out.len() as u32).to_le_bytes();
+        out.extend_from_slice(ZIP_CENTRAL);
+        out.extend_from_slice(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        out.extend_from_slice(&crc);
+        out.extend_from_slice(&size);
+        out.extend_from_slice(&size);
+        out.extend_from_slice(&name_len);
+        out.extend_from_slice(&[0; 12]);
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(name);
+        let cd_size = (out.len() as u32 - u32::from_le_bytes(cd_offset)).to_le_bytes();
+        out.extend_from_slice(ZIP_END);
+        out.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+        out.extend_from_slice(&cd_size);
+        out.extend_from_slice(&cd_offset);
+        out.extend_from_slice(&[0, 0]);
+        out
+    }
+
+    fn tar(name : &[u8], contents : &[u8]) -> Vec<u8> {
+        let mut header = vec![0; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name);
+        header[124..135].copy_from_slice(format!("{:011o}", contents.len()).as_bytes());
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[148..156].copy_from_slice(b"        ");
+        let sum = header.iter().map(|&b| b as u32).sum::<u32>();
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        let mut out = header;
+        out.extend_from_slice(contents);
+        out.resize(out.len().div_ceil(TAR_BLOCK) * TAR_BLOCK + 2 * TAR_BLOCK, 0);
+        out
+    }
+
+    #[test]
+    fn zip_entry() {
+        let overlay = decode_zip(&zip(b"a.txt", b"hello")).unwrap();
+        assert_eq!(overlay.warnings.len(), 0, "{:?}", overlay.warnings);
+        assert_eq!(overlay.fields[0].name, "[0] a.txt");
+        assert_eq!(overlay.fields[0].value, "5 bytes stored (0) ok");
+    }
+
+    #[test]
+    fn zip_bad_crc() {
+        let mut data = zip(b"a.txt", b"hello");
+        data[30 + 5] ^= 1;
+        let overlay = decode_zip(&data).unwrap();
+        assert!(overlay.warnings[0].contains("CRC"), "{:?}", overlay.warnings);
+    }
+
+    #[test]
+    fn zip_central_directory_past_end() {
+        let mut data = zip(b"a.txt", b"hello");
+        let pos = data.len() - 6;
+        data[pos..pos+4].copy_from_slice(&0x7fff_ffffu32.to_le_bytes());
+        let overlay = decode_zip(&data).unwrap();
+        assert!(overlay.warnings.iter().any(|w| w.contains("past the end")), "{:?}", overlay.warnings);
+        assert!(overlay.fields.iter().all(|f| f.start <= data.len() && f.name != "central_directory"));
+        assert!(overlay.locations.iter().all(|l| l.1 <= data.len()), "{:?}", overlay.locations);
+    }
+
+    #[test]
+    fn tar_entry() {
+        let overlay = decode_tar(&tar(b"a.txt", b"hello")).unwrap();
+        assert_eq!(overlay.warnings.len(), 0, "{:?}", overlay.warnings);
+        assert_eq!(overlay.fields[0].name, "[0] a.txt");
+        assert_eq!(overlay.fields[1].name, "end of archive");
+    }
+
+    #[test]
+    fn truncated_archives() {
+        let data = zip(b"a.txt", b"hello");
+        for len in 0..data.len() {
+            decode_zip(&data[..len]);
+        }
+        let data = tar(b"a.txt", b"hello");
+        for len in 0..data.len() {
+            decode_tar(&data[..len]);
+        }
+    }
+
+    #[test]
+    fn truncated_gzip() {
+        let data = gzip(b"name", b"hello hello");
+        for len in 0..data.len() {
+            decode_gzip(&data[..len]);
+        }
+    }
+}
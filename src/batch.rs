@@ -0,0 +1,265 @@
+
+use std::io::Write;
+
+use super::file::File;
+
+// byte range; `end` is exclusive and None means "to the end of the file"
+#[derive(Copy, Clone, Debug)]
+pub struct Range {
+    pub start : usize,
+    pub end : Option<usize>,
+}
+
+impl Range {
+
+    pub fn resolve(&self, file_len : usize) -> Result<(usize, usize), String> {
+        let end = self.end.unwrap_or(file_len);
+        if self.start > end {
+            return Err(format!("invalid range: 0x{:x}:0x{:x}", self.start, end));
+        }
+        Ok((self.start, end))
+    }
+
+}
+
+#[derive(Clone, Debug)]
+pub enum BatchCmd {
+    Set(usize, Vec<u8>),
+    Fill(Range, Vec<u8>),
+    Insert(usize, Vec<u8>),
+    Delete(Range),
+    Find(Vec<u8>),
+    Dump(Range),
+}
+
+pub const BATCH_OPTIONS : &[&str] = &[
+    "--set", "--fill", "--insert", "--delete", "--find", "--dump",
+];
+
+// decimal or 0x-prefixed hexadecimal
+pub fn parse_number(s : &str) -> Result<usize, String> {
+    let s = s.trim();
+    let r = if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse::<usize>()
+    };
+    r.map_err(|_| format!("invalid number: '{}'", s))
+}
+
+// "start:end", "start:" (to end of file) or "start+len"
+pub fn parse_range(s : &str) -> Result<Range, String> {
+    if let Some(i) = s.find(':') {
+        let start = parse_number(&s[..i])?;
+        let end = match s[i+1..].trim() {
+            "" => None,
+            end => Some(parse_number(end)?),
+        };
+        Ok(Range { start, end })
+    } else if let Some(i) = s.find('+') {
+        let start = parse_number(&s[..i])?;
+        let len = parse_number(&s[i+1..])?;
+        let end = start.checked_add(len).ok_or("range out of bounds")?;
+        Ok(Range { start, end : Some(end) })
+    } else {
+        let start = parse_number(s)?;
+        let end = start.checked_add(1).ok_or("range out of bounds")?;
+        Ok(Range { start, end : Some(end) })
+    }
+}
+
+// hex digits (spaces allowed) or a string in double quotes
+pub fn parse_bytes(s : &str) -> Result<Vec<u8>, String> {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        return Ok(s.as_bytes()[1..s.len()-1].to_vec());
+    }
+    let digits : Vec<u32> = s.chars()
+        .filter(|c| ! c.is_whitespace())
+        .map(|c| c.to_digit(16).ok_or_else(|| format!("invalid hex data: '{}'", s)))
+        .collect::<Result<_, _>>()?;
+    if digits.is_empty() || ! digits.len().is_multiple_of(2) {
+        return Err(format!("invalid hex data: '{}'", s));
+    }
+    Ok(digits.chunks(2).map(|d| (d[0] << 4 | d[1]) as u8).collect())
+}
+
+fn split_assignment(s : &str) -> Result<(&str, &str), String> {
+    match s.find('=') {
+        Some(i) => Ok((&s[..i], &s[i+1..])),
+        None => Err(format!("expected '<where>=<data>': '{}'", s)),
+    }
+}
+
+fn check_range(start : usize, end : usize, file_len : usize) -> Result<(), String> {
+    if start > file_len || end > file_len {
+        return Err(format!("range 0x{:x}:0x{:x} is past the end of the file (0x{:x} bytes)", start, end, file_len));
+    }
+    Ok(())
+}
+
+pub fn dump(out : &mut dyn Write, data : &[u8], start : usize, end : usize) -> ::std::io::Result<()> {
+    let mut off = start - start % 16;
+    while off < end {
+        write!(out, "{:08x} | ", off)?;
+        for i in 0..16 {
+            if i == 8 { write!(out, " ")?; }
+            if off+i >= start && off+i < end {
+                write!(out, "{:02x} ", data[off+i])?;
+            } else {
+                write!(out, "   ")?;
+            }
+        }
+        write!(out, "| ")?;
+        for i in 0..16 {
+            if off+i >= start && off+i < end {
+                let b = data[off+i];
+                write!(out, "{}", if (32..127).contains(&b) { b } else { b'.' } as char)?;
+            } else {
+                write!(out, " ")?;
+            }
+        }
+        writeln!(out)?;
+        off += 16;
+    }
+    Ok(())
+}
+
+impl BatchCmd {
+
+    pub fn parse(option : &str, arg : &str) -> Result<BatchCmd, String> {
+        match option {
+            "--set" => {
+                let (pos, data) = split_assignment(arg)?;
+                Ok(BatchCmd::Set(parse_number(pos)?, parse_bytes(data)?))
+            }
+            "--fill" => {
+                let (range, data) = split_assignment(arg)?;
+                Ok(BatchCmd::Fill(parse_range(range)?, parse_bytes(data)?))
+            }
+            "--insert" => {
+                let (pos, data) = split_assignment(arg)?;
+                Ok(BatchCmd::Insert(parse_number(pos)?, parse_bytes(data)?))
+            }
+            "--delete" => Ok(BatchCmd::Delete(parse_range(arg)?)),
+            "--find" => Ok(BatchCmd::Find(parse_bytes(arg)?)),
+            "--dump" => Ok(BatchCmd::Dump(parse_range(arg)?)),
+            _ => Err(format!("unknown batch command: '{}'", option)),
+        }
+    }
+
+    pub fn modifies_file(&self) -> bool {
+        ! matches!(*self, BatchCmd::Find(_) | BatchCmd::Dump(_))
+    }
+
+    pub fn run(&self, file : &mut File, out : &mut dyn Write) -> Result<(), String> {
+        let len = file.data.len();
+        match *self {
+            BatchCmd::Set(pos, ref data) => {
+                let end = pos.checked_add(data.len()).ok_or("range out of bounds")?;
+                check_range(pos, end, len)?;
+                file.set_bytes(pos, data);
+            }
+            BatchCmd::Fill(range, ref data) => {
+                let (start, end) = range.resolve(len)?;
+                check_range(start, end, len)?;
+                file.fill(start, end, data);
+            }
+            BatchCmd::Insert(pos, ref data) => {
+                check_range(pos, pos, len)?;
+                file.insert(pos, data);
+            }
+            BatchCmd::Delete(range) => {
+                let (start, end) = range.resolve(len)?;
+                check_range(start, end, len)?;
+                file.delete(start, end);
+            }
+            BatchCmd::Find(ref data) => {
+                let mut pos = 0;
+                while let Some(found) = file.find(pos, data) {
+                    writeln!(out, "0x{:08x}", found).map_err(|e| e.to_string())?;
+                    pos = found + 1;
+                }
+            }
+            BatchCmd::Dump(range) => {
+                let (start, end) = range.resolve(len)?;
+                check_range(start, end, len)?;
+                dump(out, &file.data, start, end).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(data : &[u8]) -> File {
+        let mut file = File::new();
+        file.data = data.to_vec();
+        file
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse_number("42"), Ok(42));
+        assert_eq!(parse_number(" 0x2A "), Ok(42));
+        assert!(parse_number("0x").is_err());
+        assert!(parse_number("-1").is_err());
+        assert!(parse_number("99999999999999999999999").is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        let r = parse_range("0x10:0x20").unwrap();
+        assert_eq!((r.start, r.end), (16, Some(32)));
+        let r = parse_range("16:").unwrap();
+        assert_eq!((r.start, r.end), (16, None));
+        let r = parse_range("16+4").unwrap();
+        assert_eq!((r.start, r.end), (16, Some(20)));
+        let r = parse_range("16").unwrap();
+        assert_eq!((r.start, r.end), (16, Some(17)));
+        assert!(parse_range("x:1").is_err());
+        assert!(parse_range("8:4").unwrap().resolve(10).is_err());
+    }
+
+    #[test]
+    fn ranges_out_of_bounds() {
+        let max = format!("{}", usize::MAX);
+        assert_eq!(parse_range(&format!("{}+1", max)).err().unwrap(), "range out of bounds");
+        assert_eq!(parse_range(&max).err().unwrap(), "range out of bounds");
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(parse_bytes("de ad BE EF"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse_bytes(" \"hi\" "), Ok(b"hi".to_vec()));
+        assert_eq!(parse_bytes("\"\""), Ok(vec![]));
+        assert!(parse_bytes("abc").is_err());
+        assert!(parse_bytes("zz").is_err());
+        assert!(parse_bytes("").is_err());
+    }
+
+    #[test]
+    fn run_commands() {
+        let mut f = file(b"abcdef");
+        let mut out = vec![];
+        BatchCmd::parse("--set", "1=5858").unwrap().run(&mut f, &mut out).unwrap();
+        BatchCmd::parse("--delete", "4:").unwrap().run(&mut f, &mut out).unwrap();
+        BatchCmd::parse("--insert", "0=\"<\"").unwrap().run(&mut f, &mut out).unwrap();
+        assert_eq!(f.data, b"<aXXd");
+        BatchCmd::parse("--find", "58").unwrap().run(&mut f, &mut out).unwrap();
+        assert_eq!(out, b"0x00000002\n0x00000003\n");
+        assert!(BatchCmd::parse("--fill", "3:9=00").unwrap().run(&mut f, &mut out).is_err());
+    }
+
+    #[test]
+    fn set_out_of_bounds() {
+        let mut f = file(b"abc");
+        let cmd = BatchCmd::Set(usize::MAX, vec![1]);
+        assert_eq!(cmd.run(&mut f, &mut vec![]).err().unwrap(), "range out of bounds");
+        assert_eq!(f.data, b"abc");
+    }
+}
@@ -1,21 +1,42 @@
 extern crate hedr;
 
 use std::io;
+use std::io::Write;
 use std::env;
+use std::fs;
+use std::mem;
+use std::process;
+use std::ffi::OsString;
 use std::os::unix::ffi::OsStrExt;
-use std::error::Error;
+use std::os::unix::io::{AsRawFd, FromRawFd};
 
 use hedr::editor::Editor;
 use hedr::file::File;
+use hedr::config::Config;
+use hedr::history::History;
+use hedr::bookmarks::Bookmarks;
+use hedr::term;
+use hedr::batch::{BatchCmd, BATCH_OPTIONS};
+use hedr::script::Script;
+use hedr::template::Template;
 
 fn print_help() {
     print!(r"hedr [options] [FILE]...
 
 options:
- -V  --version      show version information and exit
- -h  --help         show this help and exit
- -v                 view mode (read-only)
- FILE               file to edit or view
+ -V  --version        show version information and exit
+ -h  --help           show this help and exit
+ -v                   view mode (read-only)
+ -o  --stdout         write the first buffer to stdout on exit
+ -s  --script FILE    run the commands in FILE before starting the editor
+ -t  --template FILE  show the structure described by the template FILE
+ FILE                 file to edit or view, '-' reads from stdin
+
+batch commands (edit FILE without starting the editor):
+ --set OFF=DATA  --fill RANGE=DATA  --insert OFF=DATA
+ --delete RANGE  --find DATA        --dump RANGE
+
+See README.md for scripts, templates, settings and file formats.
 ");
 }
 
@@ -27,68 +48,334 @@ Source code: https://github.com/ricardo-massaro/hedr
 ");
 }
 
-fn parse_cmdline(editor : &mut Editor) -> bool  {
+struct Options {
+    read_stdin : bool,
+    write_stdout : bool,
+    batch : Vec<BatchCmd>,
+    script : Option<OsString>,
+    template : Option<OsString>,
+}
+
+fn parse_cmdline(editor : &mut Editor) -> Option<Options> {
     let mut args = env::args_os();
+    let mut opts = Options {
+        read_stdin : false,
+        write_stdout : false,
+        batch : vec![],
+        script : None,
+        template : None,
+    };
     
     let progname = args.next();
     if progname.is_none() {
-        return true;
+        return Some(opts);
     }
     let progname = match progname.unwrap().into_string() {
         Ok(s) => s,
         Err(_) => {
-            print!("Error: can't convert program name to UTF-8\n");
-            return false;
+            println!("Error: can't convert program name to UTF-8");
+            return None;
         }
     };
     
     while let Some(arg) = args.next() {
-        if arg.len() > 0 && arg.as_os_str().as_bytes()[0] == b'-' {
+        if ! arg.is_empty() && arg.as_os_str().as_bytes()[0] == b'-' {
             match arg.into_string() {
                 Ok(s) => match s.as_str() {
                     "-h" | "--help" => {
                         print_help();
-                        return false;
+                        return None;
                     }
 
                     "-V" | "--version" => {
                         print_version();
-                        return false;
+                        return None;
                     }
                     
                     "-v" => editor.read_only = true,
 
+                    "-o" | "--stdout" => opts.write_stdout = true,
+
+                    "-s" | "--script" => {
+                        match args.next() {
+                            Some(arg) => opts.script = Some(arg),
+                            None => {
+                                println!("{}: option {} requires an argument", progname, s);
+                                return None;
+                            }
+                        }
+                    }
+
+                    "-t" | "--template" => {
+                        match args.next() {
+                            Some(arg) => opts.template = Some(arg),
+                            None => {
+                                println!("{}: option {} requires an argument", progname, s);
+                                return None;
+                            }
+                        }
+                    }
+
+                    opt if BATCH_OPTIONS.contains(&opt) => {
+                        let arg = match args.next().map(|a| a.into_string()) {
+                            Some(Ok(arg)) => arg,
+                            _ => {
+                                println!("{}: option {} requires a UTF-8 argument", progname, opt);
+                                return None;
+                            }
+                        };
+                        match BatchCmd::parse(opt, &arg) {
+                            Ok(cmd) => opts.batch.push(cmd),
+                            Err(e) => {
+                                println!("{}: {}: {}", progname, opt, e);
+                                return None;
+                            }
+                        }
+                    }
+
+                    "-" => {
+                        if opts.read_stdin {
+                            println!("{}: stdin can only be read once", progname);
+                            return None;
+                        }
+                        match File::new_from_stdin() {
+                            Ok(file) => editor.add_file(file),
+                            Err(e) => {
+                                println!("{}: error reading stdin: {}", progname, e);
+                                return None;
+                            }
+                        }
+                        opts.read_stdin = true;
+                    }
+
                     _ => {
-                        print!("{}: unknown option: '{}'\n", progname, s);
-                        return false;
+                        println!("{}: unknown option: '{}'", progname, s);
+                        return None;
                     }
                 },
                 Err(arg) => {
-                    print!("{}: can't convert argument to UTF-8: {:?}\n", progname, arg);
-                    return false;
+                    println!("{}: can't convert argument to UTF-8: {:?}", progname, arg);
+                    return None;
                 }
             }
         } else {
             match File::new_from_file(arg) {
                 Ok(file) => editor.add_file(file),
                 Err((filename, e)) => {
-                    print!("{}: error reading file {:?}: {}\n", progname, filename, e.description());
-                    return false;
+                    println!("{}: error reading file {:?}: {}", progname, filename, e);
+                    return None;
                 }
             }
         }
     }
-    true
+    Some(opts)
+}
+
+// `fd` is stdout or the copy of it saved by term::redirect_stdout(), and
+// stays open either way
+fn write_output(fd : i32, data : &[u8]) -> io::Result<()> {
+    if fd == 1 {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(data)?;
+        return out.flush();
+    }
+    let mut out = mem::ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) });
+    out.write_all(data)?;
+    out.flush()
+}
+
+fn run_batch(editor : &mut Editor, opts : &Options) -> Result<(), String> {
+    if editor.files().len() != 1 {
+        return Err("batch commands need exactly one file".to_string());
+    }
+    let modifies = opts.batch.iter().any(|cmd| cmd.modifies_file());
+    if modifies && editor.read_only {
+        return Err("batch commands can't modify the file in view mode (-v)".to_string());
+    }
+    if modifies && opts.read_stdin && ! opts.write_stdout {
+        return Err("use -o to write the result of editing stdin".to_string());
+    }
+    let file = editor.cur_file_mut().unwrap();
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for cmd in &opts.batch {
+        cmd.run(file, &mut out)?;
+    }
+    if opts.write_stdout {
+        out.write_all(&file.data).map_err(|e| e.to_string())?;
+    } else if modifies {
+        file.save().map_err(|e| format!("error writing file: {}", e))?;
+    }
+    Ok(())
+}
+
+fn apply_template(editor : &mut Editor, path : &OsString) -> Result<(), String> {
+    let template = Template::load(path)?;
+    for file in editor.files_mut() {
+        let overlay = template.apply(&file.data)
+            .map_err(|e| format!("{}: {}", file.filename.clone().unwrap_or_default(), e))?;
+        file.overlay = Some(overlay);
+    }
+    Ok(())
+}
+
+fn run_script(editor : &mut Editor, path : &OsString) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("error reading script {:?}: {}", path, e))?;
+    let script = Script::parse(&text).map_err(|e| format!("{:?}: {}", path, e))?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    script.run(editor, &mut out).map_err(|e| format!("{:?}: {}", path, e))
 }
 
 fn main() {
-    let stdin = io::stdin();
-    let mut editor = Editor::new(stdin.lock());
+    let mut editor = Editor::new(Box::new(io::stdin()), 0);
+    editor.set_config(Config::load());
+    editor.set_history(History::load());
+    editor.set_bookmarks(Bookmarks::load());
+
+    let opts = match parse_cmdline(&mut editor) {
+        Some(opts) => opts,
+        None => return,
+    };
 
-    if ! parse_cmdline(&mut editor) {
+    if ! opts.batch.is_empty() {
+        if let Err(e) = run_batch(&mut editor, &opts) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
         return;
     }
+
+    if let Some(ref path) = opts.template {
+        if let Err(e) = apply_template(&mut editor, path) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(ref path) = opts.script {
+        if let Err(e) = run_script(&mut editor, path) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        if editor.quit {
+            if opts.write_stdout {
+                if let Some(file) = editor.files().first() {
+                    if let Err(e) = write_output(1, &file.data) {
+                        eprintln!("can't write to stdout: {}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+            return;
+        }
+    }
+
+    // when stdin or stdout are part of a pipeline, talk to the terminal directly
+    let mut out_fd = 1;
+    if opts.read_stdin || ! term::is_tty(0) || ! term::is_tty(1) {
+        let tty = match term::open_tty() {
+            Ok(tty) => tty,
+            Err(e) => {
+                println!("ERROR: can't open terminal: {}", e);
+                return;
+            }
+        };
+        if ! term::is_tty(1) {
+            out_fd = match term::redirect_stdout(tty.as_raw_fd()) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    println!("ERROR: can't redirect output to terminal: {}", e);
+                    return;
+                }
+            };
+        }
+        let tty_fd = tty.as_raw_fd();
+        editor.set_input(Box::new(tty), tty_fd);
+    }
+
     if let Err(e) = editor.run() {
         println!("ERROR: {}", e);
+        return;
+    }
+
+    if opts.write_stdout {
+        if let Some(file) = editor.files().first() {
+            if let Err(e) = write_output(out_fd, &file.data) {
+                println!("ERROR: can't write to stdout: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate libc;
+
+    use super::*;
+
+    fn options(batch : &[(&str, &str)]) -> Options {
+        Options {
+            read_stdin : false,
+            write_stdout : false,
+            batch : batch.iter().map(|&(opt, arg)| BatchCmd::parse(opt, arg).unwrap()).collect(),
+            script : None,
+            template : None,
+        }
+    }
+
+    // editor with one file holding `data` at a fresh temporary path
+    fn editor_with_file(name : &str, data : &[u8]) -> (Editor<'static>, std::path::PathBuf) {
+        let path = env::temp_dir().join(format!("hedr-{}-{}", name, process::id()));
+        fs::write(&path, data).unwrap();
+        let mut editor = Editor::new(Box::new(io::empty()), -1);
+        editor.add_file(File::new_from_file(path.clone().into_os_string()).unwrap());
+        (editor, path)
+    }
+
+    #[test]
+    fn batch_edits_file() {
+        let (mut editor, path) = editor_with_file("batch", b"abc");
+        assert_eq!(run_batch(&mut editor, &options(&[("--set", "1=ff")])), Ok(()));
+        assert_eq!(fs::read(&path).unwrap(), b"a\xffc");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn batch_read_only() {
+        let (mut editor, path) = editor_with_file("batch-ro", b"abc");
+        editor.read_only = true;
+        assert!(run_batch(&mut editor, &options(&[("--set", "1=ff")])).unwrap_err().contains("-v"));
+        assert!(run_batch(&mut editor, &options(&[("--delete", "0+1")])).is_err());
+        assert_eq!(run_batch(&mut editor, &options(&[("--find", "62")])), Ok(()));
+        assert_eq!(fs::read(&path).unwrap(), b"abc");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn output_fd_stays_open() {
+        let path = env::temp_dir().join(format!("hedr-output-{}", process::id()));
+        let file = fs::File::create(&path).unwrap();
+        let fd = unsafe { libc::dup(file.as_raw_fd()) };
+        assert!(fd > 1);
+        write_output(fd, b"abc").unwrap();
+        write_output(fd, b"def").unwrap();
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+        drop(file);
+        assert_eq!(fs::read(&path).unwrap(), b"abcdef");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn batch_stdin_needs_stdout() {
+        let mut editor = Editor::new(Box::new(io::empty()), -1);
+        let mut file = File::new();
+        file.data = b"abc".to_vec();
+        editor.add_file(file);
+        let mut opts = options(&[("--set", "0=00")]);
+        opts.read_stdin = true;
+        assert!(run_batch(&mut editor, &opts).unwrap_err().contains("-o"));
     }
 }
@@ -0,0 +1,142 @@
+
+use std::fs;
+use std::path::PathBuf;
+
+use super::config::config_dir;
+
+struct Entry {
+    path : String,
+    hash : u32,
+    name : String,
+    pos : usize,
+}
+
+fn storable(text : &str) -> bool {
+    ! text.contains(['\t', '\n'])
+}
+
+// named offsets of files, kept across sessions and stored as
+// "hash<TAB>offset<TAB>name<TAB>path" lines; the hash is the CRC-32 of the
+// file contents, so bookmarks follow files that are moved and are found by
+// path when a file changed outside the editor
+pub struct Bookmarks {
+    path : Option<PathBuf>,
+    entries : Vec<Entry>,
+}
+
+impl Default for Bookmarks {
+    fn default() -> Bookmarks {
+        Bookmarks::new()
+    }
+}
+
+impl Bookmarks {
+
+    pub fn new() -> Bookmarks {
+        Bookmarks {
+            path : None,
+            entries : vec![],
+        }
+    }
+
+    pub fn load() -> Bookmarks {
+        let path = match config_dir() {
+            Some(dir) => dir.join("bookmarks"),
+            None => return Bookmarks::new(),
+        };
+        let mut entries = vec![];
+        if let Ok(text) = fs::read_to_string(&path) {
+            for line in text.lines() {
+                let parts : Vec<&str> = line.splitn(4, '\t').collect();
+                if let [hash, pos, name, file] = parts.as_slice() {
+                    if let (Ok(hash), Ok(pos)) = (u32::from_str_radix(hash, 16), usize::from_str_radix(pos, 16)) {
+                        entries.push(Entry { path : file.to_string(), hash, name : name.to_string(), pos });
+                    }
+                }
+            }
+        }
+        Bookmarks {
+            path : Some(path),
+            entries,
+        }
+    }
+
+    // bookmarks of the file at `path` with contents `hash`, falling back
+    // to a file with the same contents and then to the same path
+    pub fn find(&self, path : &str, hash : u32) -> Vec<(String, usize)> {
+        let exact = self.entries.iter().any(|e| e.path == path && e.hash == hash);
+        let same_hash = self.entries.iter().find(|e| e.hash == hash).map(|e| e.path.clone());
+        let matches = |e : &Entry| if exact {
+            e.path == path && e.hash == hash
+        } else if let Some(ref other) = same_hash {
+            e.path == *other && e.hash == hash
+        } else {
+            e.path == path
+        };
+        self.entries.iter().filter(|e| matches(e)).map(|e| (e.name.clone(), e.pos)).collect()
+    }
+
+    // replaces the bookmarks of the file at `path`; paths and names that
+    // would break the lines of the bookmarks file are not stored
+    pub fn set(&mut self, path : &str, hash : u32, bookmarks : &[(String, usize)]) {
+        if ! storable(path) {
+            return;
+        }
+        self.entries.retain(|e| e.path != path);
+        for (name, pos) in bookmarks.iter().filter(|b| storable(&b.0)) {
+            self.entries.push(Entry { path : path.to_string(), hash, name : name.clone(), pos : *pos });
+        }
+        self.save();
+    }
+
+    // saving is best effort, like the prompt history
+    fn save(&self) {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+        let mut text = String::new();
+        for e in &self.entries {
+            text.push_str(&format!("{:08x}\t{:x}\t{}\t{}\n", e.hash, e.pos, e.name, e.path));
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, text);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks(names : &[(&str, usize)]) -> Vec<(String, usize)> {
+        names.iter().map(|&(name, pos)| (name.to_string(), pos)).collect()
+    }
+
+    #[test]
+    fn find_by_path_and_contents() {
+        let mut b = Bookmarks::new();
+        b.set("/a", 1, &marks(&[("one", 1), ("two", 2)]));
+        b.set("/b", 2, &marks(&[("other", 3)]));
+        assert_eq!(b.find("/a", 1), marks(&[("one", 1), ("two", 2)]));
+        // moved file, found by its contents
+        assert_eq!(b.find("/c", 2), marks(&[("other", 3)]));
+        // changed file, found by its path
+        assert_eq!(b.find("/a", 5), marks(&[("one", 1), ("two", 2)]));
+        assert_eq!(b.find("/c", 5), vec![]);
+        b.set("/a", 1, &[]);
+        assert_eq!(b.find("/a", 1), vec![]);
+    }
+
+    #[test]
+    fn tabs_and_newlines_are_not_stored() {
+        let mut b = Bookmarks::new();
+        b.set("/a", 1, &marks(&[("ok", 1), ("with\ttab", 2), ("with\nnewline", 3)]));
+        assert_eq!(b.find("/a", 1), marks(&[("ok", 1)]));
+        b.set("/a\tb", 1, &marks(&[("x", 1)]));
+        b.set("/a\nb", 1, &marks(&[("x", 1)]));
+        assert_eq!(b.entries.len(), 1);
+    }
+}
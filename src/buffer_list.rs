@@ -0,0 +1,59 @@
+
+use super::editor::Editor;
+use super::list_popup::ListPopup;
+
+// popup listing the open buffers; typing a number or part of a name
+// narrows the list
+pub struct BufferList<'a, 'b : 'a> {
+    popup : ListPopup<'a, 'b>,
+}
+
+impl<'a, 'b> BufferList<'a, 'b> {
+
+    pub fn new(editor : &'a mut Editor<'b>) -> BufferList<'a, 'b> {
+        let items = editor.files().iter().enumerate().map(|(index, file)| {
+            format!("{:3}{} {}  ({} bytes)",
+                    index + 1, if file.modified { '*' } else { ' ' },
+                    file.filename.clone().unwrap_or_else(|| file.short_name()),
+                    file.data.len())
+        }).collect();
+        let cur = editor.cur_file_index();
+        BufferList {
+            popup : ListPopup::new(editor, "Buffers", items).select(cur),
+        }
+    }
+
+    // returns the index of the chosen buffer
+    pub fn select_buffer(&mut self) -> Option<usize> {
+        self.popup.choose()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::file::File;
+
+    // buffer chosen after typing `keys` with buffers alpha, beta and gamma
+    fn select(keys : &[u8]) -> Option<usize> {
+        let mut editor = Editor::new(Box::new(keys), -1);
+        for name in &["alpha", "beta", "gamma"] {
+            let mut file = File::new();
+            file.data = name.as_bytes().to_vec();
+            file.filename = Some(name.to_string());
+            editor.add_file(file);
+        }
+        BufferList::new(&mut editor).select_buffer()
+    }
+
+    #[test]
+    fn select_buffer() {
+        assert_eq!(select(b"\r"), Some(2));
+        assert_eq!(select(b"\x1b[A\r"), Some(1));
+        assert_eq!(select(b"alp\r"), Some(0));
+        assert_eq!(select(b"2\r"), Some(1));
+        assert_eq!(select(b"delta\r"), None);
+        assert_eq!(select(b"\x03"), None);
+    }
+}
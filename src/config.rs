@@ -0,0 +1,210 @@
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use super::screen::{Color, ColorDepth, Theme};
+use super::term::parse_key_name;
+use super::keymap::{Action, KeyMap};
+use super::editor::EditorPane;
+use super::magic::MagicDb;
+use super::minimap::MinimapMode;
+
+pub const MAX_BYTES_PER_LINE : usize = 64;
+
+pub struct Config {
+    pub bytes_per_line : usize,
+    pub start_pane : EditorPane,
+    pub read_only : bool,
+    pub theme : Theme,
+    pub color_depth : Option<ColorDepth>,
+    pub byte_colors : bool,
+    pub min_string_length : usize,
+    pub minimap : MinimapMode,
+    pub keymap : KeyMap,
+    pub magic : MagicDb,
+    pub errors : Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
+}
+
+impl Config {
+
+    pub fn new() -> Config {
+        Config {
+            bytes_per_line : 16,
+            start_pane : EditorPane::Hex,
+            read_only : false,
+            theme : Theme::new(),
+            color_depth : None,
+            byte_colors : false,
+            min_string_length : 4,
+            minimap : MinimapMode::Bytes,
+            keymap : KeyMap::new(),
+            magic : MagicDb::new(),
+            errors : vec![],
+        }
+    }
+
+    pub fn load() -> Config {
+        let mut config = Config::new();
+        if let Some(path) = config_path() {
+            match fs::read_to_string(&path) {
+                Ok(text) => config.parse(&text),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => config.errors.push(format!("Error reading {}: {}", path.display(), e)),
+            }
+        }
+        config.magic = MagicDb::load(&mut config.errors);
+        config
+    }
+
+    pub fn parse(&mut self, text : &str) {
+        for (num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = match parts.next() {
+                Some(value) => value.trim(),
+                None => {
+                    self.errors.push(format!("config line {}: expected 'name = value'", num+1));
+                    continue;
+                }
+            };
+            if let Err(e) = self.set(name, value) {
+                self.errors.push(format!("config line {}: {}", num+1, e));
+            }
+        }
+    }
+
+    fn set(&mut self, name : &str, value : &str) -> Result<(), String> {
+        let words : Vec<&str> = name.split_whitespace().collect();
+        match words.as_slice() {
+            ["bytes_per_line"] => {
+                self.bytes_per_line = match value.parse::<usize>() {
+                    Ok(n) if (1..=MAX_BYTES_PER_LINE).contains(&n) => n,
+                    _ => return Err(format!("invalid bytes_per_line: '{}'", value)),
+                };
+            }
+            ["min_string_length"] => {
+                self.min_string_length = match value.parse::<usize>() {
+                    Ok(n) if n >= 1 => n,
+                    _ => return Err(format!("invalid min_string_length: '{}'", value)),
+                };
+            }
+            ["minimap"] => {
+                self.minimap = MinimapMode::from_name(value).ok_or_else(|| format!("invalid minimap: '{}'", value))?;
+            }
+            ["start_pane"] => {
+                self.start_pane = match value {
+                    "hex" => EditorPane::Hex,
+                    "text" => EditorPane::Text,
+                    _ => return Err(format!("invalid start_pane: '{}'", value)),
+                };
+            }
+            ["read_only"] => self.read_only = parse_bool(value)?,
+            ["byte_colors"] => self.byte_colors = parse_bool(value)?,
+            ["theme"] => {
+                self.theme = Theme::named(value).ok_or_else(|| format!("unknown theme: '{}'", value))?;
+            }
+            ["color_depth"] => {
+                self.color_depth = Some(match value {
+                    "8" | "basic" => ColorDepth::Basic,
+                    "256" => ColorDepth::Indexed,
+                    "truecolor" | "24bit" => ColorDepth::TrueColor,
+                    _ => return Err(format!("invalid color_depth: '{}'", value)),
+                });
+            }
+            ["color", element] => {
+                let colors = value.split_whitespace().collect::<Vec<_>>();
+                let (fg, bg) = match colors.as_slice() {
+                    [fg] => (Color::fg_from_name(fg), Some(Color::None)),
+                    [fg, bg] => (Color::fg_from_name(fg), Color::bg_from_name(bg)),
+                    _ => (None, None),
+                };
+                let pair = match (fg, bg) {
+                    (Some(fg), Some(bg)) => (fg, bg),
+                    _ => return Err(format!("invalid color: '{}'", value)),
+                };
+                match self.theme.element_mut(element) {
+                    Some(e) => *e = pair,
+                    None => return Err(format!("unknown color element: '{}'", element)),
+                }
+            }
+            ["bind", key] => {
+                let key = parse_key_name(key).ok_or_else(|| format!("invalid key: '{}'", key))?;
+                if value == "none" {
+                    self.keymap.unbind(&key);
+                } else {
+                    let action = Action::from_name(value).ok_or_else(|| format!("unknown action: '{}'", value))?;
+                    self.keymap.bind(key, action);
+                }
+            }
+            _ => return Err(format!("unknown setting: '{}'", name)),
+        }
+        Ok(())
+    }
+
+}
+
+fn parse_bool(value : &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("invalid boolean: '{}'", value)),
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if ! dir.is_empty() => Some(PathBuf::from(dir).join("hedr")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("hedr")),
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::term::{Key, Special, MOD_CTRL};
+
+    #[test]
+    fn settings() {
+        let mut config = Config::new();
+        config.parse("# comment\n\nbytes_per_line = 32\nstart_pane = text\nread_only = yes\n\
+                      minimap = entropy\nbind C-Up = page-up\nbind C-x = none\n");
+        assert_eq!(config.errors.len(), 0, "{:?}", config.errors);
+        assert_eq!(config.bytes_per_line, 32);
+        assert!(config.start_pane == EditorPane::Text);
+        assert!(config.read_only);
+        assert!(config.minimap == MinimapMode::Entropy);
+        assert_eq!(config.keymap.lookup(&Key::Special(Special::Up, MOD_CTRL)), Some(Action::PageUp));
+        assert_eq!(config.keymap.lookup(&Key::Ctrl('x')), None);
+    }
+
+    #[test]
+    fn errors() {
+        let mut config = Config::new();
+        config.parse("bytes_per_line = 0\nnonsense\ncolor nothing = red\nbind C-Q-x = quit\nbind C-x = fly\nfoo = 1\n");
+        assert_eq!(config.errors, [
+            "config line 1: invalid bytes_per_line: '0'",
+            "config line 2: expected 'name = value'",
+            "config line 3: unknown color element: 'nothing'",
+            "config line 4: invalid key: 'C-Q-x'",
+            "config line 5: unknown action: 'fly'",
+            "config line 6: unknown setting: 'foo'",
+        ]);
+        assert_eq!(config.bytes_per_line, 16);
+    }
+}
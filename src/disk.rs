@@ -0,0 +1,703 @@
+
+use super::overlay::{Field, Overlay};
+use super::formats::{Fields, group, enum_name, check_crc};
+use super::template::format_bytes;
+use super::inflate::crc32;
+
+const SECTOR : usize = 512;
+const BOOT_SIGNATURE : &[u8] = b"\x55\xaa";
+const GPT_SIGNATURE : &[u8] = b"EFI PART";
+// size of the header fields covered by its CRC in revision 1.0
+const GPT_HEADER_SIZE : usize = 92;
+const EXT_SUPERBLOCK : usize = 1024;
+const EXT_MAGIC : u64 = 0xef53;
+const GPT_PROTECTIVE : u64 = 0xee;
+const ISO_PVD : usize = 0x8000;
+// logical partitions followed in a chain of extended boot records
+const MAX_LOGICAL : usize = 128;
+const MAX_GPT_ENTRIES : usize = 1024;
+
+const MBR_TYPE_NAMES : &[(u64, &str)] = &[
+    (0x00, "empty"), (0x01, "FAT12"), (0x04, "FAT16 <32M"), (0x05, "extended"), (0x06, "FAT16"),
+    (0x07, "NTFS/exFAT"), (0x0b, "FAT32 CHS"), (0x0c, "FAT32 LBA"), (0x0e, "FAT16 LBA"),
+    (0x0f, "extended LBA"), (0x82, "Linux swap"), (0x83, "Linux"), (0x85, "Linux extended"),
+    (0x8e, "Linux LVM"), (0xa5, "FreeBSD"), (0xee, "GPT protective"), (0xef, "EFI system"),
+    (0xfd, "Linux RAID"),
+];
+
+const GPT_TYPE_NAMES : &[(&str, &str)] = &[
+    ("C12A7328-F81F-11D2-BA4B-00A0C93EC93B", "EFI system"),
+    ("21686148-6449-6E6F-744E-656564454649", "BIOS boot"),
+    ("0FC63DAF-8483-4772-8E79-3D69D8477DE4", "Linux filesystem"),
+    ("0657FD6D-A4AB-43C4-84E5-0933C84B4F4F", "Linux swap"),
+    ("E6D6D379-F507-44C2-A23C-238F2A3DF928", "Linux LVM"),
+    ("A19D880F-05FC-4D3B-A006-743F0F84911E", "Linux RAID"),
+    ("4F68BCE3-E8CD-4DB1-96E7-FBCAF984B709", "Linux root (x86-64)"),
+    ("933AC7E1-2EB4-4F13-B844-0E14E2AEF915", "Linux home"),
+    ("EBD0A0A2-B9E5-4433-87C0-68B6B72699C7", "Microsoft basic data"),
+    ("E3C9E316-0B5C-4DB8-817D-F92DF00215AE", "Microsoft reserved"),
+    ("DE94BBA4-06D1-4D40-A16A-BFD50179D6AC", "Windows recovery"),
+    ("48465300-0000-11AA-AA11-00306543ECAC", "Apple HFS+"),
+    ("7C3457EF-0000-11AA-AA11-00306543ECAC", "Apple APFS"),
+];
+
+const GPT_ATTRIBUTE_NAMES : &[(u64, &str)] = &[
+    (0x1, "REQUIRED"), (0x2, "NO_BLOCK_IO"), (0x4, "LEGACY_BIOS_BOOTABLE"),
+];
+
+const EXT_COMPAT_HAS_JOURNAL : u64 = 0x4;
+const EXT_INCOMPAT_EXTENTS : u64 = 0x40;
+
+const EXT_STATE_NAMES : &[(u64, &str)] = &[(1, "clean"), (2, "errors"), (4, "orphans")];
+
+const EXT_OS_NAMES : &[(u64, &str)] = &[
+    (0, "Linux"), (1, "Hurd"), (2, "Masix"), (3, "FreeBSD"), (4, "Lites"),
+];
+
+// a partition found in a partition table
+struct Partition {
+    name : String,
+    start : usize,
+}
+
+fn type_name(ty : u64) -> String {
+    match MBR_TYPE_NAMES.iter().find(|t| t.0 == ty) {
+        Some(t) => t.1.to_string(),
+        None => format!("type 0x{:02x}", ty),
+    }
+}
+
+fn is_extended(ty : u64) -> bool {
+    ty == 0x05 || ty == 0x0f || ty == 0x85
+}
+
+// the mixed endian text form of a GUID
+fn guid(bytes : &[u8]) -> String {
+    format!("{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+            bytes[3], bytes[2], bytes[1], bytes[0], bytes[5], bytes[4], bytes[7], bytes[6],
+            bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15])
+}
+
+fn utf16_name(bytes : &[u8]) -> String {
+    let units : Vec<u16> = bytes.chunks(2).map(|c| c[0] as u16 | (c[1] as u16) << 8).take_while(|&u| u != 0).collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn at(data : &[u8], pos : usize, bytes : &[u8]) -> bool {
+    data.get(pos..pos+bytes.len()) == Some(bytes)
+}
+
+// name of the filesystem whose superblock or boot sector starts at `pos`
+fn filesystem_at(data : &[u8], pos : usize) -> Option<&'static str> {
+    let f = Fields::new(data, 0, false);
+    if at(data, pos + 3, b"NTFS    ") {
+        return Some("NTFS");
+    }
+    if at(data, pos + 510, BOOT_SIGNATURE) && (at(data, pos + 54, b"FAT") || at(data, pos + 82, b"FAT32")) {
+        return Some(if f.peek(pos + 22, 2) == Some(0) { "FAT32" } else { "FAT" });
+    }
+    if f.peek(pos + EXT_SUPERBLOCK + 56, 2) == Some(EXT_MAGIC) {
+        let compat = f.peek(pos + EXT_SUPERBLOCK + 92, 4).unwrap_or(0);
+        let incompat = f.peek(pos + EXT_SUPERBLOCK + 96, 4).unwrap_or(0);
+        return Some(if incompat & EXT_INCOMPAT_EXTENTS != 0 { "ext4" }
+                    else if compat & EXT_COMPAT_HAS_JOURNAL != 0 { "ext3" }
+                    else { "ext2" });
+    }
+    if at(data, pos + ISO_PVD, b"\x01CD001") {
+        return Some("ISO 9660");
+    }
+    None
+}
+
+fn gpt_header_pos(data : &[u8]) -> Option<usize> {
+    [SECTOR, 4096].iter().cloned().find(|&pos| at(data, pos, GPT_SIGNATURE))
+}
+
+// an MBR has the boot signature and plausible partition entries
+fn is_mbr(data : &[u8]) -> bool {
+    if ! at(data, 510, BOOT_SIGNATURE) {
+        return false;
+    }
+    let entries = &data[446..510];
+    entries.chunks(16).all(|e| e[0] == 0 || e[0] == 0x80)
+        && entries.chunks(16).any(|e| e[4] != 0)
+}
+
+pub fn detect(data : &[u8]) -> Option<String> {
+    if data.len() < SECTOR {
+        return None;
+    }
+    if gpt_header_pos(data).is_some() {
+        return Some("disk image with a GPT partition table".to_string());
+    }
+    if let Some(fs) = filesystem_at(data, 0) {
+        return Some(format!("{} filesystem image", fs));
+    }
+    if is_mbr(data) {
+        return Some("disk image with an MBR partition table".to_string());
+    }
+    None
+}
+
+fn mbr_entry(data : &[u8], pos : usize, name : &str) -> Option<(Field, u64, u64, u64)> {
+    let mut e = Fields::new(data, pos, false);
+    e.named("status", 1, &[(0, "inactive"), (0x80, "active")])?;
+    e.bytes("chs_first", 3)?;
+    let ty = e.named("type", 1, MBR_TYPE_NAMES)?;
+    e.bytes("chs_last", 3)?;
+    let lba = e.uint("lba_first", 4)?;
+    let sectors = e.uint("sectors", 4)?;
+    let value = if ty == 0 { "empty".to_string() }
+                else { format!("{} at sector {}, {} sectors", enum_name(ty, MBR_TYPE_NAMES), lba, sectors) };
+    Some((e.into_field(name, value), ty, lba, sectors))
+}
+
+// boot code, four primary partition entries and the boot signature;
+// extended partitions hold a chain of boot records with one logical
+// partition each
+fn decode_mbr(data : &[u8], fields : &mut Vec<Field>, partitions : &mut Vec<Partition>) -> Option<()> {
+    let mut mbr = Fields::new(data, 0, false);
+    mbr.bytes("boot_code", 440)?;
+    mbr.uint_with("disk_signature", 4, |v| format!("0x{:08x}", v))?;
+    mbr.bytes("reserved", 2)?;
+    let mut extended = None;
+    for i in 0..4 {
+        let (entry, ty, lba, sectors) = mbr_entry(data, mbr.pos, &format!("[{}]", i))?;
+        mbr.pos += 16;
+        mbr.push(entry);
+        if is_extended(ty) {
+            extended = Some(lba);
+        } else if ty != 0 && ty != GPT_PROTECTIVE && sectors > 0 {
+            partitions.push(Partition { name : format!("partition {} {}", i + 1, type_name(ty)), start : lba as usize * SECTOR });
+        }
+    }
+    mbr.bytes("signature", 2)?;
+    fields.push(mbr.into_field("mbr", String::new()));
+
+    // logical partitions are relative to their boot record, the next
+    // boot record is relative to the extended partition
+    if let Some(ext_lba) = extended {
+        let mut ebr_lba = ext_lba;
+        let mut n = 0;
+        while n < MAX_LOGICAL {
+            let pos = ebr_lba as usize * SECTOR;
+            if ! at(data, pos + 510, BOOT_SIGNATURE) {
+                break;
+            }
+            let mut ebr = Fields::new(data, pos + 446, false);
+            let (entry, ty, lba, sectors) = mbr_entry(data, ebr.pos, "logical")?;
+            ebr.pos += 16;
+            ebr.push(entry);
+            let (next, _, next_lba, _) = mbr_entry(data, ebr.pos, "next")?;
+            ebr.pos += 16;
+            ebr.push(next);
+            fields.push(ebr.into_field(&format!("ebr {}", n + 1), String::new()));
+            if ty != 0 && sectors > 0 {
+                let start = (ebr_lba + lba) as usize * SECTOR;
+                partitions.push(Partition { name : format!("partition {} {}", n + 5, type_name(ty)), start });
+            }
+            if next_lba == 0 {
+                break;
+            }
+            ebr_lba = ext_lba + next_lba;
+            n += 1;
+        }
+    }
+    Some(())
+}
+
+// header with CRCs of itself and of the partition entry array
+fn decode_gpt(data : &[u8], pos : usize, fields : &mut Vec<Field>, partitions : &mut Vec<Partition>,
+              warnings : &mut Vec<String>) -> Option<()> {
+    let sector = pos;
+    let mut hdr = Fields::new(data, pos, false);
+    hdr.text("signature", 8)?;
+    hdr.uint_with("revision", 4, |v| format!("{}.{}", v >> 16, v & 0xffff))?;
+    let size = hdr.uint("header_size", 4)? as usize;
+    let crc_pos = hdr.pos;
+    let stored_crc = hdr.peek(crc_pos, 4)?;
+    // the CRC covers header_size bytes with the CRC field zeroed
+    let status = match data.get(pos..pos+size) {
+        Some(header) if (GPT_HEADER_SIZE..=sector).contains(&size) => {
+            let mut header = header.to_vec();
+            header[crc_pos-pos..crc_pos-pos+4].copy_from_slice(&[0; 4]);
+            check_crc(stored_crc, crc32(&header), "GPT header", warnings)
+        }
+        _ => {
+            warnings.push(format!("invalid GPT header_size {}", size));
+            " NOT CHECKED, invalid header_size".to_string()
+        }
+    };
+    hdr.uint_with("header_crc32", 4, |v| format!("0x{:08x}{}", v, status))?;
+    hdr.bytes("reserved", 4)?;
+    hdr.uint("current_lba", 8)?;
+    hdr.uint("backup_lba", 8)?;
+    hdr.uint("first_usable_lba", 8)?;
+    hdr.uint("last_usable_lba", 8)?;
+    let disk_guid = guid(hdr.bytes("disk_guid", 16)?);
+    hdr.fields.last_mut()?.value = disk_guid;
+    let entries_lba = hdr.uint("partition_entries_lba", 8)?;
+    let n_entries = hdr.uint("number_of_entries", 4)? as usize;
+    let entry_size = hdr.uint("entry_size", 4)? as usize;
+    let entries_crc = hdr.peek(hdr.pos, 4)?;
+    let entries_pos = (entries_lba as usize).saturating_mul(sector);
+    let entries_len = n_entries.saturating_mul(entry_size);
+    let status = match data.get(entries_pos..entries_pos.saturating_add(entries_len)) {
+        Some(entries) => check_crc(entries_crc, crc32(entries), "GPT partition entries", warnings),
+        None => String::new(),
+    };
+    hdr.uint_with("entries_crc32", 4, |v| format!("0x{:08x}{}", v, status))?;
+    fields.push(hdr.into_field("gpt_header", String::new()));
+
+    if entry_size < 128 || data.len() < entries_pos {
+        return Some(());
+    }
+    let mut entries = vec![];
+    for i in 0..n_entries.min(MAX_GPT_ENTRIES) {
+        let mut e = Fields::new(data, entries_pos + i * entry_size, false);
+        let type_guid = guid(data.get(e.pos..e.pos+16)?);
+        if type_guid == "00000000-0000-0000-0000-000000000000" {
+            continue;
+        }
+        let type_name = GPT_TYPE_NAMES.iter().find(|t| t.0 == type_guid).map_or("unknown type", |t| t.1);
+        e.bytes("type_guid", 16)?;
+        e.fields.last_mut()?.value = format!("{} ({})", type_name, type_guid);
+        let unique = guid(e.bytes("unique_guid", 16)?);
+        e.fields.last_mut()?.value = unique;
+        let first = e.uint("first_lba", 8)?;
+        let last = e.uint("last_lba", 8)?;
+        e.flags("attributes", 8, GPT_ATTRIBUTE_NAMES)?;
+        let name = utf16_name(e.bytes("name", 72)?);
+        e.fields.last_mut()?.value = format!("\"{}\"", name);
+        e.pos = e.start() + entry_size;
+        let label = if ! name.is_empty() { name } else { type_name.to_string() };
+        partitions.push(Partition { name : format!("partition {} {}", i + 1, label), start : (first as usize).saturating_mul(sector) });
+        let value = format!("{}, sectors {}-{}", type_name, first, last);
+        entries.push(e.into_field(&format!("[{}] {}", i, label), value));
+    }
+    fields.push(group("gpt_entries", entries_pos, entries_len.min(data.len() - entries_pos), entries));
+    Some(())
+}
+
+fn text(f : &mut Fields, name : &str, len : usize) -> Option<String> {
+    let bytes = f.text(name, len)?;
+    Some(String::from_utf8_lossy(bytes).trim_end_matches([' ', '\0']).to_string())
+}
+
+fn ext_superblock(data : &[u8], pos : usize, fs : &str) -> Option<Field> {
+    let mut f = Fields::new(data, pos, false);
+    f.uint("inodes_count", 4)?;
+    let blocks = f.uint("blocks_count", 4)?;
+    f.uint("reserved_blocks_count", 4)?;
+    f.uint("free_blocks_count", 4)?;
+    f.uint("free_inodes_count", 4)?;
+    f.uint("first_data_block", 4)?;
+    let log_block_size = f.uint_with("log_block_size", 4, |v| format!("{} ({} byte blocks)", v, 1024u64 << v.min(16)))?;
+    f.uint("log_cluster_size", 4)?;
+    f.uint("blocks_per_group", 4)?;
+    f.uint("clusters_per_group", 4)?;
+    f.uint("inodes_per_group", 4)?;
+    f.uint("mount_time", 4)?;
+    f.uint("write_time", 4)?;
+    f.uint("mount_count", 2)?;
+    f.uint("max_mount_count", 2)?;
+    f.uint_with("magic", 2, |v| format!("0x{:04x}", v))?;
+    f.flags("state", 2, EXT_STATE_NAMES)?;
+    f.uint("errors", 2)?;
+    f.uint("minor_rev_level", 2)?;
+    f.uint("last_check", 4)?;
+    f.uint("check_interval", 4)?;
+    f.named("creator_os", 4, EXT_OS_NAMES)?;
+    f.uint("rev_level", 4)?;
+    f.uint("default_resuid", 2)?;
+    f.uint("default_resgid", 2)?;
+    f.uint("first_inode", 4)?;
+    f.uint("inode_size", 2)?;
+    f.uint("block_group_nr", 2)?;
+    f.uint_with("feature_compat", 4, |v| format!("0x{:x}", v))?;
+    f.uint_with("feature_incompat", 4, |v| format!("0x{:x}", v))?;
+    f.uint_with("feature_ro_compat", 4, |v| format!("0x{:x}", v))?;
+    let uuid = guid(f.bytes("uuid", 16)?);
+    f.fields.last_mut()?.value = uuid.to_lowercase();
+    let label = text(&mut f, "volume_name", 16)?;
+    text(&mut f, "last_mounted", 64)?;
+    let size = blocks << (10 + log_block_size.min(16));
+    Some(f.into_field(&format!("{} superblock", fs), format!("\"{}\", {} bytes", label, size)))
+}
+
+fn fat_boot_sector(data : &[u8], pos : usize, fs : &str) -> Option<Field> {
+    let mut f = Fields::new(data, pos, false);
+    f.bytes("jump", 3)?;
+    text(&mut f, "oem_name", 8)?;
+    let sector_size = f.uint("bytes_per_sector", 2)?;
+    f.uint("sectors_per_cluster", 1)?;
+    f.uint("reserved_sectors", 2)?;
+    f.uint("number_of_fats", 1)?;
+    f.uint("root_entries", 2)?;
+    let sectors16 = f.uint("total_sectors_16", 2)?;
+    f.uint_with("media", 1, |v| format!("0x{:02x}", v))?;
+    f.uint("sectors_per_fat_16", 2)?;
+    f.uint("sectors_per_track", 2)?;
+    f.uint("heads", 2)?;
+    f.uint("hidden_sectors", 4)?;
+    let sectors32 = f.uint("total_sectors_32", 4)?;
+    if fs == "FAT32" {
+        f.uint("sectors_per_fat_32", 4)?;
+        f.uint_with("ext_flags", 2, |v| format!("0x{:x}", v))?;
+        f.uint("fs_version", 2)?;
+        f.uint("root_cluster", 4)?;
+        f.uint("fs_info_sector", 2)?;
+        f.uint("backup_boot_sector", 2)?;
+        f.bytes("reserved", 12)?;
+    }
+    f.uint_with("drive_number", 1, |v| format!("0x{:02x}", v))?;
+    f.uint("reserved1", 1)?;
+    f.uint_with("boot_signature", 1, |v| format!("0x{:02x}", v))?;
+    f.uint_with("volume_id", 4, |v| format!("{:04X}-{:04X}", v >> 16, v & 0xffff))?;
+    let label = text(&mut f, "volume_label", 11)?;
+    text(&mut f, "fs_type", 8)?;
+    f.pos = pos + 510;
+    f.bytes("signature", 2)?;
+    let sectors = if sectors16 != 0 { sectors16 } else { sectors32 };
+    Some(f.into_field(&format!("{} boot sector", fs), format!("\"{}\", {} bytes", label, sectors * sector_size)))
+}
+
+fn ntfs_boot_sector(data : &[u8], pos : usize) -> Option<Field> {
+    let mut f = Fields::new(data, pos, false);
+    f.bytes("jump", 3)?;
+    text(&mut f, "oem_id", 8)?;
+    let sector_size = f.uint("bytes_per_sector", 2)?;
+    let cluster_sectors = f.uint("sectors_per_cluster", 1)?;
+    f.uint("reserved_sectors", 2)?;
+    f.bytes("unused1", 5)?;
+    f.uint_with("media", 1, |v| format!("0x{:02x}", v))?;
+    f.bytes("unused2", 2)?;
+    f.uint("sectors_per_track", 2)?;
+    f.uint("heads", 2)?;
+    f.uint("hidden_sectors", 4)?;
+    f.bytes("unused3", 8)?;
+    let sectors_index = f.fields.len();
+    let sectors = f.uint("total_sectors", 8)?;
+    let mft_index = f.fields.len();
+    let mft = f.uint("mft_cluster", 8)?;
+    f.uint("mft_mirror_cluster", 8)?;
+    f.uint_with("clusters_per_mft_record", 1, |v| {
+        // negative values are the log2 of the size in bytes
+        if v >= 0x80 { format!("{} bytes", 1u64 << (256 - v).min(31)) } else { v.to_string() }
+    })?;
+    f.bytes("unused4", 3)?;
+    f.uint("clusters_per_index_record", 1)?;
+    f.bytes("unused5", 3)?;
+    f.uint_with("volume_serial", 8, |v| format!("{:016X}", v))?;
+    f.uint_with("checksum", 4, |v| format!("0x{:x}", v))?;
+    f.pos = pos + 510;
+    f.bytes("signature", 2)?;
+    // crafted boot sectors can have sizes that don't fit in 64 bits
+    let size = match sectors.checked_mul(sector_size) {
+        Some(size) => format!("{} bytes", size),
+        None => {
+            f.fields[sectors_index].value.push_str(" INVALID, size overflows");
+            "invalid size".to_string()
+        }
+    };
+    let mft_pos = match mft.checked_mul(cluster_sectors).and_then(|n| n.checked_mul(sector_size)) {
+        Some(mft_pos) => format!("0x{:x}", mft_pos),
+        None => {
+            f.fields[mft_index].value.push_str(" INVALID, offset overflows");
+            "an invalid offset".to_string()
+        }
+    };
+    Some(f.into_field("NTFS boot sector", format!("{}, MFT at {}", size, mft_pos)))
+}
+
+// numbers in ISO 9660 are stored both little and big endian
+fn both_endian(f : &mut Fields, name : &str, size : usize) -> Option<u64> {
+    let v = f.uint(name, size)?;
+    f.pos += size;
+    if let Some(field) = f.fields.last_mut() {
+        field.len *= 2;
+    }
+    Some(v)
+}
+
+fn iso_pvd(data : &[u8], pos : usize) -> Option<Field> {
+    let mut f = Fields::new(data, pos, false);
+    f.uint("type", 1)?;
+    text(&mut f, "identifier", 5)?;
+    f.uint("version", 1)?;
+    f.bytes("unused1", 1)?;
+    text(&mut f, "system_id", 32)?;
+    let label = text(&mut f, "volume_id", 32)?;
+    f.bytes("unused2", 8)?;
+    let blocks = both_endian(&mut f, "volume_space_size", 4)?;
+    f.bytes("unused3", 32)?;
+    both_endian(&mut f, "volume_set_size", 2)?;
+    both_endian(&mut f, "volume_sequence_number", 2)?;
+    let block_size = both_endian(&mut f, "logical_block_size", 2)?;
+    both_endian(&mut f, "path_table_size", 4)?;
+    f.uint("l_path_table", 4)?;
+    f.uint("optional_l_path_table", 4)?;
+    f.pos += 8;
+    f.bytes("root_directory_record", 34)?;
+    text(&mut f, "volume_set_id", 128)?;
+    text(&mut f, "publisher_id", 128)?;
+    text(&mut f, "preparer_id", 128)?;
+    text(&mut f, "application_id", 128)?;
+    text(&mut f, "copyright_file_id", 37)?;
+    text(&mut f, "abstract_file_id", 37)?;
+    text(&mut f, "bibliographic_file_id", 37)?;
+    for name in &["creation_date", "modification_date", "expiration_date", "effective_date"] {
+        let date = f.bytes(name, 17)?;
+        f.fields.last_mut()?.value = format_bytes(&date[..16], true);
+    }
+    f.uint("file_structure_version", 1)?;
+    Some(f.into_field("ISO 9660 primary volume descriptor", format!("\"{}\", {} bytes", label, blocks * block_size)))
+}
+
+// the superblock or boot sector of the filesystem starting at `pos`
+fn decode_filesystem(data : &[u8], pos : usize) -> Option<(Field, usize)> {
+    let fs = filesystem_at(data, pos)?;
+    match fs {
+        "NTFS" => ntfs_boot_sector(data, pos).map(|f| (f, pos)),
+        "FAT" | "FAT32" => fat_boot_sector(data, pos, fs).map(|f| (f, pos)),
+        "ISO 9660" => iso_pvd(data, pos + ISO_PVD).map(|f| (f, pos + ISO_PVD)),
+        _ => ext_superblock(data, pos + EXT_SUPERBLOCK, fs).map(|f| (f, pos + EXT_SUPERBLOCK)),
+    }
+}
+
+// partition tables and the superblocks of the filesystems in the
+// partitions, or of a filesystem image without a partition table
+pub fn decode(data : &[u8]) -> Option<Overlay> {
+    detect(data)?;
+    let mut fields = vec![];
+    let mut partitions = vec![];
+    let mut warnings = vec![];
+    let gpt = gpt_header_pos(data);
+    if gpt.is_some() || (filesystem_at(data, 0).is_none() && is_mbr(data)) {
+        decode_mbr(data, &mut fields, &mut partitions);
+    }
+    if let Some(pos) = gpt {
+        decode_gpt(data, pos, &mut fields, &mut partitions, &mut warnings);
+    }
+    if partitions.is_empty() {
+        partitions.push(Partition { name : "filesystem".to_string(), start : 0 });
+    }
+
+    let mut locations = vec![];
+    let mut bases = vec![];
+    for p in &partitions {
+        if p.start >= data.len() {
+            warnings.push(format!("{} starts past the end of the image", p.name));
+            continue;
+        }
+        bases.push(p.start);
+        locations.push((p.name.clone(), p.start));
+        if let Some((field, pos)) = decode_filesystem(data, p.start) {
+            locations.push((format!("{} {}", p.name, field.name), pos));
+            fields.push(field);
+        }
+    }
+    fields.sort_by_key(|f| f.start);
+    locations.sort_by_key(|l| l.1);
+    let mut overlay = Overlay::new("disk", fields);
+    overlay.locations = locations;
+    overlay.bases = bases;
+    overlay.warnings = warnings;
+    Some(overlay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(data : &mut [u8], pos : usize, bytes : &[u8]) {
+        data[pos..pos+bytes.len()].copy_from_slice(bytes);
+    }
+
+    // protective MBR, GPT header at LBA 1 and one partition entry at LBA 2
+    fn gpt_image(header_size : u32) -> Vec<u8> {
+        let mut data = vec![0; 48 * SECTOR];
+        put(&mut data, 446 + 4, &[0xee]);
+        put(&mut data, 510, BOOT_SIGNATURE);
+        let entry = 2 * SECTOR;
+        put(&mut data, entry, &[0xaf, 0x3d, 0xc6, 0x0f, 0x83, 0x84, 0x72, 0x47, 0x8e, 0x79, 0x3d, 0x69, 0xd8, 0x47, 0x7d, 0xe4]);
+        put(&mut data, entry + 32, &34u64.to_le_bytes());
+        put(&mut data, entry + 40, &40u64.to_le_bytes());
+        let entries_crc = crc32(&data[entry..entry + 4 * 128]);
+        let h = SECTOR;
+        put(&mut data, h, GPT_SIGNATURE);
+        put(&mut data, h + 8, &0x10000u32.to_le_bytes());
+        put(&mut data, h + 12, &header_size.to_le_bytes());
+        put(&mut data, h + 72, &2u64.to_le_bytes());
+        put(&mut data, h + 80, &4u32.to_le_bytes());
+        put(&mut data, h + 84, &128u32.to_le_bytes());
+        put(&mut data, h + 88, &entries_crc.to_le_bytes());
+        let header_crc = crc32(&data[h..h + 92]);
+        put(&mut data, h + 16, &header_crc.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn gpt_partitions() {
+        let overlay = decode(&gpt_image(92)).unwrap();
+        assert_eq!(overlay.warnings.len(), 0);
+        assert!(overlay.locations.iter().any(|l| l.0.starts_with("partition 1 ") && l.1 == 34 * SECTOR));
+    }
+
+    #[test]
+    fn gpt_bad_header_crc() {
+        let mut data = gpt_image(92);
+        data[SECTOR + 40] ^= 1;
+        let overlay = decode(&data).unwrap();
+        assert!(overlay.warnings.iter().any(|w| w.contains("GPT header")));
+    }
+
+    #[test]
+    fn gpt_invalid_header_size() {
+        for &size in &[0, 19, 91, 513, 0xffff_ffff] {
+            let overlay = decode(&gpt_image(size)).unwrap();
+            assert!(overlay.warnings.iter().any(|w| w.contains("header_size")), "header_size {}", size);
+        }
+    }
+
+    fn ntfs_image(sectors : u64, mft : u64) -> Vec<u8> {
+        let mut data = vec![0; 4 * SECTOR];
+        put(&mut data, 3, b"NTFS    ");
+        put(&mut data, 11, &512u16.to_le_bytes());
+        put(&mut data, 13, &[8]);
+        put(&mut data, 40, &sectors.to_le_bytes());
+        put(&mut data, 48, &mft.to_le_bytes());
+        put(&mut data, 510, BOOT_SIGNATURE);
+        data
+    }
+
+    fn values(fields : &[Field], out : &mut Vec<String>) {
+        for field in fields {
+            out.push(format!("{} = {}", field.name, field.value));
+            values(&field.children, out);
+        }
+    }
+
+    #[test]
+    fn ntfs_sizes() {
+        let overlay = decode(&ntfs_image(4, 2)).unwrap();
+        let mut out = vec![];
+        values(&overlay.fields, &mut out);
+        assert!(out.contains(&"NTFS boot sector = 2048 bytes, MFT at 0x2000".to_string()), "{:?}", out);
+    }
+
+    #[test]
+    fn ntfs_overflowing_sizes() {
+        let overlay = decode(&ntfs_image(u64::MAX, u64::MAX / 2)).unwrap();
+        let mut out = vec![];
+        values(&overlay.fields, &mut out);
+        assert!(out.iter().any(|v| v.starts_with("total_sectors") && v.contains("INVALID")), "{:?}", out);
+        assert!(out.iter().any(|v| v.starts_with("mft_cluster") && v.contains("INVALID")), "{:?}", out);
+    }
+
+    #[test]
+    fn truncated_ntfs() {
+        let data = ntfs_image(4, 2);
+        for len in 0..data.len() {
+            decode(&data[..len]);
+        }
+    }
+
+    #[test]
+    fn truncated_gpt() {
+        let data = gpt_image(92);
+        for len in (0..36 * SECTOR).step_by(7) {
+            decode(&data[..len]);
+        }
+    }
+
+    // FAT16 boot sector at `pos`
+    fn put_fat(data : &mut [u8], pos : usize) {
+        put(data, pos + 11, &512u16.to_le_bytes());
+        put(data, pos + 19, &4u16.to_le_bytes());
+        put(data, pos + 22, &1u16.to_le_bytes());
+        put(data, pos + 43, b"BOOT       ");
+        put(data, pos + 54, b"FAT16   ");
+        put(data, pos + 510, BOOT_SIGNATURE);
+    }
+
+    // ext2 superblock of the filesystem starting at `pos`
+    fn put_ext(data : &mut [u8], pos : usize) {
+        let sb = pos + EXT_SUPERBLOCK;
+        put(data, sb + 4, &8u32.to_le_bytes());
+        put(data, sb + 56, &(EXT_MAGIC as u16).to_le_bytes());
+        put(data, sb + 120, b"root");
+    }
+
+    // FAT partition at sector 2 and an extended partition at sector 4
+    // with one logical ext2 partition at sector 5
+    fn mbr_image() -> Vec<u8> {
+        let mut data = vec![0; 16 * SECTOR];
+        put(&mut data, 446 + 4, &[0x06]);
+        put(&mut data, 446 + 8, &2u32.to_le_bytes());
+        put(&mut data, 446 + 12, &2u32.to_le_bytes());
+        put(&mut data, 462 + 4, &[0x05]);
+        put(&mut data, 462 + 8, &4u32.to_le_bytes());
+        put(&mut data, 462 + 12, &12u32.to_le_bytes());
+        put(&mut data, 510, BOOT_SIGNATURE);
+        put_fat(&mut data, 2 * SECTOR);
+        let ebr = 4 * SECTOR;
+        put(&mut data, ebr + 446 + 4, &[0x83]);
+        put(&mut data, ebr + 446 + 8, &1u32.to_le_bytes());
+        put(&mut data, ebr + 446 + 12, &11u32.to_le_bytes());
+        put(&mut data, ebr + 510, BOOT_SIGNATURE);
+        put_ext(&mut data, 5 * SECTOR);
+        data
+    }
+
+    fn iso_image() -> Vec<u8> {
+        let mut data = vec![0; ISO_PVD + 2048];
+        put(&mut data, ISO_PVD, b"\x01CD001\x01");
+        put(&mut data, ISO_PVD + 40, b"CDROM");
+        put(&mut data, ISO_PVD + 80, &16u32.to_le_bytes());
+        put(&mut data, ISO_PVD + 84, &16u32.to_be_bytes());
+        put(&mut data, ISO_PVD + 128, &2048u16.to_le_bytes());
+        put(&mut data, ISO_PVD + 130, &2048u16.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn mbr_partitions() {
+        let data = mbr_image();
+        assert_eq!(detect(&data).unwrap(), "disk image with an MBR partition table");
+        let overlay = decode(&data).unwrap();
+        assert_eq!(overlay.bases, vec![2 * SECTOR, 5 * SECTOR]);
+        let mut out = vec![];
+        values(&overlay.fields, &mut out);
+        assert!(out.contains(&"FAT boot sector = \"BOOT\", 2048 bytes".to_string()), "{:?}", out);
+        assert!(out.contains(&"ext2 superblock = \"root\", 8192 bytes".to_string()), "{:?}", out);
+        assert!(out.iter().any(|v| v.starts_with("ebr 1")), "{:?}", out);
+    }
+
+    #[test]
+    fn filesystem_images() {
+        let mut data = vec![0; 4 * SECTOR];
+        put_ext(&mut data, 0);
+        assert_eq!(detect(&data).unwrap(), "ext2 filesystem image");
+        let overlay = decode(&iso_image()).unwrap();
+        let mut out = vec![];
+        values(&overlay.fields, &mut out);
+        assert!(out.contains(&"ISO 9660 primary volume descriptor = \"CDROM\", 32768 bytes".to_string()), "{:?}", out);
+    }
+
+    #[test]
+    fn truncated_mbr() {
+        let data = mbr_image();
+        for len in 0..data.len() {
+            decode(&data[..len]);
+        }
+    }
+
+    #[test]
+    fn truncated_iso() {
+        let data = iso_image();
+        for len in ISO_PVD..data.len() {
+            decode(&data[..len]);
+        }
+    }
+}
@@ -2,16 +2,45 @@
 use super::screen::*;
 use super::term::*;
 use super::file::File;
-use super::file_sel::FileSel;
-use std::error::Error;
+use super::view::View;
+use super::file_sel::{FileSel, expand_tilde, complete_path};
+use super::buffer_list::BufferList;
+use super::tree_panel::TreePanel;
+use super::template::Template;
+use super::list_popup::ListPopup;
+use super::analysis::Analysis;
+use super::minimap::{self, MinimapMode};
+use super::formats;
+use super::magic::MagicDb;
+use super::strings::find_strings;
+use super::history::History;
+use super::bookmarks::Bookmarks;
+use super::keymap::{Action, KeyMap};
+use super::config::Config;
+use super::batch::{parse_bytes, parse_number};
+use super::script::Script;
+use super::macros::{save_macro, load_macro};
 use std::io;
+use std::fs;
+use std::io::Read;
+use std::collections::VecDeque;
 use std::ffi::{OsString, OsStr};
+use std::time::{Duration, Instant};
 
 pub const SHORTCUT_SPACING : i32 = 16;
 pub const HEADER_LINES : i32 = 2;
 pub const FOOTER_LINES : i32 = 3;
 pub const BORDER_LINES : i32 = HEADER_LINES + FOOTER_LINES;
 
+// screen column of the first hex digit
+const HEX_COLUMN : i32 = 12;
+const WHEEL_LINES : usize = 3;
+const DOUBLE_CLICK_MS : u64 = 400;
+// smallest number of data lines a split view may get
+const MIN_VIEW_LINES : i32 = 2;
+// limit for repeating a macro until a search fails
+const MAX_MACRO_REPEAT : usize = 100_000;
+
 #[derive(Copy, Clone, PartialEq)]
 enum EditorMode {
     Default,
@@ -21,46 +50,140 @@ enum EditorMode {
 }
 
 #[derive(Copy, Clone, PartialEq)]
-enum EditorPane {
+pub enum EditorPane {
     Hex,
     Text,
 }
 
+// offset relative to the view's base offset, as shown in the address column
+fn offset_text(view : &View, pos : usize) -> String {
+    if pos >= view.base {
+        format!("{:08x}", pos - view.base)
+    } else {
+        format!("-{:07x}", view.base - pos)
+    }
+}
+
+// name under which the bookmarks of a file are stored
+fn bookmark_path(file : &File) -> Option<String> {
+    let path = file.path.as_ref()?;
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.into());
+    Some(path.to_string_lossy().into_owned())
+}
+
 pub struct Editor<'a> {
     pub screen : Screen,
     pub quit : bool,
     pub read_only : bool,
     files : Vec<File>,
-    cur_file : usize,
+    views : Vec<View>,
+    cur_view : usize,
     mode : EditorMode,
-    pane : EditorPane,
     half_byte_edited : bool,
-    stdin : io::StdinLock<'a>,
+    bytes_per_line : usize,
+    byte_colors : bool,
+    min_string_length : usize,
+    minimap : MinimapMode,
+    keymap : KeyMap,
+    magic : MagicDb,
+    config_errors : Vec<String>,
+    last_click : Option<(Instant, i32, i32)>,
+    last_search : Option<Vec<u8>>,
+    clipboard : Vec<u8>,
+    recording : Option<Vec<Key>>,
+    macro_keys : Vec<Key>,
+    replay : VecDeque<Key>,
+    replay_remaining : usize,
+    history : History,
+    bookmarks : Bookmarks,
+    prompt_kill : Vec<char>,
+    input : Box<dyn Read + 'a>,
+    term_fd : i32,
 }
 
 impl<'a> Editor<'a> {
-    pub fn new(stdin : io::StdinLock<'a>) -> Editor<'a> {
+    pub fn new(input : Box<dyn Read + 'a>, term_fd : i32) -> Editor<'a> {
         Editor {
             screen : Screen::new(),
             quit : false,
             read_only : false,
             files : Vec::new(),
-            cur_file : 0,
+            views : vec![View::new(0, EditorPane::Hex)],
+            cur_view : 0,
             mode : EditorMode::Default,
-            pane : EditorPane::Hex,
             half_byte_edited : false,
-            stdin : stdin,
+            bytes_per_line : 16,
+            byte_colors : false,
+            min_string_length : 4,
+            minimap : MinimapMode::Bytes,
+            keymap : KeyMap::new(),
+            magic : MagicDb::new(),
+            config_errors : vec![],
+            last_click : None,
+            last_search : None,
+            clipboard : vec![],
+            recording : None,
+            macro_keys : vec![],
+            replay : VecDeque::new(),
+            replay_remaining : 0,
+            history : History::new(),
+            bookmarks : Bookmarks::new(),
+            prompt_kill : vec![],
+            input,
+            term_fd,
+        }
+    }
+
+    pub fn set_input(&mut self, input : Box<dyn Read + 'a>, term_fd : i32) {
+        self.input = input;
+        self.term_fd = term_fd;
+    }
+
+    pub fn set_config(&mut self, config : Config) {
+        self.bytes_per_line = config.bytes_per_line;
+        for view in &mut self.views {
+            view.pane = config.start_pane;
         }
+        self.read_only = config.read_only;
+        self.screen.theme = config.theme;
+        if let Some(depth) = config.color_depth {
+            self.screen.color_depth = depth;
+        }
+        self.byte_colors = config.byte_colors;
+        self.min_string_length = config.min_string_length;
+        self.minimap = config.minimap;
+        self.keymap = config.keymap;
+        self.magic = config.magic;
+        self.config_errors = config.errors;
+    }
+
+    pub fn set_history(&mut self, history : History) {
+        self.history = history;
+    }
+
+    pub fn set_bookmarks(&mut self, bookmarks : Bookmarks) {
+        self.bookmarks = bookmarks;
     }
 
     pub fn run(&mut self) -> io::Result<()> {
-        let term_fd = 0;
+        let term_fd = self.term_fd;
         let mut orig_term = setup_term(term_fd)?;
-        self.screen.init();
+        self.screen.init(term_fd);
+        if let Some(err) = self.config_errors.first() {
+            let msg = match self.config_errors.len() {
+                1 => err.clone(),
+                n => format!("{} (and {} more config errors)", err, n-1),
+            };
+            self.screen.show_msg(msg);
+        } else {
+            self.offer_decode();
+        }
         show_cursor(false);
+        set_bracketed_paste(true);
+        set_mouse_reporting(true);
         clear_screen();
 
-        if self.files.len() == 0 {
+        if self.files.is_empty() {
             self.add_file(File::new());
         }
         
@@ -75,69 +198,355 @@ impl<'a> Editor<'a> {
         reset_color();
         clear_screen();
         show_cursor(true);
+        set_bracketed_paste(false);
+        set_mouse_reporting(false);
         flush_screen();
         restore_term(term_fd, &mut orig_term)?;
         Ok(())
     }
 
-    pub fn read_key(&mut self) -> u32 {
+    pub fn read_key(&mut self) -> Key {
+        if self.replay.is_empty() && self.replay_remaining > 0 {
+            self.replay_remaining -= 1;
+            self.replay.extend(self.macro_keys.iter().cloned());
+        }
+        if let Some(key) = self.replay.pop_front() {
+            return key;
+        }
+
         //let stdin = io::stdin();
         //let mut reader = stdin.lock();
-        match read_key(&mut self.stdin) {
+        let key = match read_key(&mut self.input) {
+            Ok(Key::Mouse(ev)) => Key::Mouse(self.detect_double_click(ev)),
             Ok(key) => key,
             Err(_) => {
                 self.quit = true;
-                0
+                Key::Unknown
+            }
+        };
+        if let Some(ref mut keys) = self.recording {
+            keys.push(key.clone());
+        }
+        key
+    }
+
+    fn is_replaying(&self) -> bool {
+        ! self.replay.is_empty() || self.replay_remaining > 0
+    }
+
+    fn stop_replay(&mut self) {
+        self.replay.clear();
+        self.replay_remaining = 0;
+    }
+
+    fn toggle_macro_recording(&mut self) {
+        if self.is_replaying() {
+            return;
+        }
+        match self.recording.take() {
+            Some(mut keys) => {
+                // drop the key that stopped the recording
+                keys.pop();
+                self.show_msg(format!("Recorded macro with {} keys", keys.len()));
+                self.macro_keys = keys;
+            }
+            None => {
+                self.recording = Some(vec![]);
+                self.show_msg("Recording macro");
+            }
+        }
+        self.screen.redraw_needed = true;
+    }
+
+    fn run_macro(&mut self, times : usize) {
+        if self.is_replaying() {
+            return;
+        }
+        if self.recording.is_some() {
+            self.show_msg("Can't run a macro while recording");
+        } else if self.macro_keys.is_empty() {
+            self.show_msg("No macro recorded");
+        } else {
+            self.replay_remaining = times;
+        }
+    }
+
+    fn prompt_repeat_macro(&mut self) {
+        if self.is_replaying() {
+            return;
+        }
+        let text = match self.prompt_get_string("Repeat macro how many times (empty: until search fails)", "count") {
+            Some(text) => text,
+            None => return,
+        };
+        if text.trim().is_empty() {
+            self.run_macro(MAX_MACRO_REPEAT);
+            return;
+        }
+        match parse_number(&text) {
+            Ok(n) => self.run_macro(n),
+            Err(e) => self.show_msg(e),
+        }
+    }
+
+    fn prompt_save_macro(&mut self) {
+        if self.is_replaying() {
+            return;
+        }
+        if self.macro_keys.is_empty() {
+            self.show_msg("No macro recorded");
+            return;
+        }
+        if let Some(name) = self.prompt_get_string("Save macro as", "macro") {
+            match save_macro(name.trim(), &self.macro_keys) {
+                Ok(()) => self.show_msg(format!("Macro saved as '{}'", name.trim())),
+                Err(e) => self.show_msg(e),
+            }
+        }
+    }
+
+    fn prompt_load_macro(&mut self) {
+        if self.is_replaying() {
+            return;
+        }
+        if let Some(name) = self.prompt_get_string("Load macro", "macro") {
+            match load_macro(name.trim()) {
+                Ok(keys) => {
+                    self.show_msg(format!("Loaded macro with {} keys", keys.len()));
+                    self.macro_keys = keys;
+                }
+                Err(e) => self.show_msg(e),
+            }
+        }
+    }
+
+    fn detect_double_click(&mut self, ev : MouseEvent) -> MouseEvent {
+        if let MouseAction::Press(button) = ev.action {
+            let now = Instant::now();
+            let is_double = match self.last_click {
+                Some((time, x, y)) => x == ev.x && y == ev.y
+                    && now.duration_since(time) < Duration::from_millis(DOUBLE_CLICK_MS),
+                None => false,
+            };
+            if is_double {
+                self.last_click = None;
+                return MouseEvent { action : MouseAction::DoubleClick(button), ..ev };
             }
+            self.last_click = Some((now, ev.x, ev.y));
         }
+        ev
     }
     
+    pub fn files(&self) -> &[File] {
+        &self.files
+    }
+
+    pub fn files_mut(&mut self) -> &mut [File] {
+        &mut self.files
+    }
+
     pub fn cur_file_mut(&mut self) -> Option<&mut File> {
-        self.files.get_mut(self.cur_file)
+        let index = self.view().file;
+        self.files.get_mut(index)
     }
 
     pub fn cur_file(&self) -> Option<&File> {
-        self.files.get(self.cur_file)
+        self.files.get(self.view().file)
+    }
+
+    fn view(&self) -> &View {
+        &self.views[self.cur_view]
+    }
+
+    fn view_mut(&mut self) -> &mut View {
+        &mut self.views[self.cur_view]
+    }
+
+    // length of the buffer shown in view `index`
+    fn view_len(&self, index : usize) -> usize {
+        self.files.get(self.views[index].file).map_or(0, |f| f.data.len())
+    }
+
+    fn cur_len(&self) -> usize {
+        self.view_len(self.cur_view)
+    }
+
+    pub fn cursor_pos(&self) -> usize {
+        self.view().cursor_pos
+    }
+
+    pub fn set_selection(&mut self, anchor : Option<usize>) {
+        self.view_mut().sel_anchor = anchor;
+        self.screen.redraw_needed = true;
+    }
+
+    // keeps every view inside its buffer after data was inserted or deleted
+    pub fn clamp_views(&mut self) {
+        for index in 0..self.views.len() {
+            let len = self.view_len(index);
+            self.views[index].clamp(len);
+            self.ensure_view_cursor_visible(index, 0);
+        }
+        self.screen.redraw_needed = true;
     }
     
-    pub fn add_file(&mut self, file : File) {
-        if self.files.len() == 1 && self.files[0].data.len() == 0 {
+    pub fn add_file(&mut self, mut file : File) {
+        if self.files.len() == 1 && self.files[0].data.is_empty()
+            && self.files[0].path.is_none() && ! self.files[0].modified {
             // remove initial empty file
             self.files.pop();
         }
+        file.file_type = self.magic.detect(&file.data);
+        if let Some(path) = bookmark_path(&file) {
+            file.bookmarks = self.bookmarks.find(&path, file.hash);
+        }
         self.files.push(file);
-        self.cur_file = self.files.len() - 1;
-        //self.files[self.cur_file].top_line = 1;
+        let pane = self.view().pane;
+        *self.view_mut() = View::new(self.files.len() - 1, pane);
+        self.half_byte_edited = false;
+        self.screen.redraw_needed = true;
+        self.offer_decode();
+    }
+
+    // tells the user when the current buffer is in a format we can decode
+    fn offer_decode(&mut self) {
+        let (desc, action) = match self.cur_file() {
+            Some(file) => match file.file_type {
+                Some(ref ty) => match ty.template {
+                    Some(ref name) => (ty.description.clone(), format!("applies template {}", name)),
+                    None if formats::detect(&file.data).is_some() => (ty.description.clone(), "decodes its structure".to_string()),
+                    None => return,
+                },
+                None => return,
+            },
+            None => return,
+        };
+        match self.keymap.key_for(Action::DecodeFormat).and_then(key_name) {
+            Some(key) => self.show_msg(format!("{}: {} {}", desc, key, action)),
+            None => self.show_msg(desc),
+        }
     }
 
+    // views that showed the removed buffer move on to the next one
     pub fn remove_cur_file(&mut self) {
-        if self.cur_file < self.files.len() {
-            self.files.remove(self.cur_file);
-            if self.cur_file > 0 {
-                if self.cur_file > self.files.len() {
-                    self.cur_file -= 1
-                } else {
-                    self.cur_file = 0;
+        let index = self.view().file;
+        if index < self.files.len() {
+            self.files.remove(index);
+            let n_files = self.files.len();
+            for view in &mut self.views {
+                if view.file > index || (view.file == index && index >= n_files && index > 0) {
+                    view.file -= 1;
                 }
             }
+            self.clamp_views();
+        }
+    }
+
+    pub fn cur_file_index(&self) -> usize {
+        self.view().file
+    }
+
+    // shows buffer `index` in the current view, keeping the offset so the
+    // same region of similar files can be compared
+    pub fn set_cur_file(&mut self, index : usize) {
+        if index < self.files.len() {
+            self.view_mut().file = index;
+            self.view_mut().sel_anchor = None;
+            self.view_mut().base = 0;
+            let len = self.cur_len();
+            self.view_mut().clamp(len);
+            self.ensure_cursor_visible(0);
+            self.half_byte_edited = false;
+            self.screen.redraw_needed = true;
+        }
+    }
+
+    fn close_cur_file(&mut self) {
+        let modified = self.cur_file().is_some_and(|f| f.modified);
+        if modified {
+            match self.prompt_get_yes_no("Buffer modified, close anyway (changes will be lost)? ") {
+                Some(true) => (),
+                _ => return,
+            }
+        }
+        self.remove_cur_file();
+        if self.files.is_empty() {
+            self.add_file(File::new());
         }
+        self.half_byte_edited = false;
+        self.screen.redraw_needed = true;
     }
 
     pub fn go_to_next_file(&mut self) {
-        if self.cur_file+1 < self.files.len() {
-            self.cur_file += 1;
-        } else if self.files.len() > 0 {
-            self.cur_file = (self.cur_file + 1) % self.files.len();
+        if ! self.files.is_empty() {
+            let index = (self.cur_file_index() + 1) % self.files.len();
+            self.set_cur_file(index);
         }
     }
 
     pub fn go_to_prev_file(&mut self) {
-        if self.cur_file > 0 {
-            self.cur_file -= 1;
-        } else if self.files.len() > 0 {
-            self.cur_file = (self.cur_file + self.files.len() - 1) % self.files.len();
+        if ! self.files.is_empty() {
+            let index = (self.cur_file_index() + self.files.len() - 1) % self.files.len();
+            self.set_cur_file(index);
         }
     }
+
+    // the new view starts as a copy of the current one and goes below it
+    fn split_view(&mut self) {
+        let n_lines = (self.screen.h - BORDER_LINES) / (self.views.len() as i32 + 1);
+        if n_lines < MIN_VIEW_LINES + 1 {
+            self.show_msg("Not enough room to split the view");
+            return;
+        }
+        let view = *self.view();
+        self.views.insert(self.cur_view + 1, view);
+        self.cur_view += 1;
+        self.clamp_views();
+    }
+
+    fn close_view(&mut self) {
+        if self.views.len() == 1 {
+            self.show_msg("Can't close the only view");
+            return;
+        }
+        self.views.remove(self.cur_view);
+        if self.cur_view >= self.views.len() {
+            self.cur_view = self.views.len() - 1;
+        }
+        self.clamp_views();
+    }
+
+    fn go_to_next_view(&mut self) {
+        self.cur_view = (self.cur_view + 1) % self.views.len();
+        self.screen.redraw_needed = true;
+    }
+
+    // screen lines of view `index` as (first data line, number of data
+    // lines); when the screen is split each view has a title line on top
+    fn view_rows(&self, index : usize) -> (i32, usize) {
+        let avail = self.screen.h - BORDER_LINES;
+        let n_views = self.views.len() as i32;
+        if n_views == 1 {
+            return (HEADER_LINES + 1, avail.max(0) as usize);
+        }
+        let n_lines = avail / n_views;
+        let first = HEADER_LINES + 1 + n_lines * index as i32;
+        let n_lines = if index as i32 == n_views - 1 { avail - n_lines * (n_views - 1) } else { n_lines };
+        (first + 1, (n_lines - 1).max(0) as usize)
+    }
+
+    fn page_lines(&self) -> usize {
+        self.view_rows(self.cur_view).1.max(1)
+    }
+
+    // the view showing screen line `y`, including its title line
+    fn view_at(&self, y : i32) -> Option<usize> {
+        let title_lines = if self.views.len() > 1 { 1 } else { 0 };
+        (0..self.views.len()).find(|&index| {
+            let (first, n_lines) = self.view_rows(index);
+            y >= first - title_lines && y < first + n_lines as i32
+        })
+    }
     
     pub fn show_msg<S>(&mut self, msg : S) where S: Into<String> {
         self.screen.show_msg(msg);
@@ -149,13 +558,16 @@ impl<'a> Editor<'a> {
 
     fn draw_header(&mut self) {
         self.screen.move_cursor(1, 1);
-        set_color(Color::FGBlack, Color::BGGray);
+        set_color_pair(self.screen.theme.header);
         print!(" ");
-        if let Some(file) = self.cur_file() {
-            match file.filename {
-                Some(ref filename) => print!("{}", filename),
-                None => print!("NO FILE"),
-            }
+        clear_eol();
+        self.draw_tabs();
+        set_color_pair(self.screen.theme.header);
+        if self.recording.is_some() {
+            print!(" [recording]");
+        }
+        if self.view().base != 0 {
+            print!(" [base 0x{:x}]", self.view().base);
         }
         clear_eol();
         self.screen.move_cursor(self.screen.w - 11, 1);
@@ -164,12 +576,46 @@ impl<'a> Editor<'a> {
 
         self.screen.move_cursor(1, HEADER_LINES);
         reset_color();
+        if let Some(ty) = self.cur_file().and_then(|f| f.file_type.as_ref()) {
+            let w = self.screen.w.max(1) as usize - 1;
+            print!(" {}", ty.description.chars().take(w).collect::<String>());
+        }
         clear_eol();
     }
 
+    // one tab per buffer, scrolled so the current one is visible
+    fn draw_tabs(&mut self) {
+        let cur_file = self.cur_file_index();
+        let labels : Vec<String> = self.files.iter().enumerate()
+            .map(|(i, file)| format!(" {}:{}{} ", i+1, file.short_name(), if file.modified { "*" } else { "" }))
+            .collect();
+        let widths : Vec<usize> = labels.iter().map(|l| l.chars().count()).collect();
+        let avail = (self.screen.w as usize).saturating_sub(28);
+
+        let mut first = 0;
+        while first < cur_file && widths[first..cur_file+1].iter().sum::<usize>() + 2 > avail {
+            first += 1;
+        }
+        if first > 0 {
+            set_color_pair(self.screen.theme.header);
+            print!("<");
+        }
+        let mut used = 0;
+        for (i, label) in labels.iter().enumerate().skip(first) {
+            if used + widths[i] + 2 > avail && i > cur_file {
+                set_color_pair(self.screen.theme.header);
+                print!(">");
+                break;
+            }
+            set_color_pair(if i == cur_file { self.screen.theme.tab_active } else { self.screen.theme.tab });
+            print!("{}", label);
+            used += widths[i];
+        }
+    }
+
     pub fn draw_key_help(&mut self, x : i32, y : i32, key : &str, help : &str) {
         self.screen.move_cursor(x, y);
-        set_color(Color::FGBlack, Color::BGGray);
+        set_color_pair(self.screen.theme.key_help);
         print!("{}", key);
         reset_color();
         print!(" {}", help);
@@ -184,209 +630,303 @@ impl<'a> Editor<'a> {
         clear_eol();
     }
     
+    // key help of the current mode, one column of two rows per entry;
+    // actions without a key binding are left out
+    fn footer_keys(&self) -> Vec<[Option<(String, &'static str)>; 2]> {
+        let k = |key : &str, help| Some((key.to_string(), help));
+        let a = |action, help| self.keymap.key_for(action).and_then(key_label).map(|key| (key, help));
+        match self.mode {
+            EditorMode::Default => vec![
+                [a(Action::Help, "Get Help"), a(Action::Quit, "Exit")],
+                [a(Action::WriteFile, "Write File"), a(Action::ReadFile, "Read File")],
+                [a(Action::Find, "Where Is"), a(Action::GoTo, "Go To")],
+                [a(Action::Copy, "Copy"), a(Action::Paste, "Paste")],
+                [a(Action::Command, "Command"), None],
+            ],
+            EditorMode::ReadFilename => vec![
+                [k("^T", "To Files"), k("^C", "Cancel")],
+                [k("Tab", "Complete"), k("Up", "History")],
+            ],
+            EditorMode::ReadString => vec![
+                [None, k("^C", "Cancel")],
+                [k("Up", "History"), None],
+            ],
+            EditorMode::ReadYesNo => vec![
+                [k(" Y", "Yes"), k(" N", "No")],
+                [None, k("^C", "Cancel")],
+            ],
+        }
+    }
+
     fn draw_footer(&mut self) {
         reset_color();
         let w = SHORTCUT_SPACING;
         let h = self.screen.h;
 
-        // key shortcuts
-        match self.mode {
-            EditorMode::Default => {
-                self.draw_key_help(1 + 0*w, h-1, "^G", "Get Help");
-                self.draw_key_help(1 + 0*w, h-0, "^X", "Exit");
-                
-                self.draw_key_help(1 + 1*w, h-1, "^O", "Write File");
-                self.draw_key_help(1 + 1*w, h-0, "^R", "Read File");
-
-                self.void_key_help(1 + 2*w, h-1);
-                self.void_key_help(1 + 2*w, h-0);
-            },
-
-            EditorMode::ReadFilename => {
-                self.draw_key_help(1 + 0*w, h-1, "^T", "To Files");
-                self.draw_key_help(1 + 0*w, h-0, "^C", "Cancel");
-
-                self.void_key_help(1 + 1*w, h-1);
-                self.void_key_help(1 + 1*w, h-0);
-            },
-
-            EditorMode::ReadString => {
-                self.void_key_help(1 + 0*w, h-1);
-                self.draw_key_help(1 + 0*w, h-0, "^C",  "Cancel");
-
-                self.void_key_help(1 + 1*w, h-1);
-                self.void_key_help(1 + 1*w, h-0);
-            },
-            
-            EditorMode::ReadYesNo => {
-                self.draw_key_help(1 + 0*w, h-1, " Y", "Yes");
-                self.draw_key_help(1 + 0*w, h-0, " N", "No");
-
-                self.void_key_help(1 + 1*w, h-1);
-                self.draw_key_help(1 + 1*w, h-0, "^C",  "Cancel");
-
-                self.void_key_help(1 + 2*w, h-1);
-                self.void_key_help(1 + 2*w, h-0);
+        // key shortcuts, clearing whatever another mode drew further right
+        let keys = self.footer_keys();
+        for (i, column) in keys.iter().enumerate() {
+            let x = 1 + i as i32 * w;
+            for (y, cell) in [h - 1, h].iter().zip(column) {
+                match *cell {
+                    Some((ref key, help)) => self.draw_key_help(x, *y, key, help),
+                    None => self.void_key_help(x, *y),
+                }
             }
         }
+        let x = 1 + keys.len() as i32 * w;
+        self.void_key_help(x, h - 1);
+        self.void_key_help(x, h);
 
         // message
         self.screen.move_cursor(1, self.screen.h - FOOTER_LINES + 1);
         if let Some(ref msg) = self.screen.msg {
-            set_color(Color::FGBlack, Color::BGGray);
+            set_color_pair(self.screen.theme.message);
             print!(" {}", msg);
+        } else if let Some(names) = self.bookmarks_at(self.cursor_pos()) {
+            print!(" Bookmark: {}", names);
+        } else if let Some(info) = self.cur_file().and_then(|f| f.overlay.as_ref()).and_then(|o| o.describe(self.cursor_pos())) {
+            // the structure field under the cursor
+            let info : String = info.chars().take((self.screen.w - 2).max(0) as usize).collect();
+            print!(" {}", info);
         }
         clear_eol();
     }
+
+    fn bookmarks_at(&self, pos : usize) -> Option<String> {
+        let file = self.cur_file()?;
+        let names : Vec<&str> = file.bookmarks.iter().filter(|b| b.1 == pos).map(|b| b.0.as_str()).collect();
+        if ! names.is_empty() { Some(names.join(", ")) } else { None }
+    }
+
+    // number of digits of the virtual address shown after the offset,
+    // 0 if the file has no memory layout
+    fn addr_digits(&self, file : &File) -> usize {
+        match file.overlay.as_ref().and_then(|o| o.max_addr()) {
+            Some(max) => format!("{:x}", max.saturating_sub(1)).len().max(8),
+            None => 0,
+        }
+    }
+
+    // screen column of the hex digits of the byte at index `i` in a line
+    fn hex_column(&self, file : &File, i : usize) -> i32 {
+        let digits = self.addr_digits(file);
+        let addr_width = if digits > 0 { digits + 1 } else { 0 };
+        HEX_COLUMN + (addr_width + 3*i + i/8) as i32
+    }
+
+    fn text_column(&self, file : &File) -> i32 {
+        self.hex_column(file, self.bytes_per_line) + 1
+    }
+
+    // colors for the byte at `pos` when drawn in `pane`, None for the default colors
+    fn byte_colors(&self, view : &View, active : bool, file : &File, pos : usize, pane : EditorPane) -> Option<ColorPair> {
+        let theme = &self.screen.theme;
+        if view.cursor_pos == pos {
+            Some(if active && pane == EditorPane::Hex && self.half_byte_edited { theme.cursor_half_byte }
+                 else if active && view.pane == pane { theme.cursor }
+                 else { theme.cursor_inactive })
+        } else if view.is_selected(pos) {
+            Some(theme.selection)
+        } else if let Some(leaf) = file.overlay.as_ref().and_then(|o| o.leaf_at(pos)) {
+            // alternate colours so that neighbouring fields can be told apart
+            Some(if leaf % 2 == 0 { theme.field } else { theme.field_alt })
+        } else if self.byte_colors {
+            Some(theme.byte_color(file.data[pos]))
+        } else {
+            None
+        }
+    }
     
-    fn draw_main_screen(&mut self) {
+    pub fn draw_main_screen(&mut self) {
         self.draw_header();
         self.draw_footer();
+        for index in 0..self.views.len() {
+            self.draw_view(index);
+        }
+        flush_screen();
+        self.screen.redraw_needed = false;
+    }
+
+    fn draw_view_title(&self, index : usize, line : i32) {
+        let view = &self.views[index];
+        let theme = &self.screen.theme;
+        self.screen.move_cursor(1, line);
+        set_color_pair(if index == self.cur_view { theme.tab_active } else { theme.tab });
+        let name = self.files.get(view.file).map_or(String::new(), |f| f.short_name());
+        print!(" {}: {} at {}", index + 1, name, offset_text(view, view.cursor_pos));
+        clear_eol();
+    }
+
+    fn draw_view(&self, index : usize) {
+        let view = self.views[index];
+        let active = index == self.cur_view;
+        let (first_line, n_lines) = self.view_rows(index);
+        let end_line = first_line + n_lines as i32;
+        if self.views.len() > 1 {
+            self.draw_view_title(index, first_line - 1);
+        }
 
-        let mut line = HEADER_LINES + 1;
+        let mut line = first_line;
 
-        if let Some(file) = self.cur_file() {
-            let mut off = 16 * file.top_line;
-            while off < file.data.len() && line <= self.screen.h - FOOTER_LINES {
+        let bpl = self.bytes_per_line;
+        if let Some(file) = self.files.get(view.file) {
+            let addr_digits = self.addr_digits(file);
+            let mut off = bpl * view.top_line;
+            while off < file.data.len() && line < end_line {
                 self.screen.move_cursor(1, line);
                 reset_color();
-                print!("{:08x} | ", off);
+                print!("{}", offset_text(&view, off));
+                // bookmarked lines are marked after the offset
+                if file.bookmarks.iter().any(|b| b.1 >= off && b.1 < off + bpl) {
+                    set_color_pair(self.screen.theme.key_help);
+                    print!("*");
+                    reset_color();
+                } else {
+                    print!(" ");
+                }
+                if addr_digits > 0 {
+                    // virtual address, blank where the line is not mapped
+                    match file.overlay.as_ref().and_then(|o| o.addr_of(off)) {
+                        Some(addr) => print!("{:01$x} ", addr, addr_digits),
+                        None => print!("{:1$} ", "", addr_digits),
+                    }
+                }
+                print!("| ");
 
-                set_bold(self.pane == EditorPane::Hex && ! self.read_only);
-                let line_len = if file.data.len() - off < 16 { file.data.len() - off } else { 16 };
+                set_bold(view.pane == EditorPane::Hex && ! self.read_only);
+                let line_len = if file.data.len() - off < bpl { file.data.len() - off } else { bpl };
                 for i in 0..line_len {
-                    if i == 8 { print!(" "); }
-                    if file.cursor_pos == off+i {
-                        self.screen.move_cursor((11 + 3*i + if i>=8 { 1 } else { 0 }) as i32, line);
-                        set_color(Color::FGBlack,
-                                  if self.half_byte_edited { Color::BGYellow }
-                                  else if self.pane == EditorPane::Hex { Color::BGGreen }
-                                  else { Color::BGGray });
+                    if i > 0 && i % 8 == 0 { print!(" "); }
+                    let colors = self.byte_colors(&view, active, file, off+i, EditorPane::Hex);
+                    if view.cursor_pos == off+i {
+                        self.screen.move_cursor(self.hex_column(file, i) - 1, line);
+                        set_color_pair(colors.unwrap());
                         set_bold(false);
                         print!(" ");
+                    } else if let Some(colors) = colors {
+                        set_color_pair(colors);
                     }
                     print!("{:02x} ", file.data[off+i]);
-                    if file.cursor_pos == off+i {
+                    if colors.is_some() {
                         reset_color();
-                        set_bold(self.pane == EditorPane::Hex && ! self.read_only);
+                        set_bold(view.pane == EditorPane::Hex && ! self.read_only);
                     }
                 }
-                for i in line_len..16 {
-                    if i == 8 { print!(" "); }
+                for i in line_len..bpl {
+                    if i > 0 && i % 8 == 0 { print!(" "); }
                     print!("   ");
                 }
                 print!("| ");
                 
-                set_bold(self.pane == EditorPane::Text && ! self.read_only);
+                set_bold(view.pane == EditorPane::Text && ! self.read_only);
                 for i in 0..line_len {
                     let b = file.data[off+i];
-                    if file.cursor_pos == off+i {
-                        set_color(Color::FGBlack, if self.pane == EditorPane::Text { Color::BGGreen } else { Color::BGGray });
-                        set_bold(false);
+                    let colors = self.byte_colors(&view, active, file, off+i, EditorPane::Text);
+                    if let Some(colors) = colors {
+                        set_color_pair(colors);
+                        if view.cursor_pos == off+i {
+                            set_bold(false);
+                        }
                     }
-                    print!("{}", if b >= 32 && b < 127 { b } else { b'.' } as char);
-                    if file.cursor_pos == off+i {
+                    print!("{}", if (32..127).contains(&b) { b } else { b'.' } as char);
+                    if colors.is_some() {
                         reset_color();
-                        set_bold(self.pane == EditorPane::Text && ! self.read_only);
+                        set_bold(view.pane == EditorPane::Text && ! self.read_only);
                     }
                 }
                 clear_eol();
 
                 line += 1;
-                off += 16;
+                off += bpl;
             }
         }
         
         reset_color();
-        for i in line .. self.screen.h - FOOTER_LINES + 1 {
+        for i in line .. end_line {
             self.screen.move_cursor(1, i);
-            //print!("{}", i);
             clear_eol();
         }
-        
-        flush_screen();
-        self.screen.redraw_needed = false;
+        self.draw_minimap(index);
+    }
+
+    fn minimap_shown(&self, file : &File) -> bool {
+        self.minimap != MinimapMode::Off && self.screen.w > self.text_column(file) + self.bytes_per_line as i32
+    }
+
+    // the whole file squeezed in a column at the right of the view, with
+    // the lines in the view drawn wider
+    fn draw_minimap(&self, index : usize) {
+        let view = &self.views[index];
+        let file = match self.files.get(view.file) {
+            Some(file) if self.minimap_shown(file) => file,
+            _ => return,
+        };
+        let (first_line, n_lines) = self.view_rows(index);
+        let bpl = self.bytes_per_line;
+        let total = file.data.len().div_ceil(bpl);
+        for row in 0..n_lines {
+            let (start, end) = minimap::row_lines(total, n_lines, row);
+            if start >= end {
+                break;
+            }
+            self.screen.move_cursor(self.screen.w, first_line + row as i32);
+            match minimap::row_color(self.minimap, &self.screen.theme, file, start * bpl, (end * bpl).min(file.data.len())) {
+                Some(colors) => set_color_pair(colors),
+                None => reset_color(),
+            }
+            let in_view = start < view.top_line + n_lines && end > view.top_line;
+            print!("{}", if in_view { '█' } else { '▐' });
+        }
+        reset_color();
+    }
+
+    // the view and file offset of a (1-based) screen position in a minimap
+    fn minimap_pos_to_offset(&self, x : i32, y : i32) -> Option<(usize, usize)> {
+        let index = self.view_at(y)?;
+        let file = self.files.get(self.views[index].file)?;
+        let (first_line, n_lines) = self.view_rows(index);
+        if x != self.screen.w || y < first_line || ! self.minimap_shown(file) {
+            return None;
+        }
+        let total = file.data.len().div_ceil(self.bytes_per_line);
+        let (start, end) = minimap::row_lines(total, n_lines, (y - first_line) as usize);
+        if start >= end {
+            return None;
+        }
+        Some((index, start * self.bytes_per_line))
     }
 
     fn process_input(&mut self) {
         let key = self.read_key();
         self.screen.msg_was_set = false;
-        if key == ctrl_key!('x') {
-            self.quit = true;
-            return;
-        } else if key == ctrl_key!('l') {
-            clear_screen();
-            self.screen.redraw_needed = true;
-        } else if key == 9 {
-            self.pane = match self.pane {
-                EditorPane::Hex => EditorPane::Text,
-                EditorPane::Text => EditorPane::Hex,
-            };
-        } else if key == ctrl_key!('g') {
-            self.show_msg("Help is not available just yet");
-        } else if key == ctrl_key!('o') {
-            self.show_msg("Writing files not implemented");
-        } else if key == ctrl_key!('r') {
-            self.prompt_read_file();
-        } else if key == ctrl_key!('a') || key == KEY_HOME {
-            self.move_cursor_home();
-        } else if key == ctrl_key!('e') || key == KEY_END {
-            self.move_cursor_end();
-        } else if key == KEY_ARROW_UP {
-            self.move_cursor_up();
-        } else if key == KEY_ARROW_DOWN {
-            self.move_cursor_down();
-        } else if key == KEY_ARROW_LEFT || key == 127 || key == 8 {
-            self.move_cursor_left();
-        } else if key == KEY_ARROW_RIGHT {
-            self.move_cursor_right();
-        } else if key == KEY_CTRL_HOME {
-            self.move_cursor_start_of_file();
-        } else if key == KEY_CTRL_END {
-            self.move_cursor_end_of_file();
-        } else if key == KEY_PAGE_UP {
-            self.move_cursor_page_up();
-        } else if key == KEY_PAGE_DOWN {
-            self.move_cursor_page_down();
-        } else if key == alt_key!(',') {
-            self.go_to_prev_file();
-        } else if key == alt_key!('.') {
-            self.go_to_next_file();
-        }
-
-
-        if ! self.read_only {
-            if key >= 32 && key < 127 && self.pane == EditorPane::Text {
-                if let Some(file) = self.cur_file_mut() {
-                    if file.cursor_pos < file.data.len() {
-                        file.data[file.cursor_pos] = key as u8;
-                    }
+        if let Some(action) = self.keymap.lookup(&key) {
+            self.run_action(action);
+        } else if let Key::Mouse(ev) = key {
+            self.process_mouse(ev);
+        } else if ! self.read_only {
+            match key {
+                Key::Char(c) if self.view().pane == EditorPane::Text => {
+                    let mut buf = [0; 4];
+                    self.write_bytes(c.encode_utf8(&mut buf).as_bytes());
                 }
-                self.move_cursor_right();
-                self.half_byte_edited = false;
-            } else if is_hex_digit(key) && self.pane == EditorPane::Hex {
-                let mut half_byte_edited = self.half_byte_edited;
-                if let Some(file) = self.cur_file_mut() {
-                    if file.cursor_pos < file.data.len() {
-                        if ! half_byte_edited {
-                            file.data[file.cursor_pos] &= 0x0f;
-                            file.data[file.cursor_pos] |= to_hex_val(key) << 4;
-                            half_byte_edited = true;
-                        } else {
-                            file.data[file.cursor_pos] &= 0xf0;
-                            file.data[file.cursor_pos] |= to_hex_val(key);
-                            half_byte_edited = false;
+                Key::Char(c) if self.view().pane == EditorPane::Hex && c.is_ascii_hexdigit() => {
+                    self.write_hex_digit(to_hex_val(c));
+                }
+                Key::Paste(ref data) => {
+                    if self.view().pane == EditorPane::Text {
+                        self.write_bytes(data);
+                    } else {
+                        let digits : Vec<u8> = data.iter()
+                            .filter(|b| b.is_ascii_hexdigit())
+                            .map(|&b| to_hex_val(b as char))
+                            .collect();
+                        for d in digits {
+                            self.write_hex_digit(d);
                         }
                     }
                 }
-                self.half_byte_edited = half_byte_edited;
-                if self.half_byte_edited {
-                    self.screen.redraw_needed = true;
-                } else {
-                    self.move_cursor_right();
-                }
-            } else {
-                self.half_byte_edited = false;
+                _ => self.half_byte_edited = false,
             }
         }
         
@@ -395,188 +935,776 @@ impl<'a> Editor<'a> {
         }
     }
 
-    fn ensure_cursor_visible(&mut self, visible_len_after : usize) {
-        let n_page_lines = (self.screen.h - BORDER_LINES) as usize;
-        if let Some(file) = self.cur_file_mut() {
-            let last_line = file.data.len() / 16 + if file.data.len() % 16 != 0 { 1 } else { 0 };
-
-            if ! (file.cursor_pos / 16 >= file.top_line
-                  && (file.cursor_pos+visible_len_after) / 16 >= file.top_line
-                  && file.cursor_pos / 16 < file.top_line + n_page_lines
-                  && (file.cursor_pos+visible_len_after) / 16 < file.top_line + n_page_lines) {
-                if file.cursor_pos / 16 < n_page_lines/2 {
-                    file.top_line = 0;
+    pub fn run_action(&mut self, action : Action) {
+        match action {
+            Action::Quit => self.quit = true,
+            Action::Redraw => {
+                clear_screen();
+                self.screen.redraw_needed = true;
+            }
+            Action::SwitchPane => {
+                let view = self.view_mut();
+                view.pane = match view.pane {
+                    EditorPane::Hex => EditorPane::Text,
+                    EditorPane::Text => EditorPane::Hex,
+                };
+                self.screen.redraw_needed = true;
+            }
+            Action::Help => self.show_msg("Help is not available just yet"),
+            Action::WriteFile => self.write_file(),
+            Action::ReadFile => { self.prompt_read_file(); }
+            Action::LineStart => self.move_cursor_home(),
+            Action::LineEnd => self.move_cursor_end(),
+            Action::Up => self.move_cursor_up(),
+            Action::Down => self.move_cursor_down(),
+            Action::Left => self.move_cursor_left(),
+            Action::Right => self.move_cursor_right(),
+            Action::FileStart => self.move_cursor_start_of_file(),
+            Action::FileEnd => self.move_cursor_end_of_file(),
+            Action::PageUp => self.move_cursor_page_up(),
+            Action::PageDown => self.move_cursor_page_down(),
+            Action::PrevFile => self.go_to_prev_file(),
+            Action::NextFile => self.go_to_next_file(),
+            Action::ClearSelection => self.clear_selection(),
+            Action::Find => self.prompt_find(),
+            Action::FindNext => self.repeat_find(),
+            Action::GoTo => self.prompt_go_to(),
+            Action::Copy => {
+                if self.copy_selection() {
+                    self.show_msg("Selection copied");
                 } else {
-                    file.top_line = file.cursor_pos / 16 - n_page_lines/2;
-                    if file.top_line + n_page_lines > last_line {
-                        file.top_line = last_line - n_page_lines;
-                    }
+                    self.show_msg("Nothing selected");
                 }
             }
-        }
-    }
-    
-    fn move_cursor_start_of_file(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            file.cursor_pos = 0;
-            file.top_line = 0;
-        }
-        self.screen.redraw_needed = true;
-    }
-
-    fn move_cursor_end_of_file(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            if file.data.len() > 0 {
-                file.cursor_pos = file.data.len() - 1;
-            } else {
-                file.cursor_pos = 0;
+            Action::Paste => {
+                if ! self.read_only {
+                    self.paste_clipboard();
+                }
+            }
+            Action::Command => self.prompt_command(),
+            Action::BufferList => {
+                if let Some(index) = BufferList::new(self).select_buffer() {
+                    self.set_cur_file(index);
+                }
+            }
+            Action::CloseFile => self.close_cur_file(),
+            Action::SplitView => self.split_view(),
+            Action::CloseView => self.close_view(),
+            Action::NextView => self.go_to_next_view(),
+            Action::ApplyTemplate => self.prompt_apply_template(),
+            Action::StructureTree => {
+                if self.cur_file().is_some_and(|f| f.overlay.as_ref().is_some_and(|o| ! o.fields.is_empty())) {
+                    TreePanel::new(self).show();
+                } else {
+                    self.show_msg("No structure overlay, apply a template first");
+                }
+            }
+            Action::DecodeFormat => self.decode_format(),
+            Action::GoToLocation => self.choose_location(),
+            Action::SetBase => self.toggle_base(),
+            Action::Strings => self.choose_string(),
+            Action::Analysis => self.show_analysis(),
+            Action::SetBookmark => self.prompt_set_bookmark(),
+            Action::Bookmarks => self.choose_bookmark(),
+            Action::Minimap => {
+                self.minimap = self.minimap.next();
+                self.show_msg(format!("Minimap: {}", self.minimap.name()));
+                self.screen.redraw_needed = true;
+            }
+            Action::RecordMacro => self.toggle_macro_recording(),
+            Action::RunMacro => self.run_macro(1),
+            Action::RepeatMacro => self.prompt_repeat_macro(),
+            Action::SaveMacro => self.prompt_save_macro(),
+            Action::LoadMacro => self.prompt_load_macro(),
+            Action::ToggleByteColors => {
+                self.byte_colors = ! self.byte_colors;
+                self.screen.redraw_needed = true;
             }
         }
-        self.ensure_cursor_visible(1);
-        self.screen.redraw_needed = true;
-    }
-    
-    fn move_cursor_home(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            file.cursor_pos = file.cursor_pos / 16 * 16;
-        }
-        self.screen.redraw_needed = true;
+        self.half_byte_edited = false;
     }
 
-    fn move_cursor_end(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            file.cursor_pos = file.cursor_pos / 16 * 16 + 15;
-            if file.data.len() > 0 && file.cursor_pos >= file.data.len() {
-                file.cursor_pos = file.data.len()-1;
+    fn process_mouse(&mut self, ev : MouseEvent) {
+        if let MouseAction::Press(MouseButton::Left) | MouseAction::Drag(MouseButton::Left) = ev.action {
+            if let Some((index, pos)) = self.minimap_pos_to_offset(ev.x, ev.y) {
+                self.cur_view = index;
+                self.set_cursor_pos(pos);
+                return;
             }
         }
-        self.screen.redraw_needed = true;
+        match ev.action {
+            MouseAction::WheelUp => {
+                let index = self.view_at(ev.y).unwrap_or(self.cur_view);
+                self.scroll_up(index, WHEEL_LINES);
+            }
+            MouseAction::WheelDown => {
+                let index = self.view_at(ev.y).unwrap_or(self.cur_view);
+                self.scroll_down(index, WHEEL_LINES);
+            }
+            MouseAction::Press(MouseButton::Left) | MouseAction::DoubleClick(MouseButton::Left) => {
+                if let Some(index) = self.view_at(ev.y) {
+                    // clicking a view makes it the current one
+                    if index != self.cur_view {
+                        self.cur_view = index;
+                        self.half_byte_edited = false;
+                        self.screen.redraw_needed = true;
+                    }
+                }
+                if let Some((pos, pane)) = self.screen_pos_to_offset(ev.x, ev.y) {
+                    self.half_byte_edited = false;
+                    let view = self.view_mut();
+                    view.pane = pane;
+                    view.cursor_pos = pos;
+                    view.sel_anchor = None;
+                    self.screen.redraw_needed = true;
+                }
+            }
+            MouseAction::Drag(MouseButton::Left) => {
+                if let Some((pos, _)) = self.screen_pos_to_offset(ev.x, ev.y) {
+                    let view = self.view_mut();
+                    if view.sel_anchor.is_none() {
+                        view.sel_anchor = Some(view.cursor_pos);
+                    }
+                    view.cursor_pos = pos;
+                    self.screen.redraw_needed = true;
+                }
+            }
+            _ => (),
+        }
     }
-    
-    fn move_cursor_page_up(&mut self) {
-        let n_page_lines = (self.screen.h - BORDER_LINES) as usize;
+
+    // convert a (1-based) screen position in the current view to a file
+    // offset and the pane it's in
+    fn screen_pos_to_offset(&self, x : i32, y : i32) -> Option<(usize, EditorPane)> {
+        let (first_line, n_lines) = self.view_rows(self.cur_view);
+        if y < first_line || y >= first_line + n_lines as i32 {
+            return None;
+        }
+        let bpl = self.bytes_per_line;
+        let file = self.cur_file()?;
+        let hex_column = self.hex_column(file, 0);
+        let text_column = self.text_column(file);
+        let (col, pane) = if x >= hex_column && x < text_column - 2 {
+            // each group of 8 bytes takes 3*8+1 columns
+            let c = (x - hex_column) as usize;
+            let c = c - c / (3*8+1);
+            (c / 3, EditorPane::Hex)
+        } else if x >= text_column && x < text_column + bpl as i32 {
+            ((x - text_column) as usize, EditorPane::Text)
+        } else {
+            return None;
+        };
+        let len = self.cur_len();
+        if len == 0 {
+            return None;
+        }
+        let line = self.view().top_line + (y - first_line) as usize;
+        let pos = line * bpl + col.min(bpl - 1);
+        Some((pos.min(len - 1), pane))
+    }
+
+    fn clear_selection(&mut self) {
+        self.set_selection(None);
+    }
+
+    fn scroll_up(&mut self, index : usize, n_lines : usize) {
+        let view = &mut self.views[index];
+        view.top_line = view.top_line.saturating_sub(n_lines);
+        self.screen.redraw_needed = true;
+    }
+
+    fn scroll_down(&mut self, index : usize, n_lines : usize) {
+        let bpl = self.bytes_per_line;
+        let n_page_lines = self.view_rows(index).1;
+        let len = self.view_len(index);
+        let view = &mut self.views[index];
+        let last_line = len.div_ceil(bpl);
+        view.top_line += n_lines;
+        if view.top_line + n_page_lines > last_line {
+            view.top_line = last_line.saturating_sub(n_page_lines);
+        }
+        self.screen.redraw_needed = true;
+    }
+
+    fn write_bytes(&mut self, bytes : &[u8]) {
+        for &b in bytes {
+            let pos = self.view().cursor_pos;
+            if let Some(file) = self.cur_file_mut() {
+                if pos < file.data.len() {
+                    file.data[pos] = b;
+                    file.mark_changed(pos, pos + 1);
+                }
+            }
+            self.move_cursor_right();
+        }
+        self.half_byte_edited = false;
+    }
+
+    fn write_hex_digit(&mut self, val : u8) {
+        let mut half_byte_edited = self.half_byte_edited;
+        let pos = self.view().cursor_pos;
         if let Some(file) = self.cur_file_mut() {
-            if file.cursor_pos >= 16*n_page_lines {
-                file.cursor_pos -= 16*n_page_lines;
-                if file.top_line > n_page_lines {
-                    file.top_line -= n_page_lines;
+            if pos < file.data.len() {
+                if ! half_byte_edited {
+                    file.data[pos] &= 0x0f;
+                    file.data[pos] |= val << 4;
+                    half_byte_edited = true;
                 } else {
-                    file.top_line = 0;
+                    file.data[pos] &= 0xf0;
+                    file.data[pos] |= val;
+                    half_byte_edited = false;
                 }
+                file.mark_changed(pos, pos + 1);
+            }
+        }
+        self.half_byte_edited = half_byte_edited;
+        if self.half_byte_edited {
+            self.screen.redraw_needed = true;
+        } else {
+            self.move_cursor_right();
+        }
+    }
+
+    fn ensure_cursor_visible(&mut self, visible_len_after : usize) {
+        let index = self.cur_view;
+        self.ensure_view_cursor_visible(index, visible_len_after);
+    }
+
+    fn ensure_view_cursor_visible(&mut self, index : usize, visible_len_after : usize) {
+        let bpl = self.bytes_per_line;
+        let n_page_lines = self.view_rows(index).1.max(1);
+        let len = self.view_len(index);
+        let view = &mut self.views[index];
+        let last_line = len.div_ceil(bpl);
+
+        if ! (view.cursor_pos / bpl >= view.top_line
+              && (view.cursor_pos+visible_len_after) / bpl >= view.top_line
+              && view.cursor_pos / bpl < view.top_line + n_page_lines
+              && (view.cursor_pos+visible_len_after) / bpl < view.top_line + n_page_lines) {
+            if view.cursor_pos / bpl < n_page_lines/2 {
+                view.top_line = 0;
             } else {
-                file.cursor_pos = 0;
+                view.top_line = view.cursor_pos / bpl - n_page_lines/2;
+                if view.top_line + n_page_lines > last_line {
+                    view.top_line = last_line.saturating_sub(n_page_lines);
+                }
             }
         }
+    }
+
+    pub fn set_cursor_pos(&mut self, pos : usize) {
+        let len = self.cur_len();
+        self.view_mut().cursor_pos = if pos < len { pos } else { len.saturating_sub(1) };
+        self.half_byte_edited = false;
         self.ensure_cursor_visible(0);
         self.screen.redraw_needed = true;
     }
 
-    fn move_cursor_page_down(&mut self) {
-        let n_page_lines = (self.screen.h - BORDER_LINES) as usize;
+    // search forward from the byte after the cursor, moving the cursor to the match
+    pub fn find_next(&mut self, pattern : &[u8]) -> bool {
+        self.last_search = Some(pattern.to_vec());
+        let pos = self.view().cursor_pos;
+        let found = match self.cur_file() {
+            Some(file) => file.find(pos + 1, pattern),
+            None => None,
+        };
+        match found {
+            Some(pos) => {
+                self.set_cursor_pos(pos);
+                true
+            }
+            None => {
+                // a failed search ends macro replay
+                self.stop_replay();
+                false
+            }
+        }
+    }
+
+    pub fn copy_selection(&mut self) -> bool {
+        let data = match (self.cur_file(), self.view().selection()) {
+            (Some(file), Some((start, end))) => file.data[start..end+1].to_vec(),
+            _ => return false,
+        };
+        self.clipboard = data;
+        true
+    }
+
+    pub fn paste_clipboard(&mut self) {
+        let data = self.clipboard.clone();
+        let pos = self.view().cursor_pos;
         if let Some(file) = self.cur_file_mut() {
-            let last_line = file.data.len() / 16 + if file.data.len() % 16 != 0 { 1 } else { 0 };
-            if file.cursor_pos + 16*n_page_lines < file.data.len() {
-                file.cursor_pos += 16*n_page_lines;
-                file.top_line += n_page_lines;
-                if file.top_line + n_page_lines > last_line {
-                    if last_line > n_page_lines {
-                        file.top_line = last_line - n_page_lines;
-                    } else {
-                        file.top_line = 0;
-                    }
+            let pos = pos.min(file.data.len());
+            let len = data.len().min(file.data.len() - pos);
+            file.set_bytes(pos, &data[..len]);
+        }
+        self.screen.redraw_needed = true;
+    }
+
+    pub fn save_cur_file(&mut self, path : Option<OsString>) -> io::Result<()> {
+        let file = match self.cur_file_mut() {
+            Some(file) => file,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no file")),
+        };
+        match path {
+            Some(path) => file.save_as(path)?,
+            None => file.save()?,
+        }
+        // the contents changed, so the bookmarks are stored under the new hash
+        self.store_bookmarks();
+        Ok(())
+    }
+
+    fn store_bookmarks(&mut self) {
+        let index = self.cur_file_index();
+        let file = match self.files.get(index) {
+            Some(file) => file,
+            None => return,
+        };
+        if let Some(path) = bookmark_path(file) {
+            self.bookmarks.set(&path, file.hash, &file.bookmarks);
+        }
+    }
+
+    // names the cursor position; an empty name removes the bookmarks there
+    fn prompt_set_bookmark(&mut self) {
+        let name = match self.prompt_get_string("Bookmark name (empty removes the bookmarks here)", "bookmark") {
+            Some(name) => name.replace('\t', " ").trim().to_string(),
+            None => return,
+        };
+        let pos = self.cursor_pos();
+        let file = match self.cur_file_mut() {
+            Some(file) => file,
+            None => return,
+        };
+        let msg = if name.is_empty() {
+            let count = file.bookmarks.len();
+            file.bookmarks.retain(|b| b.1 != pos);
+            if file.bookmarks.len() == count { "No bookmark here".to_string() } else { "Bookmark removed".to_string() }
+        } else {
+            file.bookmarks.retain(|b| b.0 != name);
+            file.bookmarks.push((name.clone(), pos));
+            file.bookmarks.sort_by_key(|b| b.1);
+            format!("Bookmark '{}' set", name)
+        };
+        self.store_bookmarks();
+        self.show_msg(msg);
+        self.screen.redraw_needed = true;
+    }
+
+    fn choose_bookmark(&mut self) {
+        let bookmarks = match self.cur_file() {
+            Some(file) if ! file.bookmarks.is_empty() => file.bookmarks.clone(),
+            _ => {
+                self.show_msg("No bookmarks in this file");
+                return;
+            }
+        };
+        let items = bookmarks.iter().map(|b| format!("{}  ({})", b.0, offset_text(self.view(), b.1))).collect();
+        let pos = self.cursor_pos();
+        let cur = bookmarks.iter().rposition(|b| b.1 <= pos).unwrap_or(0);
+        if let Some(index) = ListPopup::new(self, "Bookmarks", items).select(cur).choose() {
+            self.set_cursor_pos(bookmarks[index].1);
+        }
+    }
+
+    fn write_file(&mut self) {
+        let has_path = self.cur_file().is_some_and(|f| f.path.is_some());
+        let path = if has_path {
+            None
+        } else {
+            match self.prompt_get_filename("File name to write") {
+                Some(path) => Some(path),
+                None => return,
+            }
+        };
+        match self.save_cur_file(path) {
+            Ok(()) => self.show_msg("File saved"),
+            Err(e) => self.show_msg(format!("Error writing file: {}", e)),
+        }
+    }
+
+    fn prompt_find(&mut self) {
+        let text = match self.prompt_get_string("Search (hex or \"text\")", "search") {
+            Some(text) => text,
+            None => return,
+        };
+        match parse_bytes(&text) {
+            Ok(pattern) => {
+                if ! self.find_next(&pattern) {
+                    self.show_msg("Not found");
                 }
-            } else if file.data.len() > 0 {
-                file.cursor_pos = file.data.len() - 1;
+            }
+            Err(e) => self.show_msg(e),
+        }
+    }
+
+    fn repeat_find(&mut self) {
+        match self.last_search.clone() {
+            Some(pattern) => {
+                if ! self.find_next(&pattern) {
+                    self.show_msg("Not found");
+                }
+            }
+            None => self.show_msg("No previous search"),
+        }
+    }
+
+    fn prompt_go_to(&mut self) {
+        let text = match self.prompt_get_string("Go to offset (@ADDR for an address)", "offset") {
+            Some(text) => text,
+            None => return,
+        };
+        let text = text.trim();
+        if let Some(addr) = text.strip_prefix('@') {
+            // virtual address or RVA, through the memory layout of the overlay
+            match parse_number(addr) {
+                Ok(addr) => match self.cur_file().and_then(|f| f.overlay.as_ref()).map(|o| o.offset_of(addr as u64)) {
+                    Some(Some(pos)) => self.set_cursor_pos(pos),
+                    Some(None) => self.show_msg(format!("Address 0x{:x} is not mapped to the file", addr)),
+                    None => self.show_msg("No memory layout, decode the file first"),
+                },
+                Err(e) => self.show_msg(e),
+            }
+            return;
+        }
+        // offsets are relative to the base offset
+        match parse_number(text) {
+            Ok(pos) => {
+                let base = self.view().base;
+                self.set_cursor_pos(base.saturating_add(pos));
+            }
+            Err(e) => self.show_msg(e),
+        }
+    }
+
+    // makes the cursor position the base offset, or clears the base offset
+    // when the cursor is already there
+    fn toggle_base(&mut self) {
+        let pos = self.cursor_pos();
+        let view = self.view_mut();
+        view.base = if view.base == pos { 0 } else { pos };
+        let base = view.base;
+        if base == 0 {
+            self.show_msg("Base offset cleared");
+        } else {
+            self.show_msg(format!("Base offset set to 0x{:x}", base));
+        }
+        self.screen.redraw_needed = true;
+    }
+
+    // decodes the current buffer with a template, see Template::load
+    pub fn apply_template(&mut self, name : &OsStr) -> Result<(), String> {
+        let template = Template::load(name)?;
+        let file = self.cur_file_mut().ok_or("no file")?;
+        file.overlay = Some(template.apply(&file.data)?);
+        self.screen.redraw_needed = true;
+        Ok(())
+    }
+
+    fn prompt_apply_template(&mut self) {
+        let name = match self.prompt_get_filename("Template (empty removes the overlay)") {
+            Some(name) => name,
+            None => return,
+        };
+        if name.is_empty() {
+            if let Some(file) = self.cur_file_mut() {
+                file.overlay = None;
+            }
+            self.screen.redraw_needed = true;
+            return;
+        }
+        match self.apply_template(&name) {
+            Ok(()) => self.show_msg("Template applied"),
+            Err(e) => self.show_msg(e),
+        }
+    }
+
+    // decodes the current buffer with the built-in parser for its format,
+    // or the template given for it in the signature file
+    fn decode_format(&mut self) {
+        let template = self.cur_file().and_then(|f| f.file_type.as_ref()).and_then(|t| t.template.clone());
+        if let Some(name) = template {
+            match self.apply_template(OsStr::new(&name)) {
+                Ok(()) => self.show_msg(format!("Template {} applied", name)),
+                Err(e) => self.show_msg(e),
+            }
+            return;
+        }
+        let overlay = match self.cur_file() {
+            Some(file) => formats::decode(&file.data),
+            None => return,
+        };
+        match overlay {
+            Some(overlay) => {
+                let msg = match overlay.warnings.len() {
+                    0 => format!("Decoded as {}", overlay.name),
+                    1 => format!("Decoded as {}: {}", overlay.name, overlay.warnings[0]),
+                    n => format!("Decoded as {}: {} (and {} more problems)", overlay.name, overlay.warnings[0], n - 1),
+                };
+                if let Some(file) = self.cur_file_mut() {
+                    file.overlay = Some(overlay);
+                }
+                self.show_msg(msg);
+                self.screen.redraw_needed = true;
+            }
+            None => self.show_msg("Unknown file format"),
+        }
+    }
+
+    // lists the strings in the current buffer and goes to the chosen one
+    fn choose_string(&mut self) {
+        let strings = match self.cur_file() {
+            Some(file) => find_strings(&file.data, self.min_string_length),
+            None => return,
+        };
+        if strings.is_empty() {
+            self.show_msg(format!("No strings of {} or more characters", self.min_string_length));
+            return;
+        }
+        let items = strings.iter().map(|s| format!("{}  {:8}  {}", offset_text(self.view(), s.pos), s.encoding, s.text)).collect();
+        let pos = self.cursor_pos();
+        let cur = strings.iter().rposition(|s| s.pos <= pos).unwrap_or(0);
+        if let Some(index) = ListPopup::new(self, "Strings", items).select(cur).choose() {
+            self.set_cursor_pos(strings[index].pos);
+        }
+    }
+
+    // byte histogram of the selection (or the whole buffer) and entropy graph
+    fn show_analysis(&mut self) {
+        let len = self.cur_file().map_or(0, |f| f.data.len());
+        if len == 0 {
+            self.show_msg("Nothing to analyse in an empty buffer");
+            return;
+        }
+        let range = match self.view().selection() {
+            Some((start, end)) if start < len => (start, (end + 1).min(len)),
+            _ => (0, len),
+        };
+        Analysis::new(self, range).show();
+    }
+
+    // lets the user pick one of the named places of the structure overlay
+    fn choose_location(&mut self) {
+        let locations = match self.cur_file().and_then(|f| f.overlay.as_ref()) {
+            Some(overlay) if ! overlay.locations.is_empty() => overlay.locations.clone(),
+            _ => {
+                self.show_msg("No locations, decode the file or apply a template first");
+                return;
+            }
+        };
+        let items = locations.iter().map(|l| format!("{}  ({:#x})", l.0, l.1)).collect();
+        // start at the last location before the cursor
+        let pos = self.cursor_pos();
+        let cur = locations.iter().rposition(|l| l.1 <= pos).unwrap_or(0);
+        if let Some(index) = ListPopup::new(self, "Go to location", items).select(cur).choose() {
+            let pos = locations[index].1;
+            self.set_cursor_pos(pos);
+            // partition starts become the base offset
+            let is_base = self.cur_file().and_then(|f| f.overlay.as_ref()).is_some_and(|o| o.bases.contains(&pos));
+            if is_base && self.view().base != pos {
+                self.toggle_base();
+            }
+        }
+    }
+
+    fn prompt_command(&mut self) {
+        let text = match self.prompt_get_string("Command", "command") {
+            Some(text) => text,
+            None => return,
+        };
+        let mut out = vec![];
+        match Script::parse(&text).and_then(|script| script.run(self, &mut out)) {
+            Ok(()) => {
+                let out = String::from_utf8_lossy(&out);
+                if let Some(line) = out.lines().last() {
+                    self.show_msg(line.to_string());
+                }
+            }
+            Err(e) => self.show_msg(e),
+        }
+        self.screen.redraw_needed = true;
+    }
+    
+    fn move_cursor_start_of_file(&mut self) {
+        let view = self.view_mut();
+        view.cursor_pos = 0;
+        view.top_line = 0;
+        self.screen.redraw_needed = true;
+    }
+
+    fn move_cursor_end_of_file(&mut self) {
+        let len = self.cur_len();
+        let view = self.view_mut();
+        if len > 0 {
+            view.cursor_pos = len - 1;
+        } else {
+            view.cursor_pos = 0;
+        }
+        self.ensure_cursor_visible(1);
+        self.screen.redraw_needed = true;
+    }
+    
+    fn move_cursor_home(&mut self) {
+        let bpl = self.bytes_per_line;
+        let view = self.view_mut();
+        view.cursor_pos = view.cursor_pos / bpl * bpl;
+        self.screen.redraw_needed = true;
+    }
+
+    fn move_cursor_end(&mut self) {
+        let bpl = self.bytes_per_line;
+        let len = self.cur_len();
+        let view = self.view_mut();
+        view.cursor_pos = view.cursor_pos / bpl * bpl + bpl-1;
+        if len > 0 && view.cursor_pos >= len {
+            view.cursor_pos = len-1;
+        }
+        self.screen.redraw_needed = true;
+    }
+    
+    fn move_cursor_page_up(&mut self) {
+        let bpl = self.bytes_per_line;
+        let n_page_lines = self.page_lines();
+        let view = self.view_mut();
+        if view.cursor_pos >= bpl*n_page_lines {
+            view.cursor_pos -= bpl*n_page_lines;
+            if view.top_line > n_page_lines {
+                view.top_line -= n_page_lines;
             } else {
-                file.cursor_pos = 0;
+                view.top_line = 0;
             }
+        } else {
+            view.cursor_pos = 0;
+        }
+        self.ensure_cursor_visible(0);
+        self.screen.redraw_needed = true;
+    }
+
+    fn move_cursor_page_down(&mut self) {
+        let bpl = self.bytes_per_line;
+        let n_page_lines = self.page_lines();
+        let len = self.cur_len();
+        let view = self.view_mut();
+        let last_line = len.div_ceil(bpl);
+        if view.cursor_pos + bpl*n_page_lines < len {
+            view.cursor_pos += bpl*n_page_lines;
+            view.top_line += n_page_lines;
+            if view.top_line + n_page_lines > last_line {
+                if last_line > n_page_lines {
+                    view.top_line = last_line - n_page_lines;
+                } else {
+                    view.top_line = 0;
+                }
+            }
+        } else if len > 0 {
+            view.cursor_pos = len - 1;
+        } else {
+            view.cursor_pos = 0;
         }
         self.screen.redraw_needed = true;
     }
     
     fn move_cursor_up(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            if file.cursor_pos >= 16 {
-                file.cursor_pos -= 16;
-            }
+        let bpl = self.bytes_per_line;
+        let view = self.view_mut();
+        if view.cursor_pos >= bpl {
+            view.cursor_pos -= bpl;
         }
         self.ensure_cursor_visible(0);
         self.screen.redraw_needed = true;
     }
 
     fn move_cursor_down(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            if file.cursor_pos+16 < file.data.len() {
-                file.cursor_pos += 16;
-            }
+        let bpl = self.bytes_per_line;
+        let len = self.cur_len();
+        let view = self.view_mut();
+        if view.cursor_pos+bpl < len {
+            view.cursor_pos += bpl;
         }
         self.ensure_cursor_visible(0);
         self.screen.redraw_needed = true;
     }
     
     fn move_cursor_left(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            if file.cursor_pos > 0 {
-                file.cursor_pos -= 1;
-            }
+        let view = self.view_mut();
+        if view.cursor_pos > 0 {
+            view.cursor_pos -= 1;
         }
         self.ensure_cursor_visible(0);
         self.screen.redraw_needed = true;
     }
     
     fn move_cursor_right(&mut self) {
-        if let Some(file) = self.cur_file_mut() {
-            if file.cursor_pos+1 < file.data.len() {
-                file.cursor_pos += 1;
-            } else if file.data.len() > 0 {
-                file.cursor_pos = file.data.len()-1;
-            } else {
-                file.cursor_pos = 0;
-            }
+        let len = self.cur_len();
+        let view = self.view_mut();
+        if view.cursor_pos+1 < len {
+            view.cursor_pos += 1;
+        } else if len > 0 {
+            view.cursor_pos = len-1;
+        } else {
+            view.cursor_pos = 0;
         }
         self.ensure_cursor_visible(0);
         self.screen.redraw_needed = true;
     }
     
+    // opens each file chosen in the prompt (several can be marked in the
+    // file selector) as a separate buffer, showing the first one
     pub fn prompt_read_file(&mut self) -> bool {
-        let filename = match self.prompt_get_filename("Read file") {
-            Some(filename) => filename,
+        let filenames = match self.prompt_get_filenames("Read file") {
+            Some(filenames) => filenames,
             None => return false,
         };
 
-        match File::new_from_file(filename) {
-            Ok(file) => {
-                self.add_file(file);
-                true
-            },
-            Err((filename, e)) => {
-                self.show_msg(format!("Error reading file {:?}: {}", filename, e.description()));
-                false
-            },
+        let mut first = None;
+        let mut errors = vec![];
+        for filename in filenames {
+            match File::new_from_file(filename) {
+                Ok(file) => {
+                    self.add_file(file);
+                    first = first.or(Some(self.cur_file_index()));
+                },
+                Err((filename, e)) => errors.push(format!("Error reading file {:?}: {}", filename, e)),
+            }
         }
+        if let Some(index) = first {
+            self.set_cur_file(index);
+        }
+        match errors.len() {
+            0 => (),
+            1 => self.show_msg(errors.remove(0)),
+            n => self.show_msg(format!("{} (and {} more errors)", errors[0], n-1)),
+        }
+        first.is_some()
     }
 
-    pub fn prompt_get_filename(&mut self, prompt : &str) -> Option<OsString> {
+    // the typed file name, or every file chosen in the file selector
+    pub fn prompt_get_filenames(&mut self, prompt : &str) -> Option<Vec<OsString>> {
         let old_mode = self.mode;
         self.mode = EditorMode::ReadFilename;
-        let ret = self.prompt_get_text(prompt);
+        let ret = self.prompt_get_text(prompt, "file");
         self.mode = old_mode;
-        ret
+        ret.map(|names| names.iter().map(|name| expand_tilde(&name.to_string_lossy()).into()).collect())
+    }
+
+    // prompts that take a single file refuse several marked files
+    pub fn prompt_get_filename(&mut self, prompt : &str) -> Option<OsString> {
+        let mut names = self.prompt_get_filenames(prompt)?;
+        if names.len() > 1 {
+            self.show_msg("Only one file can be chosen here");
+            return None;
+        }
+        names.pop()
     }
 
-    pub fn prompt_get_string(&mut self, prompt : &str) -> Option<String> {
+    // `history` names the list of previous entries offered by Up/Down
+    pub fn prompt_get_string(&mut self, prompt : &str, history : &str) -> Option<String> {
         let old_mode = self.mode;
         self.mode = EditorMode::ReadString;
-        let ret = self.prompt_get_text(prompt);
+        let ret = self.prompt_get_text(prompt, history);
         self.mode = old_mode;
-        if let Some(os_str) = ret {
-            match os_str.into_string() {
-                Ok(string) => Some(string),
-                Err(_) => None,
-            }
-        } else {
-            None
-        }
+        ret.and_then(|mut texts| texts.pop()?.into_string().ok())
     }
 
     pub fn prompt_get_yes_no(&mut self, prompt : &str) -> Option<bool> {
@@ -593,24 +1721,24 @@ impl<'a> Editor<'a> {
                 self.draw_main_screen();
             }
             reset_color();
-            set_color(Color::FGBlack, Color::BGGray);
+            set_color_pair(self.screen.theme.message);
             self.screen.move_cursor(1, self.screen.h - FOOTER_LINES + 1);
             print!(" {}", prompt);
             clear_eol();
             show_cursor(true);
             flush_screen();
 
-            let key = self.read_key();
-            if key == ctrl_key!('c') {
-                break;
-            }
-            if key == b'Y' as u32 || key == b'y' as u32 {
-                answer = Some(true);
-                break;
-            }
-            if key == b'Y' as u32 || key == b'y' as u32 {
-                answer = Some(false);
-                break;
+            match self.read_key() {
+                Key::Ctrl('c') => break,
+                Key::Char('Y') | Key::Char('y') => {
+                    answer = Some(true);
+                    break;
+                }
+                Key::Char('N') | Key::Char('n') => {
+                    answer = Some(false);
+                    break;
+                }
+                _ => (),
             }
         }
 
@@ -620,9 +1748,15 @@ impl<'a> Editor<'a> {
         answer
     }
 
-    fn prompt_get_text(&mut self, prompt : &str) -> Option<OsString> {
+    // the typed text, or the files chosen with the file selector
+    fn prompt_get_text(&mut self, prompt : &str, history : &str) -> Option<Vec<OsString>> {
+        let entries = self.history.list(history);
+        let mut history_index = entries.len();
+        let mut edited_line = vec![];
+        // ambiguous Tab completions, listed above the prompt
+        let mut completions : Vec<String> = vec![];
         let mut filename = Vec::<char>::new();
-        let mut text : Option<OsString> = None;
+        let mut text : Option<Vec<OsString>> = None;
         let mut cursor_pos = 0_usize;
 
         self.clear_msg();
@@ -633,71 +1767,132 @@ impl<'a> Editor<'a> {
                 self.draw_main_screen();
             }
             reset_color();
-            set_color(Color::FGBlack, Color::BGGray);
+            set_color_pair(self.screen.theme.message);
+            if ! completions.is_empty() {
+                self.screen.move_cursor(1, self.screen.h - FOOTER_LINES);
+                let list = completions.join("  ");
+                print!(" {}", list.chars().take(self.screen.w as usize - 2).collect::<String>());
+                clear_eol();
+            }
             self.screen.move_cursor(1, self.screen.h - FOOTER_LINES + 1);
             print!(" {}: ", prompt);
-            for c in &filename {
+            // scroll the text horizontally to keep the cursor on screen
+            let prompt_width = prompt.chars().count() + 3;
+            let avail = (self.screen.w as usize).saturating_sub(prompt_width + 1).max(1);
+            let mut start = 0;
+            while start < cursor_pos && str_width(&filename[start..cursor_pos]) >= avail {
+                start += 1;
+            }
+            let mut width = 0;
+            for &c in &filename[start..] {
+                width += char_width(c);
+                if width > avail {
+                    break;
+                }
                 print!("{}", c);
             }
             clear_eol();
-            self.screen.move_cursor(((prompt.len() + 4 + cursor_pos)&0xffff_ffff) as i32, self.screen.h - FOOTER_LINES + 1);
+            let cursor_x = prompt_width + str_width(&filename[start..cursor_pos]) + 1;
+            self.screen.move_cursor(cursor_x as i32, self.screen.h - FOOTER_LINES + 1);
             show_cursor(true);
             flush_screen();
 
             let key = self.read_key();
-            if key == ctrl_key!('c') {
-                break;
-            }
-            if key == 13 {
-                let string : String = filename.into_iter().collect();
-                text = Some(string.into());
-                break;
+            if ! completions.is_empty() && key != Key::Tab {
+                completions.clear();
+                self.screen.redraw_needed = true;
             }
-
-            if key >= 32 && key < 127 {
-                if cursor_pos >= filename.len() {
-                    filename.push(key as u8 as char);
-                } else {
-                    filename.insert(cursor_pos, key as u8 as char);
+            match key {
+                Key::Ctrl('c') => break,
+                Key::Enter => {
+                    let string : String = filename.into_iter().collect();
+                    self.history.add(history, &string);
+                    text = Some(vec![string.into()]);
+                    break;
                 }
-                cursor_pos += 1;
-                continue;
-            }
-            if key == 8 || key == 127 {
-                if cursor_pos > 0 {
+                Key::Special(Special::Up, _) if history_index > 0 => {
+                    if history_index == entries.len() {
+                        edited_line = filename.clone();
+                    }
+                    history_index -= 1;
+                    filename = entries[history_index].chars().collect();
+                    cursor_pos = filename.len();
+                }
+                Key::Special(Special::Down, _) if history_index < entries.len() => {
+                    history_index += 1;
+                    filename = match entries.get(history_index) {
+                        Some(entry) => entry.chars().collect(),
+                        None => edited_line.clone(),
+                    };
+                    cursor_pos = filename.len();
+                }
+                Key::Tab if self.mode == EditorMode::ReadFilename => {
+                    let typed : String = filename[..cursor_pos].iter().collect();
+                    let (completed, candidates) = complete_path(&typed);
+                    let rest = filename.split_off(cursor_pos);
+                    filename = completed.chars().collect();
+                    cursor_pos = filename.len();
+                    filename.extend(rest);
+                    completions = candidates;
+                    self.screen.redraw_needed = true;
+                }
+                Key::Char(c) if ! c.is_control() => {
+                    filename.insert(cursor_pos, c);
+                    cursor_pos += 1;
+                }
+                Key::Paste(data) => {
+                    for c in String::from_utf8_lossy(&data).chars().filter(|c| ! c.is_control()) {
+                        filename.insert(cursor_pos, c);
+                        cursor_pos += 1;
+                    }
+                }
+                Key::Backspace if cursor_pos > 0 => {
                     cursor_pos -= 1;
                     filename.remove(cursor_pos);
                 }
-                continue;
-            }
-            if key == ctrl_key!('a') || key == KEY_HOME {
-                cursor_pos = 0;
-                continue;
-            }
-            if key == ctrl_key!('e') || key == KEY_END {
-                cursor_pos = filename.len();
-                continue;
-            }
-            if key == ctrl_key!('t') && self.mode == EditorMode::ReadFilename {
-                let mut fs = FileSel::new(self);
-                show_cursor(false);
-                text = fs.select_file(OsStr::new("."));
-                show_cursor(true);
-                if text.is_some() {
-                    break;
+                Key::Ctrl('d') | Key::Special(Special::Del, _) if cursor_pos < filename.len() => {
+                    filename.remove(cursor_pos);
                 }
-            }
-            if key == KEY_ARROW_LEFT {
-                if cursor_pos > 0 {
-                    cursor_pos -= 1;
+                Key::Ctrl('w') => {
+                    // back to the previous whitespace, like the shell
+                    let mut start = cursor_pos;
+                    while start > 0 && filename[start-1].is_whitespace() { start -= 1; }
+                    while start > 0 && ! filename[start-1].is_whitespace() { start -= 1; }
+                    self.prompt_kill = filename.drain(start..cursor_pos).collect();
+                    cursor_pos = start;
                 }
-                continue;
-            }
-            if key == KEY_ARROW_RIGHT {
-                if cursor_pos < filename.len() {
-                    cursor_pos += 1;
+                Key::Ctrl('u') => {
+                    self.prompt_kill = filename.drain(..cursor_pos).collect();
+                    cursor_pos = 0;
+                }
+                Key::Ctrl('k') => {
+                    self.prompt_kill = filename.split_off(cursor_pos);
+                }
+                Key::Ctrl('y') => {
+                    for &c in &self.prompt_kill {
+                        filename.insert(cursor_pos, c);
+                        cursor_pos += 1;
+                    }
                 }
-                continue;
+                Key::Alt('b') | Key::Special(Special::Left, MOD_CTRL) => cursor_pos = prev_word(&filename, cursor_pos),
+                Key::Alt('f') | Key::Special(Special::Right, MOD_CTRL) => cursor_pos = next_word(&filename, cursor_pos),
+                Key::Ctrl('a') | Key::Special(Special::Home, _) => cursor_pos = 0,
+                Key::Ctrl('e') | Key::Special(Special::End, _) => cursor_pos = filename.len(),
+                Key::Ctrl('t') if self.mode == EditorMode::ReadFilename => {
+                    let files = {
+                        let mut fs = FileSel::new(self);
+                        show_cursor(false);
+                        fs.select_files(OsStr::new("."))
+                    };
+                    show_cursor(true);
+                    if ! files.is_empty() {
+                        text = Some(files);
+                        break;
+                    }
+                }
+                Key::Special(Special::Left, _) if cursor_pos > 0 => cursor_pos -= 1,
+                Key::Special(Special::Right, _) if cursor_pos < filename.len() => cursor_pos += 1,
+                _ => (),
             }
         }
 
@@ -708,31 +1903,220 @@ impl<'a> Editor<'a> {
     
 }
 
-fn is_hex_digit(key : u32) -> bool {
-    macro_rules! ascii {
-        ($ch:expr) => { $ch as u32 }
+// start of the word before `pos` in a prompt
+fn prev_word(text : &[char], pos : usize) -> usize {
+    let mut pos = pos;
+    while pos > 0 && ! text[pos-1].is_alphanumeric() { pos -= 1; }
+    while pos > 0 && text[pos-1].is_alphanumeric() { pos -= 1; }
+    pos
+}
+
+// end of the word after `pos` in a prompt
+fn next_word(text : &[char], pos : usize) -> usize {
+    let mut pos = pos;
+    while pos < text.len() && ! text[pos].is_alphanumeric() { pos += 1; }
+    while pos < text.len() && text[pos].is_alphanumeric() { pos += 1; }
+    pos
+}
+
+// key names in the footer, with control keys shown like "^G"
+fn key_label(key : &Key) -> Option<String> {
+    match *key {
+        Key::Ctrl(c) => Some(format!("^{}", c.to_ascii_uppercase())),
+        Key::Alt(c) if c.is_ascii_lowercase() => Some(format!("M-{}", c.to_ascii_uppercase())),
+        _ => key_name(key),
     }
+}
 
-    (key >= ascii!('0') && key <= ascii!('9'))
-        || (key >= ascii!('a') && key <= ascii!('f'))
-        || (key >= ascii!('A') && key <= ascii!('F'))
+fn to_hex_val(c : char) -> u8 {
+    match c.to_digit(16) {
+        Some(v) => v as u8,
+        None => 0,
+    }
 }
 
-fn to_hex_val(key : u32) -> u8 {
-    macro_rules! ascii {
-        ($ch:expr) => { $ch as u32 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // the text entered at a prompt for the given typed keys
+    fn prompt(keys : &[u8]) -> Option<String> {
+        let mut editor = Editor::new(Box::new(keys), -1);
+        editor.prompt_get_string("test", "test")
     }
-    macro_rules! byte {
-        ($ch:expr) => { ($ch) as u8 }
+
+    #[test]
+    fn footer_keys() {
+        let mut editor = Editor::new(Box::new(io::empty()), -1);
+        let default : Vec<_> = editor.footer_keys().into_iter().flatten().flatten().collect();
+        for &mode in &[EditorMode::ReadFilename, EditorMode::ReadString, EditorMode::ReadYesNo] {
+            editor.mode = mode;
+            let keys = editor.footer_keys();
+            // columns from the third on are cleared by draw_footer()
+            assert!(keys.len() <= 2);
+            assert!(keys.iter().flatten().flatten().all(|k| k.1 == "Cancel" || ! default.contains(k)));
+        }
     }
 
-    byte!(if key >= ascii!('0') && key <= ascii!('9') {
-        key - ascii!('0')
-    } else if key >= ascii!('a') && key <= ascii!('f') {
-        key - ascii!('a') + 10
-    } else if key >= ascii!('A') && key <= ascii!('F') {
-        key - ascii!('A') + 10
-    } else {
-        0
-    })
+    // editor with buffers named after `names`, each holding its name
+    fn editor_with_files<'a>(names : &[&str], keys : &'a [u8]) -> Editor<'a> {
+        let mut editor = Editor::new(Box::new(keys), -1);
+        for name in names {
+            let mut file = File::new();
+            file.data = name.as_bytes().to_vec();
+            file.filename = Some(name.to_string());
+            editor.add_file(file);
+        }
+        editor
+    }
+
+    // names of the buffers shown in each view
+    fn view_files(editor : &Editor) -> Vec<String> {
+        editor.views.iter().map(|v| editor.files[v.file].filename.clone().unwrap()).collect()
+    }
+
+    #[test]
+    fn remove_file_with_two_views() {
+        // (buffers shown, buffer removed, buffers shown afterwards)
+        let cases = [
+            ([0, 2], 0, ["b", "c"]),
+            ([2, 0], 0, ["c", "b"]),
+            ([1, 2], 1, ["c", "c"]),
+            ([1, 0], 1, ["c", "a"]),
+            ([2, 1], 2, ["b", "b"]),
+            ([2, 0], 2, ["b", "a"]),
+            ([2, 2], 2, ["b", "b"]),
+        ];
+        for &(shown, removed, after) in &cases {
+            let mut editor = editor_with_files(&["a", "b", "c"], b"");
+            editor.views = vec![View::new(shown[0], EditorPane::Hex), View::new(shown[1], EditorPane::Hex)];
+            editor.cur_view = if shown[0] == removed { 0 } else { 1 };
+            editor.remove_cur_file();
+            assert_eq!(editor.files.len(), 2);
+            assert_eq!(view_files(&editor), after, "{:?} removing {}", shown, removed);
+        }
+    }
+
+    #[test]
+    fn remove_only_file() {
+        let mut editor = editor_with_files(&["a"], b"");
+        editor.views.push(View::new(0, EditorPane::Text));
+        editor.close_cur_file();
+        assert_eq!(editor.files.len(), 1);
+        assert!(editor.files[0].data.is_empty());
+        assert!(editor.views.iter().all(|v| v.file == 0));
+    }
+
+    #[test]
+    fn close_modified_file() {
+        for &(keys, n_files) in &[(&b"n"[..], 2), (b"\x03", 2), (b"y", 1)] {
+            let mut editor = editor_with_files(&["a", "b"], keys);
+            editor.files[1].modified = true;
+            editor.close_cur_file();
+            assert_eq!(editor.files.len(), n_files, "{:?}", keys);
+            assert_eq!(editor.files[0].filename.as_deref(), Some("a"));
+            assert!(editor.mode == EditorMode::Default);
+        }
+    }
+
+    #[test]
+    fn read_files() {
+        let dir = env::temp_dir().join(format!("hedr-read-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), b"abc").unwrap();
+        let keys = format!("{}\r{}\r", dir.join("a").display(), dir.join("missing").display());
+        let mut editor = editor_with_files(&[], keys.as_bytes());
+        assert!(editor.prompt_read_file());
+        assert_eq!(editor.files.len(), 1);
+        assert_eq!(editor.files[0].data, b"abc");
+        assert!(! editor.prompt_read_file());
+        assert!(editor.screen.msg.as_ref().unwrap().starts_with("Error reading file"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn type_text() {
+        let mut editor = editor_with_files(&["abcd"], "x\u{e9}".as_bytes());
+        editor.view_mut().pane = EditorPane::Text;
+        editor.process_input();
+        editor.process_input();
+        assert_eq!(editor.files[0].data, b"x\xc3\xa9d");
+        assert_eq!(editor.cursor_pos(), 3);
+    }
+
+    #[test]
+    fn split_view_rows() {
+        for &h in &[25, 26] {
+            let mut editor = editor_with_files(&["a"], b"");
+            editor.screen.h = h;
+            editor.split_view();
+            assert_eq!(editor.views.len(), 2);
+            let avail = h - BORDER_LINES;
+            let (first0, n0) = editor.view_rows(0);
+            let (first1, n1) = editor.view_rows(1);
+            // each view has a title line and the last one takes the odd line
+            assert_eq!(first0, HEADER_LINES + 2);
+            assert_eq!(first1, first0 + n0 as i32 + 1);
+            assert_eq!(first1 + n1 as i32, HEADER_LINES + 1 + avail);
+            assert_eq!(n1 - n0, (avail % 2) as usize);
+            assert_eq!(editor.view_at(HEADER_LINES), None);
+            assert_eq!(editor.view_at(first0 - 1), Some(0));
+            assert_eq!(editor.view_at(first1 - 2), Some(0));
+            assert_eq!(editor.view_at(first1 - 1), Some(1));
+            assert_eq!(editor.view_at(first1 + n1 as i32 - 1), Some(1));
+            assert_eq!(editor.view_at(first1 + n1 as i32), None);
+        }
+    }
+
+    #[test]
+    fn split_view_too_small() {
+        let mut editor = editor_with_files(&["a"], b"");
+        editor.screen.h = BORDER_LINES + 2 * MIN_VIEW_LINES + 1;
+        editor.split_view();
+        assert_eq!(editor.views.len(), 1);
+    }
+
+    #[test]
+    fn footer_key_bindings() {
+        let mut editor = Editor::new(Box::new(io::empty()), -1);
+        let labels = |editor : &Editor| -> Vec<String> {
+            editor.footer_keys().into_iter().flatten().flatten().map(|k| format!("{} {}", k.0, k.1)).collect()
+        };
+        assert_eq!(labels(&editor), ["^G Get Help", "^X Exit", "^O Write File", "^R Read File",
+                                     "^W Where Is", "^_ Go To", "M-6 Copy", "^U Paste", "M-X Command"]);
+        editor.keymap.bind(Key::Special(Special::F(3), MOD_NONE), Action::Find);
+        editor.keymap.unbind(&Key::Ctrl('w'));
+        editor.keymap.unbind(&Key::Ctrl('g'));
+        let labels = labels(&editor);
+        assert!(labels.contains(&"F3 Where Is".to_string()), "{:?}", labels);
+        assert!(! labels.iter().any(|l| l.ends_with("Get Help")), "{:?}", labels);
+    }
+
+    #[test]
+    fn words() {
+        let text : Vec<char> = "ab, cd.e".chars().collect();
+        assert_eq!(prev_word(&text, 8), 7);
+        assert_eq!(prev_word(&text, 7), 4);
+        assert_eq!(prev_word(&text, 4), 0);
+        assert_eq!(next_word(&text, 0), 2);
+        assert_eq!(next_word(&text, 2), 6);
+        assert_eq!(next_word(&text, 7), 8);
+    }
+
+    #[test]
+    fn prompt_editing() {
+        assert_eq!(prompt(b"abc\r").as_deref(), Some("abc"));
+        assert_eq!(prompt("h\u{e9}llo\x7f\r".as_bytes()).as_deref(), Some("h\u{e9}ll"));
+        assert_eq!(prompt(b"abc\x01\x1b[3~\r").as_deref(), Some("bc"));
+        assert_eq!(prompt(b"abc\x1b[D\x1b[D\x04\r").as_deref(), Some("ac"));
+        assert_eq!(prompt(b"abc\x03").as_deref(), None);
+    }
+
+    #[test]
+    fn prompt_kill_yank() {
+        assert_eq!(prompt(b"foo bar\x17\x01\x19 \r").as_deref(), Some("bar foo "));
+        assert_eq!(prompt(b"foo bar\x1bb\x0b\x05\x19\x19\r").as_deref(), Some("foo barbar"));
+        assert_eq!(prompt(b"foo bar\x1bb\x15\x05\x19\r").as_deref(), Some("barfoo "));
+    }
 }
@@ -0,0 +1,401 @@
+
+use super::overlay::{Overlay, Segment};
+use super::formats::{Fields, group, c_string, enum_name, flag_names};
+
+// symbols decoded from each symbol table, to keep huge tables manageable
+const MAX_SYMBOLS : usize = 20_000;
+
+const ET_REL : u64 = 1;
+const PT_LOAD : u64 = 1;
+const SHT_SYMTAB : u64 = 2;
+const SHT_NOBITS : u64 = 8;
+const SHT_DYNSYM : u64 = 11;
+const SHF_ALLOC : u64 = 2;
+const SHN_LORESERVE : u64 = 0xff00;
+const STT_SECTION : u64 = 3;
+const STT_FILE : u64 = 4;
+
+const CLASS_NAMES : &[(u64, &str)] = &[(1, "32-bit"), (2, "64-bit")];
+
+const DATA_NAMES : &[(u64, &str)] = &[(1, "little endian"), (2, "big endian")];
+
+const OSABI_NAMES : &[(u64, &str)] = &[
+    (0, "System V"), (1, "HP-UX"), (2, "NetBSD"), (3, "Linux"), (6, "Solaris"),
+    (9, "FreeBSD"), (12, "OpenBSD"), (97, "ARM"), (255, "standalone"),
+];
+
+const TYPE_NAMES : &[(u64, &str)] = &[
+    (0, "none"), (1, "relocatable"), (2, "executable"), (3, "shared object"), (4, "core"),
+];
+
+const MACHINE_NAMES : &[(u64, &str)] = &[
+    (2, "SPARC"), (3, "x86"), (8, "MIPS"), (20, "PowerPC"), (21, "PowerPC64"), (22, "S390"),
+    (40, "ARM"), (42, "SuperH"), (43, "SPARC V9"), (50, "IA-64"), (62, "x86-64"),
+    (183, "AArch64"), (243, "RISC-V"), (258, "LoongArch"),
+];
+
+const PT_NAMES : &[(u64, &str)] = &[
+    (0, "NULL"), (1, "LOAD"), (2, "DYNAMIC"), (3, "INTERP"), (4, "NOTE"), (5, "SHLIB"),
+    (6, "PHDR"), (7, "TLS"), (0x6474e550, "GNU_EH_FRAME"), (0x6474e551, "GNU_STACK"),
+    (0x6474e552, "GNU_RELRO"), (0x6474e553, "GNU_PROPERTY"),
+];
+
+const PF_NAMES : &[(u64, &str)] = &[(4, "R"), (2, "W"), (1, "X")];
+
+const SHT_NAMES : &[(u64, &str)] = &[
+    (0, "NULL"), (1, "PROGBITS"), (2, "SYMTAB"), (3, "STRTAB"), (4, "RELA"), (5, "HASH"),
+    (6, "DYNAMIC"), (7, "NOTE"), (8, "NOBITS"), (9, "REL"), (11, "DYNSYM"),
+    (14, "INIT_ARRAY"), (15, "FINI_ARRAY"), (16, "PREINIT_ARRAY"), (17, "GROUP"),
+    (18, "SYMTAB_SHNDX"), (0x6ffffff6, "GNU_HASH"), (0x6ffffffd, "VERDEF"),
+    (0x6ffffffe, "VERNEED"), (0x6fffffff, "VERSYM"),
+];
+
+const SHF_NAMES : &[(u64, &str)] = &[
+    (0x1, "WRITE"), (0x2, "ALLOC"), (0x4, "EXEC"), (0x10, "MERGE"), (0x20, "STRINGS"),
+    (0x40, "INFO_LINK"), (0x80, "LINK_ORDER"), (0x200, "GROUP"), (0x400, "TLS"),
+];
+
+const STB_NAMES : &[(u64, &str)] = &[(0, "LOCAL"), (1, "GLOBAL"), (2, "WEAK")];
+
+const STT_NAMES : &[(u64, &str)] = &[
+    (0, "NOTYPE"), (1, "OBJECT"), (2, "FUNC"), (3, "SECTION"), (4, "FILE"), (5, "COMMON"),
+    (6, "TLS"), (10, "IFUNC"),
+];
+
+struct Section {
+    name_off : u64,
+    name : String,
+    ty : u64,
+    flags : u64,
+    addr : u64,
+    offset : u64,
+    size : u64,
+    link : u64,
+    entsize : u64,
+}
+
+// (64-bit, big endian) from the identification bytes
+fn ident(data : &[u8]) -> Option<(bool, bool)> {
+    if data.len() < 16 || &data[..4] != b"\x7fELF" {
+        return None;
+    }
+    let is_64 = match data[4] { 1 => false, 2 => true, _ => return None };
+    let big_endian = match data[5] { 1 => false, 2 => true, _ => return None };
+    Some((is_64, big_endian))
+}
+
+pub fn detect(data : &[u8]) -> Option<String> {
+    let (is_64, big_endian) = ident(data)?;
+    let f = Fields::new(data, 0, big_endian);
+    let ty = f.peek(16, 2)?;
+    let machine = f.peek(18, 2)?;
+    let mut desc = format!("ELF {}-bit {}", if is_64 { 64 } else { 32 }, if big_endian { "MSB" } else { "LSB" });
+    if let Some(t) = TYPE_NAMES.iter().find(|t| t.0 == ty) {
+        desc.push(' ');
+        desc.push_str(t.1);
+    }
+    if let Some(m) = MACHINE_NAMES.iter().find(|m| m.0 == machine) {
+        desc.push_str(", ");
+        desc.push_str(m.1);
+    }
+    Some(desc)
+}
+
+fn in_file(data : &[u8], offset : u64, size : u64) -> bool {
+    offset <= data.len() as u64 && size <= data.len() as u64 - offset
+}
+
+pub fn decode(data : &[u8]) -> Option<Overlay> {
+    let (is_64, big_endian) = ident(data)?;
+    let word = if is_64 { 8 } else { 4 };
+    let mut fields = vec![];
+
+    // file header
+    let mut hdr = Fields::new(data, 0, big_endian);
+    let mut id = Fields::new(data, 0, big_endian);
+    id.text("magic", 4)?;
+    id.named("class", 1, CLASS_NAMES)?;
+    id.named("data", 1, DATA_NAMES)?;
+    id.uint("version", 1)?;
+    id.named("os_abi", 1, OSABI_NAMES)?;
+    id.uint("abi_version", 1)?;
+    id.bytes("padding", 7)?;
+    hdr.pos = id.pos;
+    hdr.push(id.into_field("e_ident", String::new()));
+    let file_type = hdr.named("e_type", 2, TYPE_NAMES)?;
+    hdr.named("e_machine", 2, MACHINE_NAMES)?;
+    hdr.uint("e_version", 4)?;
+    let entry = hdr.uint_with("e_entry", word, |v| format!("0x{:x}", v))?;
+    let phoff = hdr.uint("e_phoff", word)?;
+    let shoff = hdr.uint("e_shoff", word)?;
+    hdr.uint_with("e_flags", 4, |v| format!("0x{:x}", v))?;
+    hdr.uint("e_ehsize", 2)?;
+    let phentsize = hdr.uint("e_phentsize", 2)?;
+    let phnum = hdr.uint("e_phnum", 2)?;
+    let shentsize = hdr.uint("e_shentsize", 2)?;
+    let shnum = hdr.uint("e_shnum", 2)?;
+    let shstrndx = hdr.uint("e_shstrndx", 2)?;
+    fields.push(hdr.into_field("header", String::new()));
+
+    // program headers
+    let mut segments = vec![];
+    if phnum > 0 && phentsize > 0 && in_file(data, phoff, phnum * phentsize) {
+        let mut headers = vec![];
+        for i in 0..phnum {
+            let mut ph = Fields::new(data, (phoff + i * phentsize) as usize, big_endian);
+            let p_type = ph.named("p_type", 4, PT_NAMES)?;
+            let mut flags = 0;
+            if is_64 {
+                flags = ph.flags("p_flags", 4, PF_NAMES)?;
+            }
+            let offset = ph.uint_with("p_offset", word, |v| format!("0x{:x}", v))?;
+            let vaddr = ph.uint_with("p_vaddr", word, |v| format!("0x{:x}", v))?;
+            ph.uint_with("p_paddr", word, |v| format!("0x{:x}", v))?;
+            let filesz = ph.uint("p_filesz", word)?;
+            ph.uint("p_memsz", word)?;
+            if ! is_64 {
+                flags = ph.flags("p_flags", 4, PF_NAMES)?;
+            }
+            ph.uint("p_align", word)?;
+            if p_type == PT_LOAD && in_file(data, offset, filesz) && vaddr.checked_add(filesz).is_some() {
+                segments.push(Segment { offset : offset as usize, size : filesz as usize, addr : vaddr });
+            }
+            let type_name = PT_NAMES.iter().find(|t| t.0 == p_type).map_or("?", |t| t.1);
+            let value = format!("{} {} 0x{:x} at 0x{:x}", type_name, flag_names(flags, PF_NAMES), filesz, vaddr);
+            headers.push(ph.into_field(&format!("[{}]", i), value));
+        }
+        fields.push(group("program_headers", phoff as usize, (phnum * phentsize) as usize, headers));
+    }
+
+    // section headers, names come from the section name string table
+    let mut sections = vec![];
+    if shnum > 0 && shentsize > 0 && in_file(data, shoff, shnum * shentsize) {
+        let mut headers = vec![];
+        for i in 0..shnum {
+            let mut sh = Fields::new(data, (shoff + i * shentsize) as usize, big_endian);
+            let name = sh.uint("sh_name", 4)?;
+            let ty = sh.named("sh_type", 4, SHT_NAMES)?;
+            let flags = sh.flags("sh_flags", word, SHF_NAMES)?;
+            let addr = sh.uint_with("sh_addr", word, |v| format!("0x{:x}", v))?;
+            let offset = sh.uint_with("sh_offset", word, |v| format!("0x{:x}", v))?;
+            let size = sh.uint("sh_size", word)?;
+            let link = sh.uint("sh_link", 4)?;
+            sh.uint("sh_info", 4)?;
+            sh.uint("sh_addralign", word)?;
+            let entsize = sh.uint("sh_entsize", word)?;
+            headers.push(sh);
+            sections.push(Section { name_off : name, name : String::new(), ty, flags, addr, offset, size, link, entsize });
+        }
+        let names_offset = sections.get(shstrndx as usize).map(|s| s.offset);
+        for (i, sh) in headers.iter_mut().enumerate() {
+            let name_off = sections[i].name_off;
+            sections[i].name = match names_offset {
+                Some(off) => c_string(data, off.saturating_add(name_off) as usize),
+                None => String::new(),
+            };
+            sh.fields[0].value = format!("\"{}\" ({})", sections[i].name, name_off);
+        }
+        let headers = headers.into_iter().enumerate().map(|(i, sh)| {
+            let value = format!("{} 0x{:x} bytes at 0x{:x}", enum_name(sections[i].ty, SHT_NAMES), sections[i].size, sections[i].offset);
+            sh.into_field(&format!("[{}] {}", i, sections[i].name), value)
+        }).collect();
+        fields.push(group("section_headers", shoff as usize, (shnum * shentsize) as usize, headers));
+    }
+
+    // relocatable files have no program headers, map their sections instead
+    if segments.is_empty() {
+        for s in &sections {
+            if s.flags & SHF_ALLOC != 0 && s.addr != 0 && s.ty != SHT_NOBITS && in_file(data, s.offset, s.size)
+                && s.addr.checked_add(s.size).is_some() {
+                segments.push(Segment { offset : s.offset as usize, size : s.size as usize, addr : s.addr });
+            }
+        }
+    }
+
+    let mut locations = vec![];
+    for s in &sections {
+        if s.ty != SHT_NOBITS && s.size > 0 && in_file(data, s.offset, s.size) {
+            locations.push((format!("section {}", s.name), s.offset as usize));
+        }
+    }
+
+    // symbol tables, names come from the linked string table
+    for s in &sections {
+        if (s.ty != SHT_SYMTAB && s.ty != SHT_DYNSYM) || ! in_file(data, s.offset, s.size) {
+            continue;
+        }
+        // tables with entries too short to hold a symbol can't be read
+        let sym_size = if is_64 { 24 } else { 16 };
+        let entsize = if s.entsize > 0 { s.entsize } else { sym_size };
+        if entsize < sym_size {
+            continue;
+        }
+        let strtab = sections.get(s.link as usize).map_or(0, |t| t.offset);
+        let count = (s.size / entsize) as usize;
+        let mut symbols = vec![];
+        for i in 0..count.min(MAX_SYMBOLS) {
+            let mut sym = Fields::new(data, (s.offset + i as u64 * entsize) as usize, big_endian);
+            let (name_off, value, size, info, shndx) = match symbol(&mut sym, is_64) {
+                Some(symbol) => symbol,
+                None => break,
+            };
+            let name = c_string(data, strtab.saturating_add(name_off) as usize);
+            sym.fields[0].value = format!("\"{}\" ({})", name, name_off);
+            let sym_type = info & 0xf;
+            if ! name.is_empty() && shndx != 0 && shndx < SHN_LORESERVE && sym_type != STT_SECTION && sym_type != STT_FILE {
+                // symbols of relocatable files are relative to their section
+                let offset = if file_type == ET_REL {
+                    sections.get(shndx as usize).filter(|t| t.ty != SHT_NOBITS).map(|t| t.offset.saturating_add(value) as usize)
+                } else {
+                    segments.iter().find_map(|seg| seg.offset_of(value))
+                };
+                if let Some(offset) = offset.filter(|&o| o < data.len()) {
+                    locations.push((format!("symbol {}", name), offset));
+                }
+            }
+            symbols.push(sym.into_field(&format!("[{}] {}", i, name), format!("0x{:x} size {}", value, size)));
+        }
+        fields.push(group(&format!("symbols {}", s.name), s.offset as usize, s.size as usize, symbols));
+    }
+
+    let mut overlay = Overlay::new("ELF", fields);
+    overlay.segments = segments;
+    if let Some(offset) = overlay.offset_of(entry) {
+        locations.push(("entry point".to_string(), offset));
+    }
+    locations.sort_by_key(|l| l.1);
+    locations.dedup();
+    overlay.locations = locations;
+    Some(overlay)
+}
+
+// st_name, st_value, st_size, st_info and st_shndx of a symbol table entry
+fn symbol(sym : &mut Fields, is_64 : bool) -> Option<(u64, u64, u64, u64, u64)> {
+    let name_off = sym.uint("st_name", 4)?;
+    if is_64 {
+        let info = sym.uint_with("st_info", 1, format_info)?;
+        sym.uint("st_other", 1)?;
+        let shndx = sym.uint("st_shndx", 2)?;
+        let value = sym.uint_with("st_value", 8, |v| format!("0x{:x}", v))?;
+        let size = sym.uint("st_size", 8)?;
+        Some((name_off, value, size, info, shndx))
+    } else {
+        let value = sym.uint_with("st_value", 4, |v| format!("0x{:x}", v))?;
+        let size = sym.uint("st_size", 4)?;
+        let info = sym.uint_with("st_info", 1, format_info)?;
+        sym.uint("st_other", 1)?;
+        let shndx = sym.uint("st_shndx", 2)?;
+        Some((name_off, value, size, info, shndx))
+    }
+}
+
+fn format_info(v : u64) -> String {
+    let bind = STB_NAMES.iter().find(|b| b.0 == v >> 4).map_or("?", |b| b.1);
+    let ty = STT_NAMES.iter().find(|t| t.0 == v & 0xf).map_or("?", |t| t.1);
+    format!("{} {} (0x{:x})", bind, ty, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(data : &mut [u8], pos : usize, bytes : &[u8]) {
+        data[pos..pos+bytes.len()].copy_from_slice(bytes);
+    }
+
+    // 64-bit little endian executable with one PT_LOAD segment mapping
+    // the whole file at `vaddr`
+    fn elf_image(vaddr : u64) -> Vec<u8> {
+        let mut data = vec![0; 0x100];
+        put(&mut data, 0, b"\x7fELF\x02\x01\x01");
+        put(&mut data, 16, &2u16.to_le_bytes());
+        put(&mut data, 18, &62u16.to_le_bytes());
+        put(&mut data, 20, &1u32.to_le_bytes());
+        put(&mut data, 32, &64u64.to_le_bytes());
+        put(&mut data, 52, &64u16.to_le_bytes());
+        put(&mut data, 54, &56u16.to_le_bytes());
+        put(&mut data, 56, &1u16.to_le_bytes());
+        put(&mut data, 58, &64u16.to_le_bytes());
+        put(&mut data, 64, &PT_LOAD.to_le_bytes()[..4]);
+        put(&mut data, 64 + 16, &vaddr.to_le_bytes());
+        put(&mut data, 64 + 32, &0x100u64.to_le_bytes());
+        put(&mut data, 64 + 40, &0x100u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn load_segment_addresses() {
+        let overlay = decode(&elf_image(0x400000)).unwrap();
+        assert_eq!(overlay.addr_of(0x10), Some(0x400010));
+        assert_eq!(overlay.offset_of(0x4000ff), Some(0xff));
+        assert_eq!(overlay.offset_of(0x400100), None);
+        assert_eq!(overlay.max_addr(), Some(0x400100));
+    }
+
+    #[test]
+    fn wrapping_segment_is_not_mapped() {
+        let overlay = decode(&elf_image(u64::MAX - 0x10)).unwrap();
+        assert_eq!(overlay.segments.len(), 0);
+        assert_eq!(overlay.addr_of(0x20), None);
+        assert_eq!(overlay.max_addr(), None);
+    }
+
+    // elf_image() with a symbol table holding "main" at 0x400010, its
+    // string table and their section headers
+    fn elf_with_symbols(entsize : u64) -> Vec<u8> {
+        let mut data = elf_image(0x400000);
+        data.resize(0x280, 0);
+        put(&mut data, 40, &0x180u64.to_le_bytes());
+        put(&mut data, 60, &3u16.to_le_bytes());
+        put(&mut data, 62, &2u16.to_le_bytes());
+        put(&mut data, 0x100, b"\0.symtab\0.strtab\0main\0");
+        let sym = 0x140 + 24;
+        put(&mut data, sym, &17u32.to_le_bytes());
+        put(&mut data, sym + 4, &[0x12, 0]);
+        put(&mut data, sym + 6, &1u16.to_le_bytes());
+        put(&mut data, sym + 8, &0x400010u64.to_le_bytes());
+        let symtab = 0x180 + 64;
+        put(&mut data, symtab, &1u32.to_le_bytes());
+        put(&mut data, symtab + 4, &SHT_SYMTAB.to_le_bytes()[..4]);
+        put(&mut data, symtab + 24, &0x140u64.to_le_bytes());
+        put(&mut data, symtab + 32, &48u64.to_le_bytes());
+        put(&mut data, symtab + 40, &2u32.to_le_bytes());
+        put(&mut data, symtab + 56, &entsize.to_le_bytes());
+        let strtab = 0x180 + 128;
+        put(&mut data, strtab, &9u32.to_le_bytes());
+        put(&mut data, strtab + 4, &3u32.to_le_bytes());
+        put(&mut data, strtab + 24, &0x100u64.to_le_bytes());
+        put(&mut data, strtab + 32, &0x40u64.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn symbols() {
+        for &entsize in &[0, 24] {
+            let overlay = decode(&elf_with_symbols(entsize)).unwrap();
+            assert!(overlay.fields.iter().any(|f| f.name == "symbols .symtab"));
+            assert!(overlay.locations.contains(&("symbol main".to_string(), 0x10)), "{:?}", overlay.locations);
+        }
+    }
+
+    #[test]
+    fn short_symbol_entries() {
+        for &entsize in &[1, 8, 23] {
+            let overlay = decode(&elf_with_symbols(entsize)).unwrap();
+            assert!(overlay.fields.iter().any(|f| f.name == "section_headers"));
+            assert!(! overlay.fields.iter().any(|f| f.name.starts_with("symbols")));
+            assert!(overlay.locations.contains(&("section .symtab".to_string(), 0x140)), "{:?}", overlay.locations);
+        }
+    }
+
+    #[test]
+    fn truncated_elf() {
+        for data in &[elf_image(0x400000), elf_with_symbols(24)] {
+            for len in 0..data.len() {
+                if let Some(overlay) = decode(&data[..len]) {
+                    overlay.max_addr();
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,32 @@
 
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::fs;
 use std::ffi::OsString;
 
+use super::overlay::Overlay;
+use super::magic::FileType;
+use super::inflate::crc32;
+
 pub struct File {
     pub data : Vec<u8>,
     pub filename : Option<String>,
+    pub path : Option<OsString>,
     pub modified : bool,
-    pub cursor_pos : usize,
-    pub top_line : usize,
+    pub overlay : Option<Overlay>,
+    // what the signature database made of the data when it was opened
+    pub file_type : Option<FileType>,
+    // ranges of bytes changed since the file was read or saved
+    pub changes : Vec<(usize, usize)>,
+    // CRC-32 of the contents when read or last saved, to find bookmarks
+    pub hash : u32,
+    pub bookmarks : Vec<(String, usize)>,
+}
+
+impl Default for File {
+    fn default() -> File {
+        File::new()
+    }
 }
 
 impl File {
@@ -18,23 +35,147 @@ impl File {
         File {
             data : vec![],
             filename : None,
+            path : None,
             modified : false,
-            cursor_pos : 0,
-            top_line : 0,
+            overlay : None,
+            file_type : None,
+            changes : vec![],
+            hash : 0,
+            bookmarks : vec![],
         }
     }
 
     pub fn new_from_file(filename : OsString) -> Result<File, (OsString, io::Error)> {
         let print_filename = filename.to_string_lossy().into_owned();
+        let data = read_file(filename.clone())?;
         let file = File {
-            data : read_file(filename)?,
+            hash : crc32(&data),
+            data,
             filename : Some(print_filename),
+            path : Some(filename),
             modified : false,
-            cursor_pos : 0,
-            top_line : 0,
+            overlay : None,
+            file_type : None,
+            changes : vec![],
+            bookmarks : vec![],
         };
         Ok(file)
     }
+
+    pub fn new_from_stdin() -> io::Result<File> {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        let mut file = File::new();
+        file.data = data;
+        file.filename = Some("<stdin>".to_string());
+        Ok(file)
+    }
+
+    // file name without the directory, for tabs and lists
+    pub fn short_name(&self) -> String {
+        match self.filename {
+            Some(ref name) => match name.rfind('/') {
+                Some(i) if i+1 < name.len() => name[i+1..].to_string(),
+                _ => name.clone(),
+            },
+            None => "NO FILE".to_string(),
+        }
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "file has no name")),
+        };
+        self.save_as(path)
+    }
+
+    pub fn save_as(&mut self, path : OsString) -> io::Result<()> {
+        let mut file = fs::File::create(&path)?;
+        file.write_all(&self.data)?;
+        self.filename = Some(path.to_string_lossy().into_owned());
+        self.path = Some(path);
+        self.modified = false;
+        self.changes.clear();
+        self.hash = crc32(&self.data);
+        Ok(())
+    }
+
+    // records that the bytes in start..end changed
+    pub fn mark_changed(&mut self, start : usize, end : usize) {
+        self.modified = true;
+        if start >= end {
+            return;
+        }
+        if let Some(last) = self.changes.last_mut() {
+            if start <= last.1 && end >= last.0 {
+                *last = (last.0.min(start), last.1.max(end));
+                return;
+            }
+        }
+        self.changes.push((start, end));
+    }
+
+    pub fn is_changed(&self, start : usize, end : usize) -> bool {
+        self.changes.iter().any(|c| c.0 < end && c.1 > start)
+    }
+
+    // moves the recorded changes and the bookmarks after `pos` when
+    // `inserted` bytes are inserted or `deleted` bytes are deleted there
+    fn shift_offsets(&mut self, pos : usize, inserted : usize, deleted : usize) {
+        let shift = |p : usize| if p <= pos { p } else { (p.max(pos + deleted) - deleted) + inserted };
+        for c in &mut self.changes {
+            *c = (shift(c.0), shift(c.1));
+        }
+        self.changes.retain(|c| c.0 < c.1);
+        for b in &mut self.bookmarks {
+            b.1 = shift(b.1);
+        }
+    }
+
+    pub fn set_bytes(&mut self, pos : usize, bytes : &[u8]) {
+        let end = pos + bytes.len();
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+        }
+        self.data[pos..end].copy_from_slice(bytes);
+        self.mark_changed(pos, end);
+    }
+
+    pub fn fill(&mut self, start : usize, end : usize, pattern : &[u8]) {
+        if end > self.data.len() {
+            self.data.resize(end, 0);
+        }
+        for (b, p) in self.data[start..end].iter_mut().zip(pattern.iter().cycle()) {
+            *b = *p;
+        }
+        self.mark_changed(start, end);
+    }
+
+    pub fn insert(&mut self, pos : usize, bytes : &[u8]) {
+        let tail = self.data.split_off(pos);
+        self.data.extend_from_slice(bytes);
+        self.data.extend_from_slice(&tail);
+        self.shift_offsets(pos, bytes.len(), 0);
+        self.mark_changed(pos, pos + bytes.len());
+    }
+
+    pub fn delete(&mut self, start : usize, end : usize) {
+        self.data.drain(start..end);
+        self.shift_offsets(start, 0, end - start);
+        // the bytes around the deletion
+        self.mark_changed(start.saturating_sub(1), (start + 1).min(self.data.len()));
+    }
+
+    pub fn find(&self, start : usize, pattern : &[u8]) -> Option<usize> {
+        if pattern.is_empty() || start >= self.data.len() {
+            return None;
+        }
+        self.data[start..].windows(pattern.len())
+            .position(|w| w == pattern)
+            .map(|pos| start + pos)
+    }
+
     
 }
 
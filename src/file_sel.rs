@@ -1,80 +1,376 @@
 
+use std::env;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::cmp::Ordering;
-use std::ffi::{OsString, OsStr};
+use std::ffi::{CString, OsString, OsStr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+use libc;
 
 use super::screen::*;
 use super::term::*;
 use super::editor;
 use super::editor::Editor;
 
-#[derive(Eq)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum FileKind {
+    File,
+    Dir,
+    BlockDev,
+    CharDev,
+    Fifo,
+    Socket,
+    Unknown,
+}
+
 struct FileInfo {
     pub name : OsString,
     pub is_dir : bool,
+    pub kind : FileKind,
+    pub size : u64,
+    pub mtime : Option<SystemTime>,
+    pub mode : u32,
+    pub link_target : Option<PathBuf>,
+    pub readable : bool,
 }
 
-impl Ord for FileInfo {
+// width of the columns after the file name: mark, size, date and permissions
+const DETAILS_WIDTH : i32 = 2 + 8 + 2 + 16 + 2 + 10;
 
-    fn cmp(&self, other: &FileInfo) -> Ordering {
-        if self.is_dir && ! other.is_dir {
-            Ordering::Less
-        } else if ! self.is_dir && other.is_dir {
-            Ordering::Greater
-        } else {
-            self.name.cmp(&other.name)
+impl FileInfo {
+
+    fn new(dir : &Path, name : OsString) -> FileInfo {
+        let path = dir.join(&name);
+        let link_target = match fs::symlink_metadata(&path) {
+            Ok(ref meta) if meta.file_type().is_symlink() => fs::read_link(&path).ok(),
+            _ => None,
+        };
+        // follow symlinks for everything else, falling back to the link itself if it's broken
+        let meta = fs::metadata(&path).or_else(|_| fs::symlink_metadata(&path));
+        let mut fi = FileInfo {
+            name,
+            is_dir : false,
+            kind : FileKind::Unknown,
+            size : 0,
+            mtime : None,
+            mode : 0,
+            link_target,
+            readable : is_readable(&path),
+        };
+        if let Ok(meta) = meta {
+            let ft = meta.file_type();
+            fi.kind = if ft.is_dir() {
+                FileKind::Dir
+            } else if ft.is_file() {
+                FileKind::File
+            } else if ft.is_block_device() {
+                FileKind::BlockDev
+            } else if ft.is_char_device() {
+                FileKind::CharDev
+            } else if ft.is_fifo() {
+                FileKind::Fifo
+            } else if ft.is_socket() {
+                FileKind::Socket
+            } else {
+                FileKind::Unknown
+            };
+            fi.is_dir = fi.kind == FileKind::Dir;
+            fi.size = meta.len();
+            fi.mtime = meta.modified().ok();
+            fi.mode = meta.permissions().mode();
         }
+        fi
     }
-    
+
+    // size column, or the kind of file for entries without a meaningful size
+    fn size_str(&self) -> String {
+        match self.kind {
+            FileKind::File => format_size(self.size),
+            FileKind::Dir => "<dir>".to_string(),
+            FileKind::BlockDev => "<blk>".to_string(),
+            FileKind::CharDev => "<chr>".to_string(),
+            FileKind::Fifo => "<fifo>".to_string(),
+            FileKind::Socket => "<sock>".to_string(),
+            FileKind::Unknown => "?".to_string(),
+        }
+    }
+
+    fn perms_str(&self) -> String {
+        let mut s = String::with_capacity(10);
+        s.push(match self.kind {
+            _ if self.link_target.is_some() => 'l',
+            FileKind::Dir => 'd',
+            FileKind::BlockDev => 'b',
+            FileKind::CharDev => 'c',
+            FileKind::Fifo => 'p',
+            FileKind::Socket => 's',
+            _ => '-',
+        });
+        for (i, c) in "rwxrwxrwx".chars().enumerate() {
+            s.push(if self.mode & (0o400 >> i) != 0 { c } else { '-' });
+        }
+        s
+    }
+
+    fn display_name(&self) -> String {
+        match self.link_target {
+            Some(ref target) => format!("{} -> {}", self.name.to_string_lossy(), target.to_string_lossy()),
+            None => self.name.to_string_lossy().into_owned(),
+        }
+    }
+
 }
 
-impl PartialOrd for FileInfo {
+fn is_readable(path : &Path) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::R_OK) == 0 },
+        Err(_) => false,
+    }
+}
 
-    fn partial_cmp(&self, other : &FileInfo) -> Option<Ordering> {
-        Some(self.cmp(other))
+fn format_size(size : u64) -> String {
+    const UNITS : &[char] = &['K', 'M', 'G', 'T', 'P'];
+    if size < 1024 {
+        return format!("{}", size);
+    }
+    let mut val = size as f64 / 1024.0;
+    let mut unit = 0;
+    while val >= 1024.0 && unit+1 < UNITS.len() {
+        val /= 1024.0;
+        unit += 1;
+    }
+    if val < 10.0 {
+        format!("{:.1}{}", val, UNITS[unit])
+    } else {
+        format!("{:.0}{}", val, UNITS[unit])
     }
-    
 }
 
-impl PartialEq for FileInfo {
+// local time as "YYYY-MM-DD HH:MM"
+fn format_time(time : Option<SystemTime>) -> String {
+    let secs = match time.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(d) => d.as_secs() as libc::time_t,
+        None => return String::new(),
+    };
+    let mut tm : libc::tm = unsafe { mem::zeroed() };
+    if unsafe { libc::localtime_r(&secs, &mut tm) }.is_null() {
+        return String::new();
+    }
+    format!("{:04}-{:02}-{:02} {:02}:{:02}",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min)
+}
 
-    fn eq(&self, other : &FileInfo) -> bool {
-        self.name.eq(&other.name)
+// replaces a leading "~" or "~/" with the home directory
+pub fn expand_tilde(path : &str) -> String {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return format!("{}{}", home.to_string_lossy(), &path[1..]);
+        }
+    }
+    path.to_string()
+}
+
+// completes the last component of `path` against the files in its
+// directory; returns the completed path (as typed, keeping any "~") and
+// the candidates when there's more than one
+pub fn complete_path(path : &str) -> (String, Vec<String>) {
+    let (dir, prefix) = match path.rfind('/') {
+        Some(i) => (&path[..i+1], &path[i+1..]),
+        None => ("", path),
+    };
+    let read_dir = match dir {
+        "" => ".".to_string(),
+        dir => expand_tilde(dir),
+    };
+    let mut names = vec![];
+    if let Ok(list) = fs::read_dir(&read_dir) {
+        for entry in list.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(prefix) && (prefix.starts_with('.') || ! name.starts_with('.')) {
+                let is_dir = entry.path().is_dir();
+                names.push(if is_dir { format!("{}/", name) } else { name });
+            }
+        }
+    }
+    names.sort();
+
+    let mut common = match names.first() {
+        Some(name) => name.clone(),
+        None => return (path.to_string(), names),
+    };
+    for name in &names[1..] {
+        let len = common.chars().zip(name.chars()).take_while(|&(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8()).sum();
+        common.truncate(len);
+    }
+    let completed = format!("{}{}", dir, common);
+    if names.len() == 1 {
+        names.clear();
+    }
+    (completed, names)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum SortMode {
+    Name,
+    Size,
+    Time,
+    Extension,
+}
+
+impl SortMode {
+
+    fn next(self) -> SortMode {
+        match self {
+            SortMode::Name => SortMode::Size,
+            SortMode::Size => SortMode::Time,
+            SortMode::Time => SortMode::Extension,
+            SortMode::Extension => SortMode::Name,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Size => "size",
+            SortMode::Time => "date",
+            SortMode::Extension => "extension",
+        }
+    }
+
+}
+
+// typed characters closer together than this extend the type-ahead search
+const TYPE_AHEAD_MS : u64 = 1000;
+
+fn extension(name : &OsStr) -> &[u8] {
+    let name = name.as_bytes();
+    match name.iter().rposition(|&b| b == b'.') {
+        Some(i) if i > 0 => &name[i+1..],
+        _ => &[],
+    }
+}
+
+// ".." first, then directories, then the rest in the given order
+fn compare(a : &FileInfo, b : &FileInfo, mode : SortMode) -> Ordering {
+    let parent = OsStr::new("..");
+    (b.name == parent).cmp(&(a.name == parent))
+        .then(b.is_dir.cmp(&a.is_dir))
+        .then_with(|| match mode {
+            SortMode::Name => Ordering::Equal,
+            SortMode::Size => b.size.cmp(&a.size),
+            SortMode::Time => b.mtime.cmp(&a.mtime),
+            SortMode::Extension => extension(&a.name).cmp(extension(&b.name)),
+        })
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+// shell-style pattern with '*', '?' and '[...]' classes
+fn glob_match(pattern : &[char], name : &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..name.len()+1).any(|i| glob_match(&pattern[1..], &name[i..])),
+        Some('?') => ! name.is_empty() && glob_match(&pattern[1..], &name[1..]),
+        Some('[') if pattern.contains(&']') => {
+            let c = match name.first() {
+                Some(&c) => c,
+                None => return false,
+            };
+            let mut i = 1;
+            let negate = pattern.get(i) == Some(&'!') || pattern.get(i) == Some(&'^');
+            if negate { i += 1; }
+            let mut matched = false;
+            let mut first = true;
+            while i < pattern.len() && (pattern[i] != ']' || first) {
+                if i+2 < pattern.len() && pattern[i+1] == '-' && pattern[i+2] != ']' {
+                    matched |= c >= pattern[i] && c <= pattern[i+2];
+                    i += 3;
+                } else {
+                    matched |= c == pattern[i];
+                    i += 1;
+                }
+                first = false;
+            }
+            if i >= pattern.len() {
+                return false;
+            }
+            matched != negate && glob_match(&pattern[i+1..], &name[1..])
+        }
+        Some(&p) => name.first() == Some(&p) && glob_match(&pattern[1..], &name[1..]),
+    }
+}
+
+// filters without wildcards match any name containing them, ignoring case
+fn filter_match(filter : &str, name : &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    if filter.contains(['*', '?', '[']) {
+        let pattern : Vec<char> = filter.chars().collect();
+        let name : Vec<char> = name.chars().collect();
+        glob_match(&pattern, &name)
+    } else {
+        name.to_lowercase().contains(&filter.to_lowercase())
     }
-    
 }
 
 
 pub struct FileSel<'a, 'b : 'a> {
     editor : &'a mut Editor<'b>,
     quit : bool,
-    selected_filename : Option<OsString>,
+    selected_files : Vec<OsString>,
+    marked : Vec<OsString>,
     cur_dir : Option<PathBuf>,
-    files : Vec<FileInfo>,
+    all_files : Vec<FileInfo>,
+    files : Vec<usize>,
     sel_index : usize,
     top_index : usize,
+    sort_mode : SortMode,
+    show_hidden : bool,
+    filter : String,
+    editing_filter : bool,
+    type_ahead : String,
+    last_type : Option<Instant>,
 }
 
 impl<'a, 'b> FileSel<'a, 'b> {
 
     pub fn new(editor : &'a mut Editor<'b>) -> FileSel<'a, 'b> {
         FileSel {
-            editor : editor,
+            editor,
             quit : false,
-            selected_filename : None,
+            selected_files : vec![],
+            marked : vec![],
             cur_dir : None,
+            all_files : vec![],
             files : vec![],
             sel_index : 0,
             top_index : 0,
+            sort_mode : SortMode::Name,
+            show_hidden : false,
+            filter : String::new(),
+            editing_filter : false,
+            type_ahead : String::new(),
+            last_type : None,
         }
     }
+
+    fn file(&self, index : usize) -> &FileInfo {
+        &self.all_files[self.files[index]]
+    }
     
     fn draw_header(&mut self) {
         self.editor.screen.move_cursor(1, 1);
-        set_color(Color::FGBlack, Color::BGGray);
+        set_color_pair(self.editor.screen.theme.header);
         print!(" Select File");
+        if let Some(ref dir) = self.cur_dir {
+            if let Ok(dir) = dir.canonicalize() {
+                print!(": {}", dir.to_string_lossy());
+            }
+        }
         clear_eol();
         self.editor.screen.move_cursor(self.editor.screen.w - 11, 1);
         print!(" hedx v0.1");
@@ -85,6 +381,8 @@ impl<'a, 'b> FileSel<'a, 'b> {
         clear_eol();
     }
     
+    // the 0*w and h-0 terms keep the grid of key help readable
+    #[allow(clippy::identity_op, clippy::erasing_op)]
     fn draw_footer(&mut self) {
         reset_color();
         let w = editor::SHORTCUT_SPACING;
@@ -94,16 +392,27 @@ impl<'a, 'b> FileSel<'a, 'b> {
         self.editor.void_key_help(1 + 0*w, h-1);
         self.editor.draw_key_help(1 + 0*w, h-0, "^C", "Cancel");
 
-        self.editor.void_key_help(1 + 1*w, h-1);
-        self.editor.void_key_help(1 + 1*w, h-0);
+        let sort = format!("Sort: {}", self.sort_mode.name());
+        self.editor.draw_key_help(1 + 1*w, h-1, "M-S", &sort);
+        self.editor.draw_key_help(1 + 1*w, h-0, "M-H", if self.show_hidden { "Hide Hidden" } else { "Show Hidden" });
+
+        self.editor.draw_key_help(1 + 2*w, h-1, "^F", "Filter");
+        self.editor.void_key_help(1 + 2*w, h-0);
+
+        self.editor.draw_key_help(1 + 3*w, h-1, "Spc", "Mark File");
+        self.editor.draw_key_help(1 + 3*w, h-0, "M-A", "Mark All");
 
         // message
         self.editor.screen.move_cursor(1, self.editor.screen.h - editor::FOOTER_LINES + 1);
-        if let Some(ref msg) = self.editor.screen.msg {
-            set_color(Color::FGBlack, Color::BGGray);
+        if self.editing_filter {
+            set_color_pair(self.editor.screen.theme.message);
+            print!(" Filter: {}", self.filter);
+        } else if let Some(ref msg) = self.editor.screen.msg {
+            set_color_pair(self.editor.screen.theme.message);
             print!(" {}", msg);
         }
         clear_eol();
+        reset_color();
     }
     
     fn draw_main_screen(&mut self) {
@@ -111,17 +420,17 @@ impl<'a, 'b> FileSel<'a, 'b> {
         self.draw_footer();
 
         let mut max_filename_len = 0;
-        for fi in &self.files {
-            let count = fi.name.to_string_lossy().chars().count();
+        for &i in &self.files {
+            let count = self.all_files[i].display_name().chars().count();
             if max_filename_len < count {
                 max_filename_len = count;
             }
         }
-        if self.editor.screen.w < 20 {
+        if self.editor.screen.w < DETAILS_WIDTH + 10 {
             return;
         }
-        if max_filename_len > (self.editor.screen.w - 20) as usize {
-            max_filename_len = (self.editor.screen.w - 20) as usize;
+        if max_filename_len > (self.editor.screen.w - DETAILS_WIDTH - 3) as usize {
+            max_filename_len = (self.editor.screen.w - DETAILS_WIDTH - 3) as usize;
         }
         
         let mut line = editor::HEADER_LINES + 1;
@@ -129,14 +438,19 @@ impl<'a, 'b> FileSel<'a, 'b> {
         reset_color();
         while file_index < self.files.len() && line <= self.editor.screen.h - editor::FOOTER_LINES {
             self.editor.screen.move_cursor(1, line);
+            let marked = self.is_marked(file_index);
             if file_index == self.sel_index {
-                set_color(Color::FGBlack, Color::BGGray);
+                set_color_pair(self.editor.screen.theme.file_sel);
+            } else if marked {
+                set_color_pair(self.editor.screen.theme.selection);
             }
-            
-            let fi = &self.files[file_index];
-            let len = fi.name.to_string_lossy().chars().count();
-            for (i, c) in fi.name.to_string_lossy().char_indices() {
-                if len > max_filename_len && i > max_filename_len-3 {
+            print!("{} ", if marked { '*' } else { ' ' });
+
+            let fi = self.file(file_index);
+            let name = fi.display_name();
+            let len = name.chars().count();
+            for (i, c) in name.chars().enumerate() {
+                if len > max_filename_len && i+3 >= max_filename_len {
                     print!("...");
                     break;
                 }
@@ -145,11 +459,10 @@ impl<'a, 'b> FileSel<'a, 'b> {
             for _ in len..max_filename_len {
                 print!(" ");
             }
-            if fi.is_dir {
-                print!("              (dir)");
-            } else {
-                print!(" {:12} bytes", 0);
-            }
+            // '!' marks entries we can't read
+            print!(" {} {:>8}  {:16}  {}",
+                   if fi.readable { ' ' } else { '!' },
+                   fi.size_str(), format_time(fi.mtime), fi.perms_str());
 
             reset_color();
             clear_eol();
@@ -158,9 +471,10 @@ impl<'a, 'b> FileSel<'a, 'b> {
             line += 1;
         }
 
-        while file_index < self.files.len() && line <= self.editor.screen.h - editor::FOOTER_LINES {
+        while line <= self.editor.screen.h - editor::FOOTER_LINES {
             self.editor.screen.move_cursor(1, line);
             clear_eol();
+            line += 1;
         }
         
         flush_screen();
@@ -168,54 +482,204 @@ impl<'a, 'b> FileSel<'a, 'b> {
 
     fn process_input(&mut self) {
         let key = self.editor.read_key();
-        if key == ctrl_key!('c') {
+        if self.editing_filter {
+            self.process_filter_input(key);
+            return;
+        }
+        if key == Key::Ctrl('c') {
             self.quit = true;
             return;
         }
-        if key == 13 {
+        if key == Key::Enter {
             self.confirm_selection();
             return;
         }
 
         self.editor.screen.msg_was_set = false;
-        if key == KEY_ARROW_UP {
-            self.move_sel_up();
-        } else if key == KEY_ARROW_DOWN {
-            self.move_sel_down();
+        match key {
+            Key::Char(' ') | Key::Special(Special::Ins, MOD_NONE) => self.toggle_mark(),
+            Key::Alt('a') => self.mark_all(),
+            Key::Char(c) => self.type_ahead(Some(c)),
+            Key::Backspace => self.type_ahead(None),
+            Key::Special(Special::Up, MOD_NONE) => self.move_sel_up(1),
+            Key::Special(Special::Down, MOD_NONE) => self.move_sel_down(1),
+            Key::Special(Special::PageUp, MOD_NONE) => self.move_sel_up(self.page_size()),
+            Key::Special(Special::PageDown, MOD_NONE) => self.move_sel_down(self.page_size()),
+            Key::Special(Special::Home, MOD_NONE) => self.move_sel_up(self.files.len()),
+            Key::Special(Special::End, MOD_NONE) => self.move_sel_down(self.files.len()),
+            Key::Alt('s') => {
+                self.sort_mode = self.sort_mode.next();
+                self.update_view();
+            }
+            Key::Alt('h') => {
+                self.show_hidden = ! self.show_hidden;
+                self.update_view();
+            }
+            Key::Ctrl('f') => {
+                self.editing_filter = true;
+                self.editor.screen.redraw_needed = true;
+            }
+            Key::Mouse(ev) => self.process_mouse(ev),
+            _ => (),
         }
         if ! self.editor.screen.msg_was_set {
             self.editor.clear_msg();
         }
     }
 
+    // the filter is applied as it's typed; Enter keeps it, ^C and Esc clear it
+    fn process_filter_input(&mut self, key : Key) {
+        match key {
+            Key::Enter => self.editing_filter = false,
+            Key::Ctrl('c') | Key::Esc => {
+                self.editing_filter = false;
+                self.filter.clear();
+            }
+            Key::Backspace => { self.filter.pop(); }
+            Key::Char(c) => self.filter.push(c),
+            _ => return,
+        }
+        self.update_view();
+    }
+
+    fn type_ahead(&mut self, c : Option<char>) {
+        let now = Instant::now();
+        let expired = match self.last_type {
+            Some(t) => now.duration_since(t) > Duration::from_millis(TYPE_AHEAD_MS),
+            None => true,
+        };
+        if expired {
+            self.type_ahead.clear();
+        }
+        self.last_type = Some(now);
+        match c {
+            Some(c) => self.type_ahead.push(c),
+            None => { self.type_ahead.pop(); }
+        }
+        if self.type_ahead.is_empty() {
+            return;
+        }
+
+        let prefix = self.type_ahead.to_lowercase();
+        let found = (0..self.files.len())
+            .find(|&i| self.file(i).name.to_string_lossy().to_lowercase().starts_with(&prefix));
+        match found {
+            Some(index) => {
+                self.sel_index = index;
+                self.ensure_sel_visible();
+                self.editor.show_msg(format!("Search: {}", self.type_ahead));
+            }
+            None => self.editor.show_msg(format!("Search: {} (not found)", self.type_ahead)),
+        }
+        self.editor.screen.redraw_needed = true;
+    }
+
+    fn process_mouse(&mut self, ev : MouseEvent) {
+        match ev.action {
+            MouseAction::WheelUp => self.move_sel_up(3),
+            MouseAction::WheelDown => self.move_sel_down(3),
+            MouseAction::Press(MouseButton::Left) | MouseAction::DoubleClick(MouseButton::Left) => {
+                if ev.y <= editor::HEADER_LINES || ev.y > self.editor.screen.h - editor::FOOTER_LINES {
+                    return;
+                }
+                let index = self.top_index + (ev.y - editor::HEADER_LINES - 1) as usize;
+                if index >= self.files.len() {
+                    return;
+                }
+                self.sel_index = index;
+                self.editor.screen.redraw_needed = true;
+                if let MouseAction::DoubleClick(_) = ev.action {
+                    self.confirm_selection();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn entry_path(&self, index : usize) -> OsString {
+        let name = &self.file(index).name;
+        if let Some(ref dir) = self.cur_dir {
+            let path = dir.join(name);
+            match path.canonicalize() {
+                Ok(path) => path.into_os_string(),
+                Err(_) => path.into_os_string(),
+            }
+        } else {
+            name.clone()
+        }
+    }
+
+    fn is_marked(&self, index : usize) -> bool {
+        ! self.marked.is_empty() && ! self.file(index).is_dir && self.marked.contains(&self.entry_path(index))
+    }
+
+    fn show_marked_count(&mut self) {
+        match self.marked.len() {
+            0 => self.editor.clear_msg(),
+            1 => self.editor.show_msg("1 file marked"),
+            n => self.editor.show_msg(format!("{} files marked", n)),
+        }
+    }
+
+    // marks are kept across directory changes, so files from several
+    // directories can be opened together
+    fn toggle_mark(&mut self) {
+        if self.sel_index >= self.files.len() || self.file(self.sel_index).is_dir {
+            return;
+        }
+        let path = self.entry_path(self.sel_index);
+        match self.marked.iter().position(|p| *p == path) {
+            Some(i) => { self.marked.remove(i); }
+            None => self.marked.push(path),
+        }
+        self.show_marked_count();
+        self.move_sel_down(1);
+    }
+
+    fn mark_all(&mut self) {
+        for index in 0..self.files.len() {
+            if ! self.file(index).is_dir {
+                let path = self.entry_path(index);
+                if ! self.marked.contains(&path) {
+                    self.marked.push(path);
+                }
+            }
+        }
+        self.show_marked_count();
+        self.editor.screen.redraw_needed = true;
+    }
+
     fn confirm_selection(&mut self) {
         if self.sel_index >= self.files.len() {
             return;
         }
-        let file = self.files.remove(self.sel_index);
-        if file.is_dir {
-            if let Err(e) = self.change_dir(&file.name) {
+        let (name, is_dir) = {
+            let file = self.file(self.sel_index);
+            (file.name.clone(), file.is_dir)
+        };
+        if is_dir {
+            if let Err(e) = self.change_dir(&name) {
                 self.editor.show_msg(format!("Error listing directory: {}", e));
             }
             reset_color();
             clear_screen();
             self.editor.screen.redraw_needed = true;
         } else {
-            self.selected_filename = if let Some(ref mut dir) = self.cur_dir.take() {
-                dir.push(file.name.clone());
-                match dir.canonicalize() {
-                    Ok(dir) => Some(dir.as_os_str().to_os_string()),
-                    Err(_) => Some(dir.as_os_str().to_os_string())
-                }
+            self.selected_files = if ! self.marked.is_empty() {
+                self.marked.split_off(0)
             } else {
-                Some(file.name.clone())
+                vec![self.entry_path(self.sel_index)]
             };
             self.quit = true;
         }
     }
 
+    fn page_size(&self) -> usize {
+        (self.editor.screen.h - editor::BORDER_LINES) as usize
+    }
+
     fn ensure_sel_visible(&mut self) {
-        let n_page_lines = (self.editor.screen.h - editor::BORDER_LINES) as usize;
+        let n_page_lines = self.page_size();
         if self.sel_index < self.top_index || self.sel_index >= self.top_index + n_page_lines {
             if self.sel_index >= n_page_lines/2 {
                 self.top_index = self.sel_index - n_page_lines/2;
@@ -226,21 +690,48 @@ impl<'a, 'b> FileSel<'a, 'b> {
         }
     }
     
-    fn move_sel_up(&mut self) {
-        if self.sel_index > 0 {
-            self.sel_index -= 1;
-            self.ensure_sel_visible();
-        }
+    fn move_sel_up(&mut self, n : usize) {
+        self.sel_index = self.sel_index.saturating_sub(n);
+        self.ensure_sel_visible();
         self.editor.screen.redraw_needed = true;
     }
 
-    fn move_sel_down(&mut self) {
-        if self.sel_index+1 < self.files.len() {
-            self.sel_index += 1;
+    fn move_sel_down(&mut self, n : usize) {
+        if ! self.files.is_empty() {
+            self.sel_index = (self.sel_index + n).min(self.files.len() - 1);
             self.ensure_sel_visible();
         }
         self.editor.screen.redraw_needed = true;
     }
+
+    // rebuild the visible list after changing the sort mode, filter or hidden
+    // files, keeping the selected file if it's still shown
+    fn update_view(&mut self) {
+        let selected = self.files.get(self.sel_index).map(|&i| self.all_files[i].name.clone());
+
+        let parent = OsStr::new("..");
+        let mut files : Vec<usize> = (0..self.all_files.len()).filter(|&i| {
+            let fi = &self.all_files[i];
+            if fi.name == parent {
+                return true;
+            }
+            if ! self.show_hidden && fi.name.as_bytes().starts_with(b".") {
+                return false;
+            }
+            fi.is_dir || self.filter.is_empty() || filter_match(&self.filter, &fi.name)
+        }).collect();
+        let all_files = &self.all_files;
+        let mode = self.sort_mode;
+        files.sort_by(|&a, &b| compare(&all_files[a], &all_files[b], mode));
+        self.files = files;
+
+        self.sel_index = selected
+            .and_then(|name| self.files.iter().position(|&i| self.all_files[i].name == name))
+            .unwrap_or(0);
+        self.top_index = 0;
+        self.ensure_sel_visible();
+        self.editor.screen.redraw_needed = true;
+    }
     
     fn change_dir(&mut self, dir : &OsStr) -> io::Result<()> {
         let mut path = PathBuf::new();
@@ -250,30 +741,26 @@ impl<'a, 'b> FileSel<'a, 'b> {
         path.push(dir);
 
         let mut files = vec![];
-        files.push(FileInfo {
-            name : OsStr::new("..").to_os_string(),
-            is_dir : true,
-        });
+        files.push(FileInfo::new(&path, OsStr::new("..").to_os_string()));
         
         let list = fs::read_dir(&path)?;
         for file in list {
             let file = file?;
-            let fi = FileInfo {
-                name : file.file_name(),
-                is_dir : file.path().is_dir(),
-            };
-            files.push(fi);
+            files.push(FileInfo::new(&path, file.file_name()));
         }
 
-        files.sort();
-        self.files = files;
+        self.all_files = files;
+        self.files.clear();
+        self.type_ahead.clear();
+        self.cur_dir = Some(path);
+        self.update_view();
         self.sel_index = 0;
         self.top_index = 0;
-        self.cur_dir = Some(path);
         Ok(())
     }
     
-    pub fn select_file(&mut self, root_dir : &OsStr) -> Option<OsString> {
+    // returns the marked files, or the one chosen if none were marked
+    pub fn select_files(&mut self, root_dir : &OsStr) -> Vec<OsString> {
 
         reset_color();
         clear_screen();
@@ -296,7 +783,149 @@ impl<'a, 'b> FileSel<'a, 'b> {
         flush_screen();
         self.editor.screen.redraw_needed = true;
         
-        self.selected_filename.take()
+        self.selected_files.split_off(0)
     }
     
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name : &str, kind : FileKind, size : u64, mode : u32) -> FileInfo {
+        FileInfo {
+            name : OsString::from(name),
+            is_dir : kind == FileKind::Dir,
+            kind,
+            size,
+            mtime : None,
+            mode,
+            link_target : None,
+            readable : true,
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(format_size(0), "0");
+        assert_eq!(format_size(1023), "1023");
+        assert_eq!(format_size(1024), "1.0K");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(20 * 1024 * 1024), "20M");
+        assert_eq!(format_size(u64::MAX), "16384P");
+        assert_eq!(info("d", FileKind::Dir, 4096, 0).size_str(), "<dir>");
+        assert_eq!(info("f", FileKind::Fifo, 0, 0).size_str(), "<fifo>");
+        assert_eq!(format_time(None), "");
+    }
+
+    #[test]
+    fn permissions() {
+        assert_eq!(info("f", FileKind::File, 0, 0o644).perms_str(), "-rw-r--r--");
+        assert_eq!(info("d", FileKind::Dir, 0, 0o755).perms_str(), "drwxr-xr-x");
+        let mut link = info("l", FileKind::File, 0, 0o777);
+        link.link_target = Some(PathBuf::from("target"));
+        assert_eq!(link.perms_str(), "lrwxrwxrwx");
+        assert_eq!(link.display_name(), "l -> target");
+    }
+
+    fn glob(pattern : &str, name : &str) -> bool {
+        let pattern : Vec<char> = pattern.chars().collect();
+        let name : Vec<char> = name.chars().collect();
+        glob_match(&pattern, &name)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("*.rs", "main.rs"));
+        assert!(glob("*", ""));
+        assert!(! glob("*.rs", "main.rc"));
+        assert!(glob("m??n.*", "main.rs"));
+        assert!(! glob("?", ""));
+        assert!(glob("[a-c]x", "bx"));
+        assert!(! glob("[!a-c]x", "bx"));
+        assert!(glob("[^a-c]x", "dx"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("[a-]", "-"));
+        assert!(! glob("[ab", "a"));
+        assert!(glob("é*", "école"));
+    }
+
+    #[test]
+    fn filters() {
+        assert!(filter_match("MAIN", OsStr::new("src/main.rs")));
+        assert!(! filter_match("main", OsStr::new("lib.rs")));
+        assert!(filter_match("*.RS", OsStr::new("a.RS")));
+        assert!(! filter_match("*.rs", OsStr::new("a.RS")));
+    }
+
+    #[test]
+    fn sorting() {
+        let mut files = vec![
+            info("b.txt", FileKind::File, 10, 0),
+            info("a.zip", FileKind::File, 30, 0),
+            info("dir", FileKind::Dir, 0, 0),
+            info("..", FileKind::Dir, 0, 0),
+            info("c.bin", FileKind::File, 20, 0),
+        ];
+        let names = |files : &[FileInfo]| files.iter().map(|f| f.name.to_string_lossy().into_owned()).collect::<Vec<_>>();
+        files.sort_by(|a, b| compare(a, b, SortMode::Name));
+        assert_eq!(names(&files), ["..", "dir", "a.zip", "b.txt", "c.bin"]);
+        files.sort_by(|a, b| compare(a, b, SortMode::Size));
+        assert_eq!(names(&files), ["..", "dir", "a.zip", "c.bin", "b.txt"]);
+        files.sort_by(|a, b| compare(a, b, SortMode::Extension));
+        assert_eq!(names(&files), ["..", "dir", "c.bin", "b.txt", "a.zip"]);
+        assert_eq!(extension(OsStr::new(".hidden")), b"");
+    }
+
+    #[test]
+    fn completion() {
+        let dir = env::temp_dir().join(format!("hedr-complete-{}", std::process::id()));
+        fs::create_dir_all(dir.join("subdir")).unwrap();
+        for name in &["file-one", "file-two", ".hidden"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let base = format!("{}/", dir.display());
+        let complete = |path : &str| complete_path(&format!("{}{}", base, path));
+
+        assert_eq!(complete("fi"), (format!("{}file-", base), vec!["file-one".to_string(), "file-two".to_string()]));
+        assert_eq!(complete("file-o"), (format!("{}file-one", base), vec![]));
+        assert_eq!(complete("s"), (format!("{}subdir/", base), vec![]));
+        assert_eq!(complete("."), (format!("{}.hidden", base), vec![]));
+        assert_eq!(complete("x"), (format!("{}x", base), vec![]));
+        assert_eq!(complete("").1.len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn marked_files() {
+        let dir = env::temp_dir().join(format!("hedr-marked-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in &["a.bin", "b.bin", "c.bin"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let dir = dir.canonicalize().unwrap();
+        let select = |keys : &[u8]| {
+            let mut editor = Editor::new(Box::new(keys), -1);
+            editor.screen.h = 24;
+            let files = FileSel::new(&mut editor).select_files(dir.as_os_str());
+            files.iter().map(|f| Path::new(f).strip_prefix(&dir).unwrap().to_string_lossy().into_owned()).collect::<Vec<_>>()
+        };
+
+        // ".." comes first; Space marks and moves down, Enter returns the marks
+        assert_eq!(select(b"\x1b[B\x1b[B\r"), ["b.bin"]);
+        assert_eq!(select(b"\x1b[B \x1b[B \r"), ["a.bin", "c.bin"]);
+        assert_eq!(select(b"\x1b[B  \x1b[A\x1b[A \r"), ["b.bin"]);
+        assert_eq!(select(b"\x1ba\x1b[B\r"), ["a.bin", "b.bin", "c.bin"]);
+        assert_eq!(select(b"\x1b[B \x03"), Vec::<String>::new());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tilde() {
+        assert_eq!(expand_tilde("/a/~"), "/a/~");
+        assert_eq!(expand_tilde("~user"), "~user");
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(expand_tilde("~/x"), format!("{}/x", home.to_string_lossy()));
+        }
+    }
+}
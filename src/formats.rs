@@ -0,0 +1,172 @@
+
+use super::overlay::{Field, Overlay};
+use super::template::{read_uint, format_int, format_bytes};
+use super::elf;
+use super::pe;
+use super::image;
+use super::archive;
+use super::disk;
+
+// a built-in decoder for a file format
+pub struct Format {
+    pub name : &'static str,
+    // short description of the file if it has the format's magic
+    pub detect : fn(&[u8]) -> Option<String>,
+    pub decode : fn(&[u8]) -> Option<Overlay>,
+}
+
+pub const FORMATS : &[Format] = &[
+    Format { name : "elf", detect : elf::detect, decode : elf::decode },
+    Format { name : "pe", detect : pe::detect, decode : pe::decode },
+    Format { name : "png", detect : image::detect_png, decode : image::decode_png },
+    Format { name : "jpeg", detect : image::detect_jpeg, decode : image::decode_jpeg },
+    Format { name : "zip", detect : archive::detect_zip, decode : archive::decode_zip },
+    Format { name : "gzip", detect : archive::detect_gzip, decode : archive::decode_gzip },
+    Format { name : "tar", detect : archive::detect_tar, decode : archive::decode_tar },
+    Format { name : "disk", detect : disk::detect, decode : disk::decode },
+];
+
+pub fn detect(data : &[u8]) -> Option<String> {
+    FORMATS.iter().filter_map(|f| (f.detect)(data)).next()
+}
+
+// decodes the structure of the first format that recognizes the data
+pub fn decode(data : &[u8]) -> Option<Overlay> {
+    FORMATS.iter()
+        .filter(|f| (f.detect)(data).is_some())
+        .filter_map(|f| (f.decode)(data))
+        .next()
+}
+
+// "name (n)" for the known values of an enumeration
+pub fn enum_name(v : u64, names : &[(u64, &str)]) -> String {
+    match names.iter().find(|n| n.0 == v) {
+        Some(n) => format!("{} ({})", n.1, v),
+        None => format_int(v as i64, false),
+    }
+}
+
+// names of the bits set in `v`, followed by the unknown bits
+pub fn flag_names(v : u64, names : &[(u64, &str)]) -> String {
+    let mut parts : Vec<String> = names.iter().filter(|n| v & n.0 != 0).map(|n| n.1.to_string()).collect();
+    let rest = names.iter().fold(v, |v, n| v & ! n.0);
+    if rest != 0 || parts.is_empty() {
+        parts.push(format!("0x{:x}", rest));
+    }
+    parts.join("|")
+}
+
+// compares a stored CRC with the one computed for the data; returns the
+// text to append to the field's value
+pub fn check_crc(stored : u64, actual : u32, what : &str, warnings : &mut Vec<String>) -> String {
+    if stored as u32 == actual {
+        " ok".to_string()
+    } else {
+        warnings.push(format!("bad CRC in {}", what));
+        format!(" BAD CRC, expected 0x{:08x}", actual)
+    }
+}
+
+// NUL terminated string at `pos`, empty if it's outside the data
+pub fn c_string(data : &[u8], pos : usize) -> String {
+    if pos >= data.len() {
+        return String::new();
+    }
+    let bytes = data[pos..].iter().take(256).take_while(|&&b| b != 0).cloned().collect::<Vec<u8>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// builds the fields of a structure stored at increasing offsets; the
+// readers return None past the end of the data
+pub struct Fields<'d> {
+    data : &'d [u8],
+    start : usize,
+    pub pos : usize,
+    pub big_endian : bool,
+    pub fields : Vec<Field>,
+}
+
+impl<'d> Fields<'d> {
+
+    pub fn new(data : &'d [u8], pos : usize, big_endian : bool) -> Fields<'d> {
+        Fields {
+            data,
+            start : pos,
+            pos,
+            big_endian,
+            fields : vec![],
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn data(&self) -> &'d [u8] {
+        self.data
+    }
+
+    pub fn peek(&self, pos : usize, size : usize) -> Option<u64> {
+        if pos > self.data.len() || size > self.data.len() - pos {
+            return None;
+        }
+        Some(read_uint(self.data, pos, size, self.big_endian))
+    }
+
+    pub fn uint_with<F>(&mut self, name : &str, size : usize, format : F) -> Option<u64>
+        where F : Fn(u64) -> String {
+        let v = self.peek(self.pos, size)?;
+        self.fields.push(Field::new(name, format(v), self.pos, size));
+        self.pos += size;
+        Some(v)
+    }
+
+    pub fn uint(&mut self, name : &str, size : usize) -> Option<u64> {
+        self.uint_with(name, size, |v| format_int(v as i64, false))
+    }
+
+    pub fn named(&mut self, name : &str, size : usize, names : &[(u64, &str)]) -> Option<u64> {
+        self.uint_with(name, size, |v| enum_name(v, names))
+    }
+
+    pub fn flags(&mut self, name : &str, size : usize, names : &[(u64, &str)]) -> Option<u64> {
+        self.uint_with(name, size, |v| flag_names(v, names))
+    }
+
+    pub fn bytes(&mut self, name : &str, len : usize) -> Option<&'d [u8]> {
+        if len > self.data.len().saturating_sub(self.pos) {
+            return None;
+        }
+        let bytes = &self.data[self.pos..self.pos+len];
+        self.fields.push(Field::new(name, format_bytes(bytes, false), self.pos, len));
+        self.pos += len;
+        Some(bytes)
+    }
+
+    pub fn text(&mut self, name : &str, len : usize) -> Option<&'d [u8]> {
+        let bytes = self.bytes(name, len)?;
+        if let Some(field) = self.fields.last_mut() {
+            field.value = format_bytes(bytes, true);
+        }
+        Some(bytes)
+    }
+
+    pub fn push(&mut self, field : Field) {
+        self.fields.push(field);
+    }
+
+    // a field holding the fields read so far
+    pub fn into_field(self, name : &str, value : String) -> Field {
+        let mut field = Field::new(name, value, self.start, self.pos - self.start);
+        field.children = self.fields;
+        field
+    }
+
+}
+
+// a field grouping `children`, covering `len` bytes from `start`
+pub fn group(name : &str, start : usize, len : usize, children : Vec<Field>) -> Field {
+    let mut field = Field::new(name, format!("[{}]", children.len()), start, len);
+    field.children = children;
+    field
+}
@@ -0,0 +1,122 @@
+
+use std::fs;
+use std::path::PathBuf;
+
+use super::config::config_dir;
+
+// entries kept for each kind of prompt
+const MAX_ENTRIES : usize = 100;
+
+// prompt history, kept separately for each kind of prompt (file names,
+// search strings, offsets...) and stored as "kind<TAB>entry" lines
+pub struct History {
+    path : Option<PathBuf>,
+    entries : Vec<(String, String)>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+impl History {
+
+    pub fn new() -> History {
+        History {
+            path : None,
+            entries : vec![],
+        }
+    }
+
+    pub fn load() -> History {
+        let path = match config_dir() {
+            Some(dir) => dir.join("history"),
+            None => return History::new(),
+        };
+        let mut entries = vec![];
+        if let Ok(text) = fs::read_to_string(&path) {
+            for line in text.lines() {
+                let mut parts = line.splitn(2, '\t');
+                if let (Some(kind), Some(entry)) = (parts.next(), parts.next()) {
+                    entries.push((kind.to_string(), entry.to_string()));
+                }
+            }
+        }
+        History {
+            path : Some(path),
+            entries,
+        }
+    }
+
+    // entries of the given kind, oldest first
+    pub fn list(&self, kind : &str) -> Vec<String> {
+        self.entries.iter().filter(|e| e.0 == kind).map(|e| e.1.clone()).collect()
+    }
+
+    pub fn add(&mut self, kind : &str, entry : &str) {
+        if entry.is_empty() || entry.contains('\n') {
+            return;
+        }
+        self.entries.retain(|e| e.0 != kind || e.1 != entry);
+        self.entries.push((kind.to_string(), entry.to_string()));
+        let count = self.entries.iter().filter(|e| e.0 == kind).count();
+        if count > MAX_ENTRIES {
+            if let Some(i) = self.entries.iter().position(|e| e.0 == kind) {
+                self.entries.remove(i);
+            }
+        }
+        self.save();
+    }
+
+    // saving is best effort: a read-only config directory shouldn't get in the way
+    fn save(&self) {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return,
+        };
+        let mut text = String::new();
+        for (kind, entry) in &self.entries {
+            text.push_str(kind);
+            text.push('\t');
+            text.push_str(entry);
+            text.push('\n');
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, text);
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_and_duplicates() {
+        let mut h = History::new();
+        h.add("file", "a");
+        h.add("find", "x");
+        h.add("file", "b");
+        h.add("file", "a");
+        h.add("file", "");
+        h.add("file", "two\nlines");
+        assert_eq!(h.list("file"), ["b", "a"]);
+        assert_eq!(h.list("find"), ["x"]);
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let mut h = History::new();
+        h.add("find", "x");
+        for i in 0..MAX_ENTRIES + 5 {
+            h.add("file", &i.to_string());
+        }
+        let files = h.list("file");
+        assert_eq!(files.len(), MAX_ENTRIES);
+        assert_eq!(files[0], "5");
+        assert_eq!(h.list("find"), ["x"]);
+    }
+}
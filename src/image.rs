@@ -0,0 +1,282 @@
+
+use super::overlay::{Field, Overlay};
+use super::formats::Fields;
+use super::template::format_bytes;
+use super::inflate::crc32;
+
+// chunks and markers decoded before giving up on a file
+const MAX_CHUNKS : usize = 100_000;
+
+const PNG_SIGNATURE : &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const COLOR_TYPE_NAMES : &[(u64, &str)] = &[
+    (0, "grayscale"), (2, "RGB"), (3, "indexed"), (4, "grayscale+alpha"), (6, "RGBA"),
+];
+
+const JPEG_MARKER_NAMES : &[(u64, &str)] = &[
+    (0xc0, "SOF0"), (0xc1, "SOF1"), (0xc2, "SOF2"), (0xc3, "SOF3"), (0xc4, "DHT"),
+    (0xc5, "SOF5"), (0xc6, "SOF6"), (0xc7, "SOF7"), (0xc9, "SOF9"), (0xca, "SOF10"),
+    (0xcb, "SOF11"), (0xcc, "DAC"), (0xcd, "SOF13"), (0xce, "SOF14"), (0xcf, "SOF15"),
+    (0xd8, "SOI"), (0xd9, "EOI"), (0xda, "SOS"), (0xdb, "DQT"), (0xdd, "DRI"),
+    (0xe0, "APP0"), (0xe1, "APP1"), (0xe2, "APP2"), (0xed, "APP13"), (0xee, "APP14"),
+    (0xfe, "COM"),
+];
+
+pub fn detect_png(data : &[u8]) -> Option<String> {
+    if ! data.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+    let f = Fields::new(data, 0, true);
+    match (f.peek(16, 4), f.peek(20, 4)) {
+        (Some(w), Some(h)) if &data[12..16] == b"IHDR" => Some(format!("PNG image, {} x {}", w, h)),
+        _ => Some("PNG image".to_string()),
+    }
+}
+
+fn png_header(data : &[u8], pos : usize) -> Option<Field> {
+    let mut f = Fields::new(data, pos, true);
+    let w = f.uint("width", 4)?;
+    let h = f.uint("height", 4)?;
+    f.uint("bit_depth", 1)?;
+    f.named("color_type", 1, COLOR_TYPE_NAMES)?;
+    f.uint("compression", 1)?;
+    f.uint("filter", 1)?;
+    f.uint("interlace", 1)?;
+    Some(f.into_field("data", format!("{} x {}", w, h)))
+}
+
+// signature followed by chunks of length, type, data and a CRC of the
+// type and data
+pub fn decode_png(data : &[u8]) -> Option<Overlay> {
+    detect_png(data)?;
+    let mut fields = vec![];
+    let mut locations = vec![];
+    let mut warnings = vec![];
+    let mut sig = Fields::new(data, 0, true);
+    sig.bytes("signature", PNG_SIGNATURE.len())?;
+    fields.append(&mut sig.fields);
+
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < data.len() && fields.len() < MAX_CHUNKS {
+        let index = fields.len() - 1;
+        let mut chunk = Fields::new(data, pos, true);
+        // the data must fit after the length and the type
+        let (len, ty) = match (chunk.uint("length", 4), chunk.text("type", 4)) {
+            (Some(len), Some(ty)) if len as usize <= data.len() - chunk.pos => (len as usize, String::from_utf8_lossy(ty).into_owned()),
+            _ => {
+                warnings.push(format!("truncated chunk at 0x{:x}", pos));
+                break;
+            }
+        };
+        let data_pos = chunk.pos;
+        if ty == "IHDR" && len == 13 {
+            chunk.push(png_header(data, data_pos)?);
+            chunk.pos += len;
+        } else if len > 0 {
+            chunk.bytes("data", len)?;
+        }
+        let expected = crc32(&data[data_pos-4..data_pos+len]);
+        let mut status = String::new();
+        match chunk.peek(chunk.pos, 4) {
+            Some(crc) => {
+                if crc as u32 != expected {
+                    status = format!(" BAD CRC, expected 0x{:08x}", expected);
+                    warnings.push(format!("bad CRC in chunk {} {}", index, ty));
+                }
+                chunk.uint_with("crc", 4, |v| format!("0x{:08x}{}", v, status))?;
+            }
+            None => {
+                warnings.push(format!("chunk {} {} has no CRC", index, ty));
+                status = " NO CRC".to_string();
+            }
+        }
+        pos = chunk.pos;
+        locations.push((format!("chunk {} {}{}", index, ty, status), chunk.start()));
+        fields.push(chunk.into_field(&format!("[{}] {}", index, ty), format!("{} bytes{}", len, status)));
+        if ty == "IEND" {
+            break;
+        }
+    }
+    let mut overlay = Overlay::new("PNG", fields);
+    overlay.locations = locations;
+    overlay.warnings = warnings;
+    Some(overlay)
+}
+
+pub fn detect_jpeg(data : &[u8]) -> Option<String> {
+    if data.starts_with(b"\xff\xd8\xff") {
+        Some("JPEG image".to_string())
+    } else {
+        None
+    }
+}
+
+// markers without a length field
+fn is_standalone(marker : u8) -> bool {
+    marker == 0x01 || marker == 0xd8 || marker == 0xd9 || (0xd0..=0xd7).contains(&marker)
+}
+
+fn marker_name(marker : u8) -> String {
+    match JPEG_MARKER_NAMES.iter().find(|n| n.0 == marker as u64) {
+        Some(n) => n.1.to_string(),
+        None if (0xd0..=0xd7).contains(&marker) => format!("RST{}", marker - 0xd0),
+        None if (0xe0..=0xef).contains(&marker) => format!("APP{}", marker - 0xe0),
+        None => format!("0x{:02x}", marker),
+    }
+}
+
+// end of the entropy coded data that follows a scan header: the next
+// marker other than a stuffed 0xff or a restart marker
+fn scan_end(data : &[u8], mut pos : usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xff && data[pos+1] != 0 && ! (0xd0..=0xd7).contains(&data[pos+1]) {
+            return pos;
+        }
+        pos += 1;
+    }
+    data.len()
+}
+
+// markers, each 0xff and a code followed by a big endian length that
+// counts itself; scans are followed by the compressed image data
+pub fn decode_jpeg(data : &[u8]) -> Option<Overlay> {
+    detect_jpeg(data)?;
+    let mut fields = vec![];
+    let mut locations = vec![];
+    let mut warnings = vec![];
+    let mut pos = 0;
+    while pos + 1 < data.len() && fields.len() < MAX_CHUNKS {
+        if data[pos] != 0xff {
+            warnings.push(format!("expected a marker at 0x{:x}", pos));
+            break;
+        }
+        let marker = data[pos+1];
+        if marker == 0xff {
+            // fill byte
+            pos += 1;
+            continue;
+        }
+        let name = marker_name(marker);
+        let mut seg = Fields::new(data, pos, true);
+        seg.uint_with("marker", 2, |_| name.clone())?;
+        let mut value = String::new();
+        if ! is_standalone(marker) {
+            let len = match seg.uint("length", 2) {
+                Some(len) if len >= 2 && (len - 2) as usize <= data.len() - seg.pos => len as usize - 2,
+                _ => {
+                    warnings.push(format!("truncated {} segment at 0x{:x}", name, pos));
+                    break;
+                }
+            };
+            value = format!("{} bytes", len);
+            if len > 0 {
+                seg.bytes("data", len)?;
+                if name.starts_with("APP") {
+                    // the identifier string, like "JFIF" or "Exif"
+                    let id : Vec<u8> = data[seg.pos-len..seg.pos].iter().take(16).take_while(|&&b| b != 0).cloned().collect();
+                    value = format!("{} {}", format_bytes(&id, true), value);
+                }
+            }
+        }
+        pos = seg.pos;
+        locations.push((format!("marker {} {}", fields.len(), name), seg.start()));
+        fields.push(seg.into_field(&format!("[{}] {}", fields.len(), name), value));
+        if marker == 0xda {
+            let end = scan_end(data, pos);
+            let name = format!("[{}] scan data", fields.len());
+            fields.push(Field::new(&name, format!("{} bytes", end - pos), pos, end - pos));
+            pos = end;
+        }
+        if marker == 0xd9 {
+            break;
+        }
+    }
+    if pos < data.len() {
+        let name = format!("[{}] trailing data", fields.len());
+        locations.push(("trailing data".to_string(), pos));
+        fields.push(Field::new(&name, format!("{} bytes", data.len() - pos), pos, data.len() - pos));
+    }
+    let mut overlay = Overlay::new("JPEG", fields);
+    overlay.locations = locations;
+    overlay.warnings = warnings;
+    Some(overlay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(ty : &[u8], data : &[u8]) -> Vec<u8> {
+        let mut out = (data.len() as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(ty);
+        out.extend_from_slice(data);
+        out.extend_from_slice(&crc32(&out[4..]).to_be_bytes());
+        out
+    }
+
+    fn png() -> Vec<u8> {
+        let mut out = PNG_SIGNATURE.to_vec();
+        out.extend(chunk(b"IHDR", &[0, 0, 0, 2, 0, 0, 0, 3, 8, 6, 0, 0, 0]));
+        out.extend(chunk(b"IDAT", b"not really compressed"));
+        out.extend(chunk(b"IEND", b""));
+        out
+    }
+
+    fn jpeg() -> Vec<u8> {
+        let mut out = b"\xff\xd8\xff\xe0\x00\x07JFIF\x00".to_vec();
+        out.extend_from_slice(b"\xff\xda\x00\x02\x12\x34\xff\x00\x56\xff\xd9");
+        out
+    }
+
+    #[test]
+    fn png_chunks() {
+        let data = png();
+        assert_eq!(detect_png(&data).unwrap(), "PNG image, 2 x 3");
+        let overlay = decode_png(&data).unwrap();
+        assert_eq!(overlay.warnings.len(), 0, "{:?}", overlay.warnings);
+        let names : Vec<&str> = overlay.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["signature", "[0] IHDR", "[1] IDAT", "[2] IEND"]);
+    }
+
+    #[test]
+    fn png_bad_crc() {
+        let mut data = png();
+        data[8 + 25 + 8] ^= 1;
+        let overlay = decode_png(&data).unwrap();
+        assert_eq!(overlay.warnings, ["bad CRC in chunk 1 IDAT"]);
+    }
+
+    // a file cut anywhere still shows the chunks before the cut
+    #[test]
+    fn truncated_png() {
+        let data = png();
+        let idat = 8 + 25;
+        for len in PNG_SIGNATURE.len()..data.len() {
+            let overlay = decode_png(&data[..len]).unwrap();
+            if len > idat && len < data.len() - 12 {
+                assert_eq!(overlay.fields[1].name, "[0] IHDR");
+                assert!(! overlay.warnings.is_empty());
+            }
+        }
+        for len in 0..PNG_SIGNATURE.len() {
+            assert!(decode_png(&data[..len]).is_none());
+        }
+    }
+
+    #[test]
+    fn jpeg_markers() {
+        let overlay = decode_jpeg(&jpeg()).unwrap();
+        assert_eq!(overlay.warnings.len(), 0, "{:?}", overlay.warnings);
+        let names : Vec<&str> = overlay.fields.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["[0] SOI", "[1] APP0", "[2] SOS", "[3] scan data", "[4] EOI"]);
+        assert_eq!(overlay.fields[3].len, 5);
+    }
+
+    #[test]
+    fn truncated_jpeg() {
+        let data = jpeg();
+        for len in 0..data.len() {
+            decode_jpeg(&data[..len]);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+
+// decoder for deflate streams (RFC 1951), used to find where compressed
+// data ends and to check its CRC
+
+const LENGTH_BASE : [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA : [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE : [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA : [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+// order of the code length code lengths in a dynamic block header
+const CLEN_ORDER : [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    data : &'a [u8],
+    pos : usize,
+    bit : u32,
+}
+
+impl<'a> Bits<'a> {
+
+    fn read(&mut self, n : u32) -> Result<u32, String> {
+        let mut v = 0;
+        for i in 0..n {
+            let byte = *self.data.get(self.pos).ok_or("unexpected end of compressed data")?;
+            v |= (((byte >> self.bit) & 1) as u32) << i;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+        Ok(v)
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+
+}
+
+// canonical Huffman code: number of codes of each length and the symbols
+// sorted by code
+struct Huffman {
+    counts : [u16; 16],
+    symbols : Vec<u16>,
+}
+
+impl Huffman {
+
+    fn new(lengths : &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len-1] + counts[len-1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len > 0 {
+                symbols[offsets[len as usize] as usize] = sym as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits : &mut Bits) -> Result<u16, String> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..16 {
+            code |= bits.read(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).cloned().ok_or_else(|| "invalid code".to_string());
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (i, len) in lengths.iter_mut().enumerate() {
+        *len = match i { 0..=143 => 8, 144..=255 => 9, 256..=279 => 7, _ => 8 };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(bits : &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let n_lit = bits.read(5)? as usize + 257;
+    let n_dist = bits.read(5)? as usize + 1;
+    let n_clen = bits.read(4)? as usize + 4;
+    let mut clen = [0u8; 19];
+    for &i in CLEN_ORDER.iter().take(n_clen) {
+        clen[i] = bits.read(3)? as u8;
+    }
+    let clen_code = Huffman::new(&clen);
+    let mut lengths = vec![];
+    while lengths.len() < n_lit + n_dist {
+        let (len, repeat) = match clen_code.decode(bits)? {
+            sym @ 0..=15 => (sym as u8, 1),
+            16 => (*lengths.last().ok_or("repeat without a length")?, 3 + bits.read(2)?),
+            17 => (0, 3 + bits.read(3)?),
+            _ => (0, 11 + bits.read(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(len);
+        }
+    }
+    if lengths.len() > n_lit + n_dist {
+        return Err("too many code lengths".to_string());
+    }
+    Ok((Huffman::new(&lengths[..n_lit]), Huffman::new(&lengths[n_lit..])))
+}
+
+// back-references reach at most this far, so only the last this many bytes
+// of the output are kept
+const WINDOW_SIZE : usize = 32768;
+
+const CRC_TABLE : [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+// size and CRC-32 of the output of a deflate stream, and the number of
+// bytes of the stream
+pub struct Inflated {
+    pub size : usize,
+    pub crc : u32,
+    pub len : usize,
+}
+
+// output seen through the window, with the (inverted) CRC of all of it
+struct Output {
+    window : Vec<u8>,
+    size : usize,
+    crc : u32,
+}
+
+impl Output {
+
+    fn push(&mut self, b : u8) {
+        self.window[self.size % WINDOW_SIZE] = b;
+        self.size += 1;
+        self.crc = crc32_add(self.crc, b);
+    }
+
+    fn copy(&mut self, back : usize, len : usize) -> Result<(), String> {
+        if back > self.size {
+            return Err("distance too far back".to_string());
+        }
+        for _ in 0..len {
+            let b = self.window[(self.size - back) % WINDOW_SIZE];
+            self.push(b);
+        }
+        Ok(())
+    }
+
+}
+
+// decompresses the deflate stream at the start of `data` without keeping
+// the whole output
+pub fn inflate(data : &[u8]) -> Result<Inflated, String> {
+    let mut bits = Bits { data, pos : 0, bit : 0 };
+    let mut out = Output { window : vec![0; WINDOW_SIZE], size : 0, crc : ! 0 };
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let len = bits.read(16)? as usize;
+                bits.read(16)?;
+                let block = data.get(bits.pos..bits.pos+len).ok_or("unexpected end of compressed data")?;
+                for &b in block {
+                    out.push(b);
+                }
+                bits.pos += len;
+            }
+            kind @ 1..=2 => {
+                let (lit, dist) = if kind == 1 { fixed_codes() } else { dynamic_codes(&mut bits)? };
+                loop {
+                    let sym = lit.decode(&mut bits)? as usize;
+                    if sym < 256 {
+                        out.push(sym as u8);
+                    } else if sym == 256 {
+                        break;
+                    } else {
+                        let i = sym - 257;
+                        if i >= LENGTH_BASE.len() {
+                            return Err("invalid length code".to_string());
+                        }
+                        let len = LENGTH_BASE[i] as usize + bits.read(LENGTH_EXTRA[i] as u32)? as usize;
+                        let d = dist.decode(&mut bits)? as usize;
+                        if d >= DIST_BASE.len() {
+                            return Err("invalid distance code".to_string());
+                        }
+                        let back = DIST_BASE[d] as usize + bits.read(DIST_EXTRA[d] as u32)? as usize;
+                        out.copy(back, len)?;
+                    }
+                }
+            }
+            _ => return Err("invalid block type".to_string()),
+        }
+        if last {
+            break;
+        }
+    }
+    bits.align();
+    Ok(Inflated { size : out.size, crc : ! out.crc, len : bits.pos })
+}
+
+// adds a byte to a CRC-32 that is kept inverted while it's computed
+fn crc32_add(c : u32, b : u8) -> u32 {
+    CRC_TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+}
+
+// CRC-32 as used by PNG, ZIP and gzip
+pub fn crc32(data : &[u8]) -> u32 {
+    ! data.iter().fold(! 0u32, |c, &b| crc32_add(c, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // deflate streams made with zlib
+    const FIXED : &[u8] = &[0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+    const DYNAMIC : &[u8] = &[
+        0xed, 0xd2, 0xb1, 0x0d, 0x83, 0x40, 0x10, 0x44, 0xd1, 0x9c, 0x2a, 0xae, 0x04, 0x66, 0xd6, 0x06,
+        0x53, 0xd0, 0x21, 0x59, 0x02, 0x13, 0x40, 0x40, 0xf9, 0x96, 0x43, 0x4b, 0xbf, 0x84, 0x49, 0x27,
+        0xda, 0xa7, 0xfd, 0xdb, 0xfb, 0xd3, 0xdb, 0xd8, 0x8e, 0xb5, 0x9d, 0xc7, 0xde, 0xdb, 0xd5, 0xef,
+        0x6b, 0xd8, 0x7e, 0x9b, 0x60, 0x33, 0x6c, 0x05, 0xdb, 0x03, 0xb6, 0x27, 0x6c, 0x13, 0x6c, 0x33,
+        0x6c, 0x2f, 0xd8, 0x16, 0xba, 0x19, 0x21, 0x24, 0x11, 0x51, 0x44, 0x16, 0x11, 0x46, 0xa4, 0x11,
+        0x71, 0x44, 0x1e, 0x11, 0x48, 0x24, 0x32, 0x89, 0x8c, 0xbf, 0x21, 0x91, 0x49, 0x64, 0x12, 0x99,
+        0x44, 0x26, 0x91, 0x49, 0x64, 0x12, 0x99, 0x44, 0x45, 0xa2, 0x22, 0x51, 0x61, 0x6e, 0x24, 0x2a,
+        0x12, 0x15, 0x89, 0x8a, 0x44, 0xc9, 0x3f, 0xf9, 0x27, 0xff, 0xe4, 0x9f, 0xfc, 0x93, 0x7f, 0xf2,
+        0x4f, 0xfe, 0xc9, 0x3f, 0xf9, 0x27, 0xff, 0xe4, 0x9f, 0xfc, 0xff, 0xc7, 0x2f,
+    ];
+
+    fn dynamic_text() -> Vec<u8> {
+        (0..200).flat_map(|i| format!("line {} of some text\n", i % 37).into_bytes()).collect()
+    }
+
+    // writes fixed Huffman codes, most significant bit first
+    struct Writer {
+        out : Vec<u8>,
+        bit : u32,
+    }
+
+    impl Writer {
+
+        fn bits(&mut self, v : u32, n : u32) {
+            for i in 0..n {
+                if self.bit == 0 {
+                    self.out.push(0);
+                }
+                *self.out.last_mut().unwrap() |= (((v >> i) & 1) as u8) << self.bit;
+                self.bit = (self.bit + 1) % 8;
+            }
+        }
+
+        fn code(&mut self, code : u32, n : u32) {
+            for i in (0..n).rev() {
+                self.bits(code >> i, 1);
+            }
+        }
+
+        fn symbol(&mut self, sym : u32) {
+            match sym {
+                0..=143 => self.code(0x30 + sym, 8),
+                256..=279 => self.code(sym - 256, 7),
+                _ => self.code(0xc0 + sym - 280, 8),
+            }
+        }
+
+    }
+
+    // a fixed block with `literal` and then `copies` copies of 258 bytes
+    // from `back` bytes back
+    fn repeats(literal : u8, copies : usize, back : u32) -> Vec<u8> {
+        let mut w = Writer { out : vec![], bit : 0 };
+        w.bits(1, 1);
+        w.bits(1, 2);
+        w.symbol(literal as u32);
+        for _ in 0..copies {
+            w.symbol(285);
+            let d = DIST_BASE.iter().rposition(|&b| b as u32 <= back).unwrap();
+            w.code(d as u32, 5);
+            w.bits(back - DIST_BASE[d] as u32, DIST_EXTRA[d] as u32);
+        }
+        w.symbol(256);
+        w.out
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+
+    #[test]
+    fn stored_block() {
+        let data = [1, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0xaa];
+        let r = inflate(&data).unwrap();
+        assert_eq!((r.size, r.crc, r.len), (5, crc32(b"hello"), 10));
+    }
+
+    #[test]
+    fn fixed_block() {
+        let r = inflate(FIXED).unwrap();
+        assert_eq!((r.size, r.crc, r.len), (17, crc32(b"hello hello hello"), FIXED.len()));
+    }
+
+    #[test]
+    fn dynamic_block() {
+        let text = dynamic_text();
+        let r = inflate(DYNAMIC).unwrap();
+        assert_eq!((r.size, r.crc, r.len), (text.len(), crc32(&text), DYNAMIC.len()));
+    }
+
+    // output much longer than the window
+    #[test]
+    fn long_output() {
+        let r = inflate(&repeats(b'a', 1000, 1)).unwrap();
+        assert_eq!(r.size, 258_001);
+        assert_eq!(r.crc, crc32(&vec![b'a'; 258_001]));
+    }
+
+    // copies from the far end of the window once it has wrapped around
+    #[test]
+    fn window_wraps() {
+        let text : Vec<u8> = (0..40000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut data = vec![0, 0x40, 0x9c, 0xbf, 0x63];
+        data.extend_from_slice(&text);
+        data.extend(repeats(b'x', 2, 32768));
+        let mut expected = text.clone();
+        expected.push(b'x');
+        for _ in 0..2 * 258 {
+            let b = expected[expected.len() - 32768];
+            expected.push(b);
+        }
+        let r = inflate(&data).unwrap();
+        assert_eq!((r.size, r.crc), (expected.len(), crc32(&expected)));
+    }
+
+    #[test]
+    fn too_far_back() {
+        assert!(inflate(&repeats(b'a', 1, 1)).is_ok());
+        assert_eq!(inflate(&repeats(b'a', 1, 2)).err().unwrap(), "distance too far back");
+    }
+
+    #[test]
+    fn truncated_streams() {
+        for data in &[FIXED, DYNAMIC] {
+            for len in 0..data.len() {
+                assert!(inflate(&data[..len]).is_err());
+            }
+        }
+    }
+}
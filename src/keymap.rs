@@ -0,0 +1,196 @@
+
+use super::term::*;
+use super::term::Special::*;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    Redraw,
+    SwitchPane,
+    Help,
+    WriteFile,
+    ReadFile,
+    LineStart,
+    LineEnd,
+    Up,
+    Down,
+    Left,
+    Right,
+    FileStart,
+    FileEnd,
+    PageUp,
+    PageDown,
+    PrevFile,
+    NextFile,
+    ClearSelection,
+    ToggleByteColors,
+    Find,
+    FindNext,
+    GoTo,
+    Copy,
+    Paste,
+    Command,
+    RecordMacro,
+    RunMacro,
+    RepeatMacro,
+    SaveMacro,
+    LoadMacro,
+    BufferList,
+    CloseFile,
+    SplitView,
+    CloseView,
+    NextView,
+    ApplyTemplate,
+    StructureTree,
+    DecodeFormat,
+    GoToLocation,
+    SetBase,
+    Strings,
+    Analysis,
+    Minimap,
+    SetBookmark,
+    Bookmarks,
+}
+
+const ACTION_NAMES : &[(&str, Action)] = &[
+    ("quit",            Action::Quit),
+    ("redraw",          Action::Redraw),
+    ("switch-pane",     Action::SwitchPane),
+    ("help",            Action::Help),
+    ("write-file",      Action::WriteFile),
+    ("read-file",       Action::ReadFile),
+    ("line-start",      Action::LineStart),
+    ("line-end",        Action::LineEnd),
+    ("up",              Action::Up),
+    ("down",            Action::Down),
+    ("left",            Action::Left),
+    ("right",           Action::Right),
+    ("file-start",      Action::FileStart),
+    ("file-end",        Action::FileEnd),
+    ("page-up",         Action::PageUp),
+    ("page-down",       Action::PageDown),
+    ("prev-file",       Action::PrevFile),
+    ("next-file",       Action::NextFile),
+    ("clear-selection", Action::ClearSelection),
+    ("toggle-byte-colors", Action::ToggleByteColors),
+    ("find",            Action::Find),
+    ("find-next",       Action::FindNext),
+    ("go-to",           Action::GoTo),
+    ("copy",            Action::Copy),
+    ("paste",           Action::Paste),
+    ("command",         Action::Command),
+    ("record-macro",    Action::RecordMacro),
+    ("run-macro",       Action::RunMacro),
+    ("repeat-macro",    Action::RepeatMacro),
+    ("save-macro",      Action::SaveMacro),
+    ("load-macro",      Action::LoadMacro),
+    ("buffer-list",     Action::BufferList),
+    ("close-file",      Action::CloseFile),
+    ("split-view",      Action::SplitView),
+    ("close-view",      Action::CloseView),
+    ("next-view",       Action::NextView),
+    ("apply-template",  Action::ApplyTemplate),
+    ("structure-tree",  Action::StructureTree),
+    ("decode-format",   Action::DecodeFormat),
+    ("go-to-location",  Action::GoToLocation),
+    ("set-base",        Action::SetBase),
+    ("strings",         Action::Strings),
+    ("analysis",        Action::Analysis),
+    ("minimap",         Action::Minimap),
+    ("set-bookmark",    Action::SetBookmark),
+    ("bookmarks",       Action::Bookmarks),
+];
+
+impl Action {
+
+    pub fn from_name(name : &str) -> Option<Action> {
+        ACTION_NAMES.iter().find(|e| e.0 == name).map(|e| e.1)
+    }
+
+}
+
+pub struct KeyMap {
+    bindings : Vec<(Key, Action)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::new()
+    }
+}
+
+impl KeyMap {
+
+    pub fn new() -> KeyMap {
+        let mut keymap = KeyMap { bindings : vec![] };
+        keymap.bind(Key::Ctrl('x'), Action::Quit);
+        keymap.bind(Key::Ctrl('l'), Action::Redraw);
+        keymap.bind(Key::Tab, Action::SwitchPane);
+        keymap.bind(Key::Ctrl('g'), Action::Help);
+        keymap.bind(Key::Ctrl('o'), Action::WriteFile);
+        keymap.bind(Key::Ctrl('r'), Action::ReadFile);
+        keymap.bind(Key::Ctrl('a'), Action::LineStart);
+        keymap.bind(Key::Special(Home, MOD_NONE), Action::LineStart);
+        keymap.bind(Key::Ctrl('e'), Action::LineEnd);
+        keymap.bind(Key::Special(End, MOD_NONE), Action::LineEnd);
+        keymap.bind(Key::Special(Up, MOD_NONE), Action::Up);
+        keymap.bind(Key::Special(Down, MOD_NONE), Action::Down);
+        keymap.bind(Key::Special(Left, MOD_NONE), Action::Left);
+        keymap.bind(Key::Backspace, Action::Left);
+        keymap.bind(Key::Special(Right, MOD_NONE), Action::Right);
+        keymap.bind(Key::Special(Home, MOD_CTRL), Action::FileStart);
+        keymap.bind(Key::Special(End, MOD_CTRL), Action::FileEnd);
+        keymap.bind(Key::Special(PageUp, MOD_NONE), Action::PageUp);
+        keymap.bind(Key::Special(PageDown, MOD_NONE), Action::PageDown);
+        keymap.bind(Key::Alt(','), Action::PrevFile);
+        keymap.bind(Key::Alt('.'), Action::NextFile);
+        keymap.bind(Key::Esc, Action::ClearSelection);
+        keymap.bind(Key::Alt('c'), Action::ToggleByteColors);
+        keymap.bind(Key::Ctrl('w'), Action::Find);
+        keymap.bind(Key::Alt('w'), Action::FindNext);
+        keymap.bind(Key::Ctrl('_'), Action::GoTo);
+        keymap.bind(Key::Alt('6'), Action::Copy);
+        keymap.bind(Key::Ctrl('u'), Action::Paste);
+        keymap.bind(Key::Alt('x'), Action::Command);
+        keymap.bind(Key::Alt(':'), Action::RecordMacro);
+        keymap.bind(Key::Alt(';'), Action::RunMacro);
+        keymap.bind(Key::Alt('\''), Action::RepeatMacro);
+        keymap.bind(Key::Alt('k'), Action::SaveMacro);
+        keymap.bind(Key::Alt('j'), Action::LoadMacro);
+        keymap.bind(Key::Alt('l'), Action::BufferList);
+        keymap.bind(Key::Alt('q'), Action::CloseFile);
+        keymap.bind(Key::Alt('2'), Action::SplitView);
+        keymap.bind(Key::Alt('0'), Action::CloseView);
+        keymap.bind(Key::Alt('o'), Action::NextView);
+        keymap.bind(Key::Alt('m'), Action::ApplyTemplate);
+        keymap.bind(Key::Alt('t'), Action::StructureTree);
+        keymap.bind(Key::Alt('f'), Action::DecodeFormat);
+        keymap.bind(Key::Alt('g'), Action::GoToLocation);
+        keymap.bind(Key::Alt('b'), Action::SetBase);
+        keymap.bind(Key::Alt('s'), Action::Strings);
+        keymap.bind(Key::Alt('e'), Action::Analysis);
+        keymap.bind(Key::Alt('n'), Action::Minimap);
+        keymap.bind(Key::Alt('a'), Action::SetBookmark);
+        keymap.bind(Key::Alt('p'), Action::Bookmarks);
+        keymap
+    }
+
+    pub fn bind(&mut self, key : Key, action : Action) {
+        self.unbind(&key);
+        self.bindings.push((key, action));
+    }
+
+    pub fn unbind(&mut self, key : &Key) {
+        self.bindings.retain(|b| b.0 != *key);
+    }
+
+    pub fn lookup(&self, key : &Key) -> Option<Action> {
+        self.bindings.iter().find(|b| b.0 == *key).map(|b| b.1)
+    }
+
+    // first key bound to `action`
+    pub fn key_for(&self, action : Action) -> Option<&Key> {
+        self.bindings.iter().find(|b| b.1 == action).map(|b| &b.0)
+    }
+
+}
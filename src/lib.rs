@@ -1,9 +1,32 @@
 extern crate termios;
 extern crate libc;
 
-#[macro_use]
 pub mod term;
 pub mod screen;
 pub mod editor;
 pub mod file;
+pub mod view;
 pub mod file_sel;
+pub mod buffer_list;
+pub mod keymap;
+pub mod config;
+pub mod batch;
+pub mod script;
+pub mod macros;
+pub mod history;
+pub mod overlay;
+pub mod template;
+pub mod tree_panel;
+pub mod list_popup;
+pub mod formats;
+pub mod elf;
+pub mod pe;
+pub mod inflate;
+pub mod image;
+pub mod archive;
+pub mod disk;
+pub mod magic;
+pub mod strings;
+pub mod analysis;
+pub mod minimap;
+pub mod bookmarks;
@@ -0,0 +1,190 @@
+
+use super::screen::*;
+use super::term::*;
+use super::editor::Editor;
+
+// popup listing `items`; typing filters the list to the items containing
+// the text, Enter returns the index of the chosen item
+pub struct ListPopup<'a, 'b : 'a> {
+    editor : &'a mut Editor<'b>,
+    title : String,
+    items : Vec<String>,
+    matches : Vec<usize>,
+    quit : bool,
+    sel_index : usize,
+    top_index : usize,
+    filter : String,
+}
+
+impl<'a, 'b> ListPopup<'a, 'b> {
+
+    pub fn new(editor : &'a mut Editor<'b>, title : &str, items : Vec<String>) -> ListPopup<'a, 'b> {
+        let matches = (0..items.len()).collect();
+        ListPopup {
+            editor,
+            title : title.to_string(),
+            items,
+            matches,
+            quit : false,
+            sel_index : 0,
+            top_index : 0,
+            filter : String::new(),
+        }
+    }
+
+    // starts with the item at `index` selected
+    pub fn select(mut self, index : usize) -> Self {
+        if let Some(i) = self.matches.iter().position(|&m| m == index) {
+            self.sel_index = i;
+        }
+        self
+    }
+
+    // position and size of the popup: (x, y, w, h), h counting only list lines
+    fn geometry(&self) -> (i32, i32, i32, i32) {
+        let screen = &self.editor.screen;
+        let w = (screen.w - 4).clamp(20, 100);
+        let h = (screen.h - 8).max(1);
+        ((screen.w - w) / 2 + 1, (screen.h - h - 3) / 2 + 1, w, h)
+    }
+
+    fn draw_line(&self, x : i32, y : i32, w : i32, text : &str) {
+        self.editor.screen.move_cursor(x, y);
+        let width = (w - 2).max(0) as usize;
+        let text : String = text.chars().take(width).collect();
+        print!(" {:1$} ", text, width);
+    }
+
+    fn draw(&mut self) {
+        let (x, y, w, h) = self.geometry();
+        let theme = self.editor.screen.theme;
+
+        set_color_pair(theme.header);
+        let title = format!("{} ({} of {})", self.title, self.matches.len(), self.items.len());
+        self.draw_line(x, y, w, &title);
+        for line in 0..h {
+            let index = self.top_index + line as usize;
+            let text = self.matches.get(index).map_or("", |&i| self.items[i].as_str());
+            set_color_pair(if index == self.sel_index { theme.tab_active } else { theme.message });
+            self.draw_line(x, y + 1 + line, w, text);
+        }
+        set_color_pair(theme.header);
+        let filter = format!("Filter: {}", self.filter);
+        self.draw_line(x, y + 1 + h, w, &filter);
+        reset_color();
+        flush_screen();
+    }
+
+    fn ensure_sel_visible(&mut self) {
+        let (_, _, _, h) = self.geometry();
+        let h = h as usize;
+        if self.sel_index < self.top_index {
+            self.top_index = self.sel_index;
+        } else if self.sel_index >= self.top_index + h {
+            self.top_index = self.sel_index + 1 - h;
+        }
+    }
+
+    fn update_filter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.matches = (0..self.items.len())
+            .filter(|&i| self.items[i].to_lowercase().contains(&filter))
+            .collect();
+        self.sel_index = 0;
+        self.top_index = 0;
+    }
+
+    fn process_input(&mut self) -> Option<usize> {
+        let (_, _, _, h) = self.geometry();
+        let page = (h as usize).max(2) - 1;
+        let last = self.matches.len().saturating_sub(1);
+        match self.editor.read_key() {
+            Key::Ctrl('c') | Key::Esc => self.quit = true,
+            Key::Enter => {
+                self.quit = true;
+                return self.matches.get(self.sel_index).cloned();
+            }
+            Key::Special(Special::Up, MOD_NONE) if self.sel_index > 0 => self.sel_index -= 1,
+            Key::Special(Special::Down, MOD_NONE) if self.sel_index < last => self.sel_index += 1,
+            Key::Special(Special::PageUp, MOD_NONE) => self.sel_index = self.sel_index.saturating_sub(page),
+            Key::Special(Special::PageDown, MOD_NONE) => self.sel_index = (self.sel_index + page).min(last),
+            Key::Special(Special::Home, MOD_NONE) => self.sel_index = 0,
+            Key::Special(Special::End, MOD_NONE) => self.sel_index = last,
+            Key::Backspace => {
+                self.filter.pop();
+                self.update_filter();
+            }
+            Key::Char(c) => {
+                self.filter.push(c);
+                self.update_filter();
+            }
+            _ => (),
+        }
+        self.ensure_sel_visible();
+        None
+    }
+
+    // returns the index in `items` of the chosen item
+    pub fn choose(&mut self) -> Option<usize> {
+        self.ensure_sel_visible();
+        let mut selected = None;
+        while ! self.quit && ! self.editor.quit {
+            self.draw();
+            selected = self.process_input();
+        }
+        clear_screen();
+        self.editor.screen.redraw_needed = true;
+        selected
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    // item chosen after typing `keys` in a popup of `items`
+    fn choose(items : &[&str], index : usize, keys : &[u8]) -> Option<usize> {
+        let mut editor = Editor::new(Box::new(keys), -1);
+        editor.screen.w = 80;
+        editor.screen.h = 24;
+        let items = items.iter().map(|s| s.to_string()).collect();
+        ListPopup::new(&mut editor, "Test", items).select(index).choose()
+    }
+
+    #[test]
+    fn navigation() {
+        let items = ["one", "two", "three"];
+        assert_eq!(choose(&items, 0, b"\r"), Some(0));
+        assert_eq!(choose(&items, 2, b"\r"), Some(2));
+        assert_eq!(choose(&items, 0, b"\x1b[B\x1b[B\x1b[B\r"), Some(2));
+        assert_eq!(choose(&items, 1, b"\x1b[A\x1b[A\r"), Some(0));
+        assert_eq!(choose(&items, 0, b"\x1b[6~\r"), Some(2));
+        assert_eq!(choose(&items, 0, b"\x03"), None);
+        assert_eq!(choose(&[], 0, b"\r"), None);
+    }
+
+    #[test]
+    fn filter() {
+        let items = ["one", "two", "Three"];
+        assert_eq!(choose(&items, 0, b"t\r"), Some(1));
+        assert_eq!(choose(&items, 0, b"t\x1b[B\r"), Some(2));
+        assert_eq!(choose(&items, 0, b"th\r"), Some(2));
+        assert_eq!(choose(&items, 0, b"thx\x7f\r"), Some(2));
+        assert_eq!(choose(&items, 0, b"x\r"), None);
+    }
+
+    #[test]
+    fn long_list() {
+        let items : Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let items : Vec<&str> = items.iter().map(|s| s.as_str()).collect();
+        assert_eq!(choose(&items, 99, b"\x1b[B\r"), Some(99));
+        assert_eq!(choose(&items, 50, b"\x1b[5~\x1b[5~\x1b[5~\x1b[5~\x1b[5~\x1b[5~\r"), Some(0));
+        let mut editor = Editor::new(Box::new(io::empty()), -1);
+        editor.screen.h = 24;
+        let mut popup = ListPopup::new(&mut editor, "Test", items.iter().map(|s| s.to_string()).collect()).select(99);
+        popup.ensure_sel_visible();
+        assert_eq!(popup.top_index, 99 + 1 - 16);
+    }
+}
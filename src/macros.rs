@@ -0,0 +1,63 @@
+
+use std::fs;
+use std::path::PathBuf;
+
+use super::term::{Key, key_name, parse_key_name};
+use super::config::config_dir;
+
+// macros are stored one key name per line in <config dir>/macros/<name>
+fn macro_path(name : &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(format!("invalid macro name: '{}'", name));
+    }
+    match config_dir() {
+        Some(dir) => Ok(dir.join("macros").join(name)),
+        None => Err("can't find the configuration directory".to_string()),
+    }
+}
+
+pub fn save_macro(name : &str, keys : &[Key]) -> Result<(), String> {
+    let path = macro_path(name)?;
+    let mut text = String::new();
+    for key in keys {
+        match key_name(key) {
+            Some(key) => {
+                text.push_str(&key);
+                text.push('\n');
+            }
+            None => return Err("macro contains keys that can't be saved".to_string()),
+        }
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("error creating {}: {}", dir.display(), e))?;
+    }
+    fs::write(&path, text).map_err(|e| format!("error writing {}: {}", path.display(), e))
+}
+
+pub fn load_macro(name : &str) -> Result<Vec<Key>, String> {
+    let path = macro_path(name)?;
+    let text = fs::read_to_string(&path).map_err(|e| format!("error reading {}: {}", path.display(), e))?;
+    let mut keys = vec![];
+    for (num, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        match parse_key_name(line) {
+            Some(key) => keys.push(key),
+            None => return Err(format!("{} line {}: invalid key: '{}'", path.display(), num+1, line)),
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_names() {
+        for name in &["", ".hidden", "a/b", "../x"] {
+            assert_eq!(macro_path(name).err().unwrap(), format!("invalid macro name: '{}'", name));
+        }
+    }
+}
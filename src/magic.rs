@@ -0,0 +1,199 @@
+
+use std::fs;
+use std::io;
+
+use super::config::config_dir;
+use super::batch::{parse_bytes, parse_number};
+use super::formats;
+
+// file types recognised by the bytes at an offset, for the formats that
+// have no built-in decoder; tried after the built-in decoders
+const SIGNATURES : &[(usize, &[u8], &str)] = &[
+    (0, b"%PDF-", "PDF document"),
+    (0, b"GIF87a", "GIF image"),
+    (0, b"GIF89a", "GIF image"),
+    (0, b"II*\x00", "TIFF image, little endian"),
+    (0, b"MM\x00*", "TIFF image, big endian"),
+    (8, b"WEBP", "WebP image"),
+    (8, b"WAVE", "WAVE audio"),
+    (8, b"AVI ", "AVI video"),
+    (0, b"RIFF", "RIFF container"),
+    (0, b"OggS", "Ogg container"),
+    (0, b"fLaC", "FLAC audio"),
+    (0, b"ID3", "MP3 audio with ID3 tag"),
+    (4, b"ftyp", "ISO media (MP4, MOV)"),
+    (0, b"\x1a\x45\xdf\xa3", "Matroska/WebM video"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7-zip archive"),
+    (0, b"Rar!\x1a\x07", "RAR archive"),
+    (0, b"BZh", "bzip2 compressed data"),
+    (0, b"\xfd7zXZ\x00", "xz compressed data"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd compressed data"),
+    (0, b"\x04\x22\x4d\x18", "LZ4 compressed data"),
+    (0, b"\x1f\x9d", "compress'd data"),
+    (0, b"MSCF", "Microsoft cabinet archive"),
+    (0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "OLE2 compound document (MS Office)"),
+    (0, b"SQLite format 3\x00", "SQLite 3 database"),
+    (0, b"\xca\xfe\xba\xbe", "Java class or Mach-O universal binary"),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O 64-bit executable"),
+    (0, b"\xce\xfa\xed\xfe", "Mach-O 32-bit executable"),
+    (0, b"\x00asm", "WebAssembly module"),
+    (0, b"dex\n", "Android DEX"),
+    (0, b"hsqs", "squashfs filesystem"),
+    (0, b"MZ", "DOS executable"),
+    (0, b"%!PS", "PostScript document"),
+    (0, b"{\\rtf", "RTF document"),
+    (0, b"<?xml", "XML document"),
+    (0, b"#!", "script"),
+];
+
+// a signature from the user's signature file
+struct Signature {
+    offset : usize,
+    magic : Vec<u8>,
+    description : String,
+    template : Option<String>,
+}
+
+#[derive(Clone)]
+pub struct FileType {
+    pub description : String,
+    // template to apply for the overlay, from the user's signature file
+    pub template : Option<String>,
+}
+
+// signature database: the user's signatures, then the built-in decoders,
+// then the built-in signatures
+pub struct MagicDb {
+    signatures : Vec<Signature>,
+}
+
+impl Default for MagicDb {
+    fn default() -> MagicDb {
+        MagicDb::new()
+    }
+}
+
+impl MagicDb {
+
+    pub fn new() -> MagicDb {
+        MagicDb { signatures : vec![] }
+    }
+
+    // reads <config dir>/magic, which has lines like
+    // OFFSET DATA DESCRIPTION [=> TEMPLATE]
+    pub fn load(errors : &mut Vec<String>) -> MagicDb {
+        let mut db = MagicDb::new();
+        if let Some(path) = config_dir().map(|dir| dir.join("magic")) {
+            match fs::read_to_string(&path) {
+                Ok(text) => db.parse(&text, errors),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => errors.push(format!("Error reading {}: {}", path.display(), e)),
+            }
+        }
+        db
+    }
+
+    pub fn parse(&mut self, text : &str, errors : &mut Vec<String>) {
+        for (num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match parse_signature(line) {
+                Ok(sig) => self.signatures.push(sig),
+                Err(e) => errors.push(format!("magic line {}: {}", num+1, e)),
+            }
+        }
+    }
+
+    pub fn detect(&self, data : &[u8]) -> Option<FileType> {
+        let matches = |offset : usize, magic : &[u8]| match offset.checked_add(magic.len()) {
+            Some(end) => data.get(offset..end) == Some(magic),
+            None => false,
+        };
+        if let Some(sig) = self.signatures.iter().find(|s| matches(s.offset, &s.magic)) {
+            return Some(FileType { description : sig.description.clone(), template : sig.template.clone() });
+        }
+        let description = formats::detect(data)
+            .or_else(|| SIGNATURES.iter().find(|s| matches(s.0, s.1)).map(|s| s.2.to_string()))?;
+        Some(FileType { description, template : None })
+    }
+
+}
+
+// DATA is hex digits or a string in double quotes, like in batch commands
+fn parse_signature(line : &str) -> Result<Signature, String> {
+    let (offset, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => return Err("expected 'OFFSET DATA DESCRIPTION'".to_string()),
+    };
+    let offset = parse_number(offset)?;
+    let end = if let Some(quoted) = rest.strip_prefix('"') {
+        quoted.find('"').map(|i| i + 2).ok_or("unterminated string")?
+    } else {
+        rest.find(char::is_whitespace).unwrap_or(rest.len())
+    };
+    let magic = parse_bytes(&rest[..end])?;
+    if offset.checked_add(magic.len()).is_none() {
+        return Err("offset out of bounds".to_string());
+    }
+    let (description, template) = match rest[end..].find("=>") {
+        Some(i) => (&rest[end..end+i], Some(rest[end+i+2..].trim().to_string())),
+        None => (&rest[end..], None),
+    };
+    let description = description.trim();
+    if description.is_empty() {
+        return Err("missing description".to_string());
+    }
+    Ok(Signature { offset, magic, description : description.to_string(), template })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db(text : &str) -> MagicDb {
+        let mut db = MagicDb::new();
+        let mut errors = vec![];
+        db.parse(text, &mut errors);
+        assert_eq!(errors.len(), 0, "{:?}", errors);
+        db
+    }
+
+    #[test]
+    fn user_signatures() {
+        let db = db("# comment\n\n2 \"XY\" my format => my.tmpl\n0x0 0102 other format\n");
+        let t = db.detect(b"abXYz").unwrap();
+        assert_eq!(t.description, "my format");
+        assert_eq!(t.template.as_deref(), Some("my.tmpl"));
+        let t = db.detect(b"\x01\x02xy").unwrap();
+        assert_eq!(t.description, "other format");
+        assert_eq!(t.template, None);
+    }
+
+    #[test]
+    fn builtin_signatures() {
+        let db = MagicDb::new();
+        assert_eq!(db.detect(b"%PDF-1.4").unwrap().description, "PDF document");
+        assert_eq!(db.detect(b"RIFF\0\0\0\0WAVEfmt ").unwrap().description, "WAVE audio");
+        assert!(db.detect(b"RIFF\0\0").is_some());
+        assert!(db.detect(b"nothing").is_none());
+        assert!(db.detect(b"").is_none());
+    }
+
+    #[test]
+    fn bad_lines() {
+        let mut db = MagicDb::new();
+        let mut errors = vec![];
+        let max = usize::MAX;
+        db.parse(&format!("0\n0 zz bad\n0 \"ab bad\n0 abcd\n{} 0102 too far\n", max), &mut errors);
+        assert_eq!(errors, [
+            "magic line 1: expected 'OFFSET DATA DESCRIPTION'",
+            "magic line 2: invalid hex data: 'zz'",
+            "magic line 3: unterminated string",
+            "magic line 4: missing description",
+            "magic line 5: offset out of bounds",
+        ]);
+        assert!(db.detect(b"\x01\x02").is_none());
+    }
+}
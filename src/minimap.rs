@@ -0,0 +1,137 @@
+
+use super::screen::*;
+use super::file::File;
+use super::analysis::{entropy, entropy_color};
+
+// rows of big files are coloured from a few windows of bytes spread over
+// the part of the file they stand for
+const SAMPLE_WINDOWS : usize = 4;
+const WINDOW_SIZE : usize = 1024;
+
+// what the colours of the minimap column show
+#[derive(Copy, Clone, PartialEq)]
+pub enum MinimapMode {
+    Off,
+    Bytes,
+    Entropy,
+    Changes,
+}
+
+const MODE_NAMES : &[(&str, MinimapMode)] = &[
+    ("off",     MinimapMode::Off),
+    ("bytes",   MinimapMode::Bytes),
+    ("entropy", MinimapMode::Entropy),
+    ("changes", MinimapMode::Changes),
+];
+
+impl MinimapMode {
+
+    pub fn from_name(name : &str) -> Option<MinimapMode> {
+        MODE_NAMES.iter().find(|m| m.0 == name).map(|m| m.1)
+    }
+
+    pub fn name(self) -> &'static str {
+        MODE_NAMES.iter().find(|m| m.1 == self).map_or("", |m| m.0)
+    }
+
+    pub fn next(self) -> MinimapMode {
+        let i = MODE_NAMES.iter().position(|m| m.1 == self).unwrap_or(0);
+        MODE_NAMES[(i + 1) % MODE_NAMES.len()].1
+    }
+
+}
+
+// lines first..end of a file of `total` lines shown by row `row` of `rows`;
+// short files get one line per row
+pub fn row_lines(total : usize, rows : usize, row : usize) -> (usize, usize) {
+    if total <= rows {
+        (row.min(total), (row + 1).min(total))
+    } else {
+        (row * total / rows, (row + 1) * total / rows)
+    }
+}
+
+fn samples(data : &[u8], start : usize, end : usize) -> Vec<&[u8]> {
+    let len = end - start;
+    if len <= SAMPLE_WINDOWS * WINDOW_SIZE {
+        return vec![&data[start..end]];
+    }
+    let step = (len - WINDOW_SIZE) / (SAMPLE_WINDOWS - 1);
+    (0..SAMPLE_WINDOWS).map(|i| &data[start + i*step..start + i*step + WINDOW_SIZE]).collect()
+}
+
+// colour of the row standing for the bytes in start..end, None for the
+// default colours
+pub fn row_color(mode : MinimapMode, theme : &Theme, file : &File, start : usize, end : usize) -> Option<ColorPair> {
+    match mode {
+        MinimapMode::Off => None,
+        MinimapMode::Bytes => {
+            // the most common class of byte, as in the byte colours
+            let mut counts = [0usize; 6];
+            let mut example = [0u8; 6];
+            for window in samples(&file.data, start, end) {
+                for &b in window {
+                    let class = byte_class(b) as usize;
+                    counts[class] += 1;
+                    example[class] = b;
+                }
+            }
+            let class = (0..counts.len()).max_by_key(|&c| counts[c]).unwrap_or(0);
+            Some(theme.byte_color(example[class]))
+        }
+        MinimapMode::Entropy => {
+            let mut counts = [0usize; 256];
+            let mut len = 0;
+            for window in samples(&file.data, start, end) {
+                for &b in window {
+                    counts[b as usize] += 1;
+                }
+                len += window.len();
+            }
+            Some(entropy_color(theme, entropy(&counts, len)))
+        }
+        MinimapMode::Changes if file.is_changed(start, end) => Some(theme.selection),
+        MinimapMode::Changes => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_cover_every_line() {
+        assert_eq!(row_lines(3, 10, 1), (1, 2));
+        assert_eq!(row_lines(3, 10, 5), (3, 3));
+        for &(total, rows) in &[(10, 3), (1000, 7), (7, 7)] {
+            let mut next = 0;
+            for row in 0..rows {
+                let (first, end) = row_lines(total, rows, row);
+                assert_eq!(first, next);
+                assert!(end > first);
+                next = end;
+            }
+            assert_eq!(next, total);
+        }
+    }
+
+    #[test]
+    fn samples_of_big_ranges() {
+        let data = vec![0; 100_000];
+        assert_eq!(samples(&data, 10, 20).len(), 1);
+        let windows = samples(&data, 1000, 90_000);
+        assert_eq!(windows.len(), SAMPLE_WINDOWS);
+        assert!(windows.iter().all(|w| w.len() == WINDOW_SIZE));
+    }
+
+    #[test]
+    fn mode_names() {
+        let mut mode = MinimapMode::Off;
+        for _ in 0..MODE_NAMES.len() {
+            assert!(MinimapMode::from_name(mode.name()) == Some(mode));
+            mode = mode.next();
+        }
+        assert!(mode == MinimapMode::Off);
+        assert!(MinimapMode::from_name("fancy").is_none());
+    }
+}
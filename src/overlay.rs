@@ -0,0 +1,182 @@
+
+// a decoded field: a named byte range with a readable value; structs,
+// arrays and bitfields have the fields they contain as children
+pub struct Field {
+    pub name : String,
+    pub value : String,
+    pub start : usize,
+    pub len : usize,
+    pub children : Vec<Field>,
+}
+
+impl Field {
+
+    pub fn new(name : &str, value : String, start : usize, len : usize) -> Field {
+        Field {
+            name : name.to_string(),
+            value,
+            start,
+            len,
+            children : vec![],
+        }
+    }
+
+    pub fn contains(&self, pos : usize) -> bool {
+        pos >= self.start && pos < self.start + self.len
+    }
+
+}
+
+// a file range loaded at a virtual address
+#[derive(Copy, Clone)]
+pub struct Segment {
+    pub offset : usize,
+    pub size : usize,
+    pub addr : u64,
+}
+
+impl Segment {
+
+    pub fn offset_of(&self, addr : u64) -> Option<usize> {
+        if addr >= self.addr && addr - self.addr < self.size as u64 {
+            Some(self.offset + (addr - self.addr) as usize)
+        } else {
+            None
+        }
+    }
+
+}
+
+// the structure of a file as decoded by a template or a built-in parser
+pub struct Overlay {
+    pub name : String,
+    pub fields : Vec<Field>,
+    // named places to jump to, such as sections and symbols
+    pub locations : Vec<(String, usize)>,
+    // where the file is mapped in memory, for executables
+    pub segments : Vec<Segment>,
+    // offsets that become the view's base offset when jumped to, like
+    // the start of a partition
+    pub bases : Vec<usize>,
+    // problems found while decoding, like bad checksums
+    pub warnings : Vec<String>,
+    // (start, end) of the fields without children, sorted by start
+    leaves : Vec<(usize, usize)>,
+}
+
+fn collect_leaves(fields : &[Field], leaves : &mut Vec<(usize, usize)>) {
+    for field in fields {
+        if ! field.children.is_empty() {
+            collect_leaves(&field.children, leaves);
+        } else if field.len > 0 {
+            leaves.push((field.start, field.start + field.len));
+        }
+    }
+}
+
+impl Overlay {
+
+    pub fn new(name : &str, fields : Vec<Field>) -> Overlay {
+        let mut leaves = vec![];
+        collect_leaves(&fields, &mut leaves);
+        leaves.sort_by_key(|leaf| leaf.0);
+        Overlay {
+            name : name.to_string(),
+            fields,
+            locations : vec![],
+            segments : vec![],
+            bases : vec![],
+            warnings : vec![],
+            leaves,
+        }
+    }
+
+    pub fn addr_of(&self, pos : usize) -> Option<u64> {
+        self.segments.iter()
+            .find(|s| pos >= s.offset && pos - s.offset < s.size)
+            .and_then(|s| s.addr.checked_add((pos - s.offset) as u64))
+    }
+
+    pub fn offset_of(&self, addr : u64) -> Option<usize> {
+        self.segments.iter().find_map(|s| s.offset_of(addr))
+    }
+
+    // highest mapped address, to size the address column
+    pub fn max_addr(&self) -> Option<u64> {
+        self.segments.iter().map(|s| s.addr.saturating_add(s.size as u64)).max()
+    }
+
+    // index of the innermost field covering `pos`, counted in offset
+    // order so that neighbouring fields can be told apart by colour
+    pub fn leaf_at(&self, pos : usize) -> Option<usize> {
+        let n = self.leaves.partition_point(|leaf| leaf.0 <= pos);
+        if n > 0 && pos < self.leaves[n-1].1 {
+            Some(n-1)
+        } else {
+            None
+        }
+    }
+
+    // index path of the innermost field covering `pos`
+    pub fn path_at(&self, pos : usize) -> Vec<usize> {
+        let mut path = vec![];
+        let mut fields = &self.fields;
+        while let Some(i) = fields.iter().position(|f| f.contains(pos)) {
+            path.push(i);
+            fields = &fields[i].children;
+        }
+        path
+    }
+
+    pub fn field(&self, path : &[usize]) -> Option<&Field> {
+        let (&first, rest) = path.split_first()?;
+        let mut field = self.fields.get(first)?;
+        for &i in rest {
+            field = field.children.get(i)?;
+        }
+        Some(field)
+    }
+
+    // dotted name and value of the innermost field covering `pos`
+    pub fn describe(&self, pos : usize) -> Option<String> {
+        let path = self.path_at(pos);
+        let field = self.field(&path)?;
+        let names : Vec<&str> = (1..path.len()+1)
+            .filter_map(|n| self.field(&path[..n]))
+            .map(|f| f.name.as_str())
+            .collect();
+        let mut text = names.join(".").replace(".[", "[");
+        if ! field.value.is_empty() {
+            text.push_str(" = ");
+            text.push_str(&field.value);
+        }
+        Some(text)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_near_the_top_of_memory() {
+        let mut overlay = Overlay::new("test", vec![]);
+        overlay.segments.push(Segment { offset : 0x10, size : 0x100, addr : u64::MAX - 0x20 });
+        assert_eq!(overlay.addr_of(0x10), Some(u64::MAX - 0x20));
+        assert_eq!(overlay.addr_of(0x40), None);
+        assert_eq!(overlay.max_addr(), Some(u64::MAX));
+        assert_eq!(overlay.offset_of(u64::MAX - 0x1f), Some(0x11));
+    }
+
+    #[test]
+    fn leaves_and_paths() {
+        let mut parent = Field::new("parent", String::new(), 0, 8);
+        parent.children.push(Field::new("a", String::new(), 0, 4));
+        parent.children.push(Field::new("b", String::new(), 4, 4));
+        let overlay = Overlay::new("test", vec![parent]);
+        assert_eq!(overlay.leaf_at(5), Some(1));
+        assert_eq!(overlay.leaf_at(8), None);
+        assert_eq!(overlay.path_at(5), vec![0, 1]);
+    }
+}
@@ -0,0 +1,435 @@
+
+use super::overlay::{Field, Overlay, Segment};
+use super::formats::{Fields, group, c_string, flag_names};
+
+// entries decoded from each import and export table
+const MAX_ENTRIES : usize = 20_000;
+
+const DOS_HEADER_SIZE : usize = 64;
+const PE32_PLUS : u64 = 0x20b;
+const FILE_DLL : u64 = 0x2000;
+const DIR_EXPORT : usize = 0;
+const DIR_IMPORT : usize = 1;
+
+const MAGIC_NAMES : &[(u64, &str)] = &[(0x10b, "PE32"), (0x20b, "PE32+"), (0x107, "ROM")];
+
+const MACHINE_NAMES : &[(u64, &str)] = &[
+    (0x14c, "x86"), (0x166, "MIPS"), (0x1c0, "ARM"), (0x1c4, "ARMv7 Thumb-2"), (0x200, "IA-64"),
+    (0x5064, "RISC-V 64"), (0x8664, "x86-64"), (0xaa64, "ARM64"),
+];
+
+const CHARACTERISTICS_NAMES : &[(u64, &str)] = &[
+    (0x1, "RELOCS_STRIPPED"), (0x2, "EXECUTABLE_IMAGE"), (0x4, "LINE_NUMS_STRIPPED"),
+    (0x8, "LOCAL_SYMS_STRIPPED"), (0x20, "LARGE_ADDRESS_AWARE"), (0x100, "32BIT_MACHINE"),
+    (0x200, "DEBUG_STRIPPED"), (0x1000, "SYSTEM"), (0x2000, "DLL"),
+];
+
+const SUBSYSTEM_NAMES : &[(u64, &str)] = &[
+    (1, "native"), (2, "Windows GUI"), (3, "Windows console"), (7, "POSIX console"),
+    (9, "Windows CE"), (10, "EFI application"), (11, "EFI boot service driver"),
+    (12, "EFI runtime driver"), (13, "EFI ROM"), (14, "Xbox"), (16, "boot application"),
+];
+
+const DLL_CHARACTERISTICS_NAMES : &[(u64, &str)] = &[
+    (0x20, "HIGH_ENTROPY_VA"), (0x40, "DYNAMIC_BASE"), (0x80, "FORCE_INTEGRITY"),
+    (0x100, "NX_COMPAT"), (0x200, "NO_ISOLATION"), (0x400, "NO_SEH"), (0x800, "NO_BIND"),
+    (0x1000, "APPCONTAINER"), (0x2000, "WDM_DRIVER"), (0x4000, "GUARD_CF"),
+    (0x8000, "TERMINAL_SERVER_AWARE"),
+];
+
+const DIRECTORY_NAMES : &[&str] = &[
+    "export", "import", "resource", "exception", "certificate", "base_relocation", "debug",
+    "architecture", "global_ptr", "tls", "load_config", "bound_import", "iat",
+    "delay_import", "clr_runtime", "reserved",
+];
+
+const SECTION_NAMES : &[(u64, &str)] = &[
+    (0x20, "CODE"), (0x40, "INITIALIZED_DATA"), (0x80, "UNINITIALIZED_DATA"),
+    (0x2000000, "DISCARDABLE"), (0x10000000, "SHARED"), (0x20000000, "EXECUTE"),
+    (0x40000000, "READ"), (0x80000000, "WRITE"),
+];
+
+struct Directory {
+    rva : u64,
+    size : u64,
+}
+
+struct ExportTable {
+    n_functions : u64,
+    n_names : u64,
+    functions_rva : u64,
+    names_rva : u64,
+    ordinals_rva : u64,
+}
+
+// offset of the PE signature, from the DOS header
+fn pe_offset(data : &[u8]) -> Option<usize> {
+    if data.len() < DOS_HEADER_SIZE || &data[..2] != b"MZ" {
+        return None;
+    }
+    let off = Fields::new(data, 0, false).peek(0x3c, 4)? as usize;
+    if off > data.len() || data.len() - off < 4 || &data[off..off+4] != b"PE\0\0" {
+        return None;
+    }
+    Some(off)
+}
+
+pub fn detect(data : &[u8]) -> Option<String> {
+    let off = pe_offset(data)?;
+    let f = Fields::new(data, 0, false);
+    let machine = f.peek(off + 4, 2)?;
+    let characteristics = f.peek(off + 22, 2)?;
+    let magic = f.peek(off + 24, 2).unwrap_or(0);
+    let mut desc = format!("{} {}", if magic == PE32_PLUS { "PE32+" } else { "PE32" },
+                           if characteristics & FILE_DLL != 0 { "DLL" } else { "executable" });
+    if let Some(m) = MACHINE_NAMES.iter().find(|m| m.0 == machine) {
+        desc.push_str(", ");
+        desc.push_str(m.1);
+    }
+    Some(desc)
+}
+
+fn rva_offset(segments : &[Segment], rva : u64) -> Option<usize> {
+    segments.iter().find_map(|s| s.offset_of(rva))
+}
+
+fn hex(v : u64) -> String {
+    format!("0x{:x}", v)
+}
+
+pub fn decode(data : &[u8]) -> Option<Overlay> {
+    let pe_off = pe_offset(data)?;
+    let mut fields = vec![];
+
+    // DOS header
+    let mut dos = Fields::new(data, 0, false);
+    dos.text("e_magic", 2)?;
+    for name in &["e_cblp", "e_cp", "e_crlc", "e_cparhdr", "e_minalloc", "e_maxalloc",
+                  "e_ss", "e_sp", "e_csum", "e_ip", "e_cs", "e_lfarlc", "e_ovno"] {
+        dos.uint(name, 2)?;
+    }
+    dos.bytes("e_res", 8)?;
+    dos.uint("e_oemid", 2)?;
+    dos.uint("e_oeminfo", 2)?;
+    dos.bytes("e_res2", 20)?;
+    dos.uint_with("e_lfanew", 4, hex)?;
+    fields.push(dos.into_field("dos_header", String::new()));
+
+    // PE signature and COFF file header
+    let mut hdr = Fields::new(data, pe_off, false);
+    hdr.text("signature", 4)?;
+    hdr.named("machine", 2, MACHINE_NAMES)?;
+    let n_sections = hdr.uint("number_of_sections", 2)?;
+    hdr.uint_with("time_date_stamp", 4, hex)?;
+    hdr.uint_with("pointer_to_symbol_table", 4, hex)?;
+    hdr.uint("number_of_symbols", 4)?;
+    let opt_size = hdr.uint("size_of_optional_header", 2)?;
+    hdr.flags("characteristics", 2, CHARACTERISTICS_NAMES)?;
+    let opt_start = hdr.pos;
+    fields.push(hdr.into_field("pe_header", String::new()));
+
+    // optional header with the data directories
+    let mut entry = None;
+    let mut word = 4;
+    let mut size_of_headers = 0;
+    let mut directories = vec![];
+    if opt_size > 0 {
+        let mut opt = Fields::new(data, opt_start, false);
+        let magic = opt.named("magic", 2, MAGIC_NAMES)?;
+        if magic == PE32_PLUS {
+            word = 8;
+        }
+        opt.uint("major_linker_version", 1)?;
+        opt.uint("minor_linker_version", 1)?;
+        opt.uint("size_of_code", 4)?;
+        opt.uint("size_of_initialized_data", 4)?;
+        opt.uint("size_of_uninitialized_data", 4)?;
+        entry = Some(opt.uint_with("address_of_entry_point", 4, hex)?);
+        opt.uint_with("base_of_code", 4, hex)?;
+        if magic != PE32_PLUS {
+            opt.uint_with("base_of_data", 4, hex)?;
+        }
+        opt.uint_with("image_base", word, hex)?;
+        opt.uint_with("section_alignment", 4, hex)?;
+        opt.uint_with("file_alignment", 4, hex)?;
+        for name in &["major_os_version", "minor_os_version", "major_image_version",
+                      "minor_image_version", "major_subsystem_version", "minor_subsystem_version"] {
+            opt.uint(name, 2)?;
+        }
+        opt.uint("win32_version_value", 4)?;
+        opt.uint_with("size_of_image", 4, hex)?;
+        size_of_headers = opt.uint_with("size_of_headers", 4, hex)?;
+        opt.uint_with("checksum", 4, hex)?;
+        opt.named("subsystem", 2, SUBSYSTEM_NAMES)?;
+        opt.flags("dll_characteristics", 2, DLL_CHARACTERISTICS_NAMES)?;
+        opt.uint_with("size_of_stack_reserve", word, hex)?;
+        opt.uint_with("size_of_stack_commit", word, hex)?;
+        opt.uint_with("size_of_heap_reserve", word, hex)?;
+        opt.uint_with("size_of_heap_commit", word, hex)?;
+        opt.uint_with("loader_flags", 4, hex)?;
+        let n_dirs = opt.uint("number_of_rva_and_sizes", 4)? as usize;
+        let dirs_start = opt.pos;
+        let mut dirs = vec![];
+        for name in DIRECTORY_NAMES.iter().take(n_dirs) {
+            let mut dir = Fields::new(data, opt.pos, false);
+            let rva = dir.uint_with("virtual_address", 4, hex)?;
+            let size = dir.uint_with("size", 4, hex)?;
+            opt.pos = dir.pos;
+            dirs.push(dir.into_field(name, format!("0x{:x} bytes at 0x{:x}", size, rva)));
+            directories.push(Directory { rva, size });
+        }
+        opt.push(group("data_directories", dirs_start, opt.pos - dirs_start, dirs));
+        fields.push(opt.into_field("optional_header", String::new()));
+    }
+
+    // section table; the headers are mapped at RVA 0, and each section at
+    // its virtual address for the part that is stored in the file
+    let mut segments = vec![];
+    if size_of_headers > 0 {
+        segments.push(Segment { offset : 0, size : (size_of_headers as usize).min(data.len()), addr : 0 });
+    }
+    let mut locations = vec![];
+    let table_start = opt_start + opt_size as usize;
+    let mut headers = vec![];
+    let mut pos = table_start;
+    for i in 0..n_sections {
+        let mut sh = Fields::new(data, pos, false);
+        let name = sh.text("name", 8)?;
+        let name = String::from_utf8_lossy(name).trim_end_matches('\0').to_string();
+        let virtual_size = sh.uint_with("virtual_size", 4, hex)?;
+        let rva = sh.uint_with("virtual_address", 4, hex)?;
+        let raw_size = sh.uint_with("size_of_raw_data", 4, hex)?;
+        let raw_offset = sh.uint_with("pointer_to_raw_data", 4, hex)?;
+        sh.uint_with("pointer_to_relocations", 4, hex)?;
+        sh.uint_with("pointer_to_line_numbers", 4, hex)?;
+        sh.uint("number_of_relocations", 2)?;
+        sh.uint("number_of_line_numbers", 2)?;
+        let flags = sh.flags("characteristics", 4, SECTION_NAMES)?;
+        pos = sh.pos;
+        let size = if virtual_size > 0 { raw_size.min(virtual_size) } else { raw_size };
+        let size = size.min((data.len() as u64).saturating_sub(raw_offset));
+        if size > 0 {
+            segments.push(Segment { offset : raw_offset as usize, size : size as usize, addr : rva });
+            locations.push((format!("section {}", name), raw_offset as usize));
+        }
+        let value = format!("{} 0x{:x} bytes at 0x{:x}", flag_names(flags, SECTION_NAMES), raw_size, raw_offset);
+        headers.push(sh.into_field(&format!("[{}] {}", i, name), value));
+    }
+    if ! headers.is_empty() {
+        fields.push(group("section_table", table_start, pos - table_start, headers));
+    }
+
+    if let Some(dir) = directories.get(DIR_IMPORT).filter(|d| d.size > 0) {
+        decode_imports(data, &segments, dir, word, &mut fields, &mut locations);
+    }
+    if let Some(dir) = directories.get(DIR_EXPORT).filter(|d| d.size > 0) {
+        decode_exports(data, &segments, dir, &mut fields, &mut locations);
+    }
+
+    if let Some(offset) = entry.and_then(|rva| rva_offset(&segments, rva)) {
+        locations.push(("entry point".to_string(), offset));
+    }
+    locations.sort_by_key(|l| l.1);
+    locations.dedup();
+    let mut overlay = Overlay::new("PE", fields);
+    overlay.segments = segments;
+    overlay.locations = locations;
+    Some(overlay)
+}
+
+// import descriptors, one per DLL, each pointing to a table of the
+// functions imported by name or ordinal
+fn decode_imports(data : &[u8], segments : &[Segment], dir : &Directory, word : usize,
+                  fields : &mut Vec<Field>, locations : &mut Vec<(String, usize)>) {
+    let start = match rva_offset(segments, dir.rva) {
+        Some(start) => start,
+        None => return,
+    };
+    let ordinal_flag = 1u64 << (word * 8 - 1);
+    let mut descriptors = vec![];
+    let mut tables = vec![];
+    let mut pos = start;
+    while descriptors.len() < MAX_ENTRIES {
+        let mut desc = Fields::new(data, pos, false);
+        let lookup_rva = match desc.uint_with("original_first_thunk", 4, hex) {
+            Some(rva) => rva,
+            None => break,
+        };
+        let (name_rva, thunk_rva) = match (desc.uint_with("time_date_stamp", 4, hex),
+                                           desc.uint_with("forwarder_chain", 4, hex),
+                                           desc.uint_with("name", 4, hex),
+                                           desc.uint_with("first_thunk", 4, hex)) {
+            (Some(_), Some(_), Some(name), Some(thunk)) => (name, thunk),
+            _ => break,
+        };
+        pos = desc.pos;
+        if lookup_rva == 0 && name_rva == 0 && thunk_rva == 0 {
+            break;
+        }
+        let dll = rva_offset(segments, name_rva).map_or(String::new(), |off| c_string(data, off));
+        desc.fields[3].value = format!("\"{}\" (0x{:x})", dll, name_rva);
+
+        // lookup table, or the address table if there is none
+        let table_rva = if lookup_rva != 0 { lookup_rva } else { thunk_rva };
+        if let Some(table_start) = rva_offset(segments, table_rva) {
+            let mut table = Fields::new(data, table_start, false);
+            let mut n = 0;
+            while n < MAX_ENTRIES {
+                let entry = match table.peek(table.pos, word) {
+                    Some(entry) if entry != 0 => entry,
+                    _ => break,
+                };
+                let name = if entry & ordinal_flag != 0 {
+                    format!("ordinal {}", entry & 0xffff)
+                } else {
+                    // hint followed by the name
+                    rva_offset(segments, entry & 0x7fff_ffff).map_or(String::new(), |off| c_string(data, off + 2))
+                };
+                locations.push((format!("import {}!{}", dll, name), table.pos));
+                table.uint_with(&format!("[{}]", n), word, |_| name.clone());
+                n += 1;
+            }
+            tables.push(table.into_field(&format!("imports {}", dll), format!("[{}]", n)));
+        }
+        descriptors.push(desc.into_field(&format!("[{}] {}", descriptors.len(), dll), String::new()));
+    }
+    fields.push(group("import_directory", start, pos - start, descriptors));
+    fields.append(&mut tables);
+}
+
+fn export_directory(exp : &mut Fields, segments : &[Segment]) -> Option<ExportTable> {
+    exp.uint_with("characteristics", 4, hex)?;
+    exp.uint_with("time_date_stamp", 4, hex)?;
+    exp.uint("major_version", 2)?;
+    exp.uint("minor_version", 2)?;
+    let name_rva = exp.uint_with("name", 4, hex)?;
+    if let Some(off) = rva_offset(segments, name_rva) {
+        let dll = c_string(exp.data(), off);
+        exp.fields[4].value = format!("\"{}\" (0x{:x})", dll, name_rva);
+    }
+    exp.uint("ordinal_base", 4)?;
+    Some(ExportTable {
+        n_functions : exp.uint("number_of_functions", 4)?,
+        n_names : exp.uint("number_of_names", 4)?,
+        functions_rva : exp.uint_with("address_of_functions", 4, hex)?,
+        names_rva : exp.uint_with("address_of_names", 4, hex)?,
+        ordinals_rva : exp.uint_with("address_of_name_ordinals", 4, hex)?,
+    })
+}
+
+// export directory with the names, ordinals and addresses of the
+// exported functions
+fn decode_exports(data : &[u8], segments : &[Segment], dir : &Directory,
+                  fields : &mut Vec<Field>, locations : &mut Vec<(String, usize)>) {
+    let start = match rva_offset(segments, dir.rva) {
+        Some(start) => start,
+        None => return,
+    };
+    let mut exp = Fields::new(data, start, false);
+    let table = match export_directory(&mut exp, segments) {
+        Some(table) => table,
+        None => return,
+    };
+    fields.push(exp.into_field("export_directory", String::new()));
+
+    let (names_start, ordinals_start) = match (rva_offset(segments, table.names_rva), rva_offset(segments, table.ordinals_rva)) {
+        (Some(names), Some(ordinals)) => (names, ordinals),
+        _ => return,
+    };
+    let functions_start = rva_offset(segments, table.functions_rva);
+    let mut names = Fields::new(data, names_start, false);
+    for i in 0..(table.n_names as usize).min(MAX_ENTRIES) {
+        let name_rva = match names.peek(names.pos, 4) {
+            Some(rva) => rva,
+            None => break,
+        };
+        let name = rva_offset(segments, name_rva).map_or(String::new(), |off| c_string(data, off));
+        let func_rva = names.peek(ordinals_start + 2*i, 2)
+            .filter(|&index| index < table.n_functions)
+            .and_then(|index| names.peek(functions_start? + 4 * index as usize, 4));
+        // forwarded exports point to a name inside the export directory
+        if let Some(rva) = func_rva.filter(|&rva| rva < dir.rva || rva - dir.rva >= dir.size) {
+            if let Some(offset) = rva_offset(segments, rva) {
+                locations.push((format!("export {}", name), offset));
+            }
+        }
+        names.uint_with(&format!("[{}]", i), 4, |_| match func_rva {
+            Some(rva) => format!("{} at 0x{:x}", name, rva),
+            None => name.clone(),
+        });
+    }
+    let n = names.fields.len();
+    fields.push(names.into_field("export_names", format!("[{}]", n)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(data : &mut [u8], pos : usize, bytes : &[u8]) {
+        data[pos..pos+bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn put32(data : &mut [u8], pos : usize, v : u32) {
+        put(data, pos, &v.to_le_bytes());
+    }
+
+    // PE32 executable with a .text section at RVA 0x1000 that imports
+    // KERNEL32.dll!ExitProcess
+    fn pe_image() -> Vec<u8> {
+        let mut data = vec![0; 0x400];
+        put(&mut data, 0, b"MZ");
+        put32(&mut data, 0x3c, 0x40);
+        put(&mut data, 0x40, b"PE\0\0");
+        put(&mut data, 0x44, &0x14cu16.to_le_bytes());
+        put(&mut data, 0x46, &1u16.to_le_bytes());
+        put(&mut data, 0x54, &224u16.to_le_bytes());
+        put(&mut data, 0x56, &0x102u16.to_le_bytes());
+        let opt = 0x58;
+        put(&mut data, opt, &0x10bu16.to_le_bytes());
+        put32(&mut data, opt + 16, 0x1100);
+        put32(&mut data, opt + 28, 0x400000);
+        put32(&mut data, opt + 60, 0x200);
+        put32(&mut data, opt + 92, 16);
+        put32(&mut data, opt + 104, 0x1000);
+        put32(&mut data, opt + 108, 40);
+        let sh = opt + 224;
+        put(&mut data, sh, b".text");
+        put32(&mut data, sh + 8, 0x200);
+        put32(&mut data, sh + 12, 0x1000);
+        put32(&mut data, sh + 16, 0x200);
+        put32(&mut data, sh + 20, 0x200);
+        put32(&mut data, sh + 36, 0x60000020);
+        // import descriptor, lookup table, hint and name, DLL name
+        put32(&mut data, 0x200, 0x1040);
+        put32(&mut data, 0x200 + 12, 0x1080);
+        put32(&mut data, 0x200 + 16, 0x1040);
+        put32(&mut data, 0x240, 0x1060);
+        put(&mut data, 0x262, b"ExitProcess\0");
+        put(&mut data, 0x280, b"KERNEL32.dll\0");
+        data
+    }
+
+    #[test]
+    fn sections_and_imports() {
+        let data = pe_image();
+        assert_eq!(detect(&data).unwrap(), "PE32 executable, x86");
+        let overlay = decode(&data).unwrap();
+        assert_eq!(overlay.locations, [
+            ("section .text".to_string(), 0x200),
+            ("import KERNEL32.dll!ExitProcess".to_string(), 0x240),
+            ("entry point".to_string(), 0x300),
+        ]);
+        assert_eq!(overlay.addr_of(0x240), Some(0x1040));
+        assert_eq!(overlay.addr_of(0x100), Some(0x100));
+        assert_eq!(overlay.offset_of(0x1100), Some(0x300));
+    }
+
+    #[test]
+    fn truncated_pe() {
+        let data = pe_image();
+        for len in 0..data.len() {
+            decode(&data[..len]);
+        }
+    }
+}
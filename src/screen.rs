@@ -1,31 +1,410 @@
-
+use std::env;
 use std::io;
 use std::io::Write;
 use super::term::get_win_size;
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Color {
     None,
     
-    FGDefault = 39,
-    FGBlack   = 30,
-    FGRed     = 31,
-    FGGreen   = 32,
-    FGYellow  = 33,
-    FGBlue    = 34,
-    FGMagenta = 35,
-    FGCyan    = 36,
-    FGGray    = 37,
+    FGDefault,
+    FGBlack,
+    FGRed,
+    FGGreen,
+    FGYellow,
+    FGBlue,
+    FGMagenta,
+    FGCyan,
+    FGGray,
+    FG256(u8),
+    FGRgb(u8, u8, u8),
     
-    BGDefault = 49,
-    BGBlack   = 40,
-    BGRed     = 41,
-    BGGreen   = 42,
-    BGYellow  = 43,
-    BGBlue    = 44,
-    BGMagenta = 45,
-    BGCyan    = 46,
-    BGGray    = 47,
+    BGDefault,
+    BGBlack,
+    BGRed,
+    BGGreen,
+    BGYellow,
+    BGBlue,
+    BGMagenta,
+    BGCyan,
+    BGGray,
+    BG256(u8),
+    BGRgb(u8, u8, u8),
+}
+
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub enum ColorDepth {
+    Basic,
+    Indexed,
+    TrueColor,
+}
+
+// basic colors in ANSI order
+const BASIC_COLORS : [(Color, Color); 8] = [
+    (Color::FGBlack,   Color::BGBlack),
+    (Color::FGRed,     Color::BGRed),
+    (Color::FGGreen,   Color::BGGreen),
+    (Color::FGYellow,  Color::BGYellow),
+    (Color::FGBlue,    Color::BGBlue),
+    (Color::FGMagenta, Color::BGMagenta),
+    (Color::FGCyan,    Color::BGCyan),
+    (Color::FGGray,    Color::BGGray),
+];
+
+const COLOR_NAMES : &[(&str, Color, Color)] = &[
+    ("default", Color::FGDefault, Color::BGDefault),
+    ("black",   Color::FGBlack,   Color::BGBlack),
+    ("red",     Color::FGRed,     Color::BGRed),
+    ("green",   Color::FGGreen,   Color::BGGreen),
+    ("yellow",  Color::FGYellow,  Color::BGYellow),
+    ("blue",    Color::FGBlue,    Color::BGBlue),
+    ("magenta", Color::FGMagenta, Color::BGMagenta),
+    ("cyan",    Color::FGCyan,    Color::BGCyan),
+    ("gray",    Color::FGGray,    Color::BGGray),
+];
+
+enum ColorSpec {
+    Named(Color, Color),
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+// accepts basic names, "#rrggbb" and "color0".."color255"
+fn parse_color(name : &str) -> Option<ColorSpec> {
+    if let Some(e) = COLOR_NAMES.iter().find(|e| e.0 == name) {
+        return Some(ColorSpec::Named(e.1, e.2));
+    }
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || ! hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let rgb = u32::from_str_radix(hex, 16).ok()?;
+        return Some(ColorSpec::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+    }
+    if let Some(index) = name.strip_prefix("color") {
+        return index.parse::<u8>().ok().map(ColorSpec::Indexed);
+    }
+    None
+}
+
+// nearest of the cube levels 0, 95, 135, 175, 215 and 255
+fn cube_level(v : u8) -> u8 {
+    if v < 48 { 0 } else if v < 115 { 1 } else { (v - 35) / 40 }
+}
+
+fn rgb_to_256(r : u8, g : u8, b : u8) -> u8 {
+    let cube = 16 + 36 * cube_level(r) + 6 * cube_level(g) + cube_level(b);
+    if r != g || g != b {
+        return cube;
+    }
+    // greys take whichever of the grey ramp and the cube is closer
+    let grey = 232 + ((r.max(8) - 8 + 5) / 10).min(23);
+    let dist = |i| (index_to_rgb(i).0 as i32 - r as i32).abs();
+    if dist(grey) < dist(cube) { grey } else { cube }
+}
+
+fn index_to_rgb(index : u8) -> (u8, u8, u8) {
+    const LEVELS : [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => {
+            let v = if index >= 8 { 255 } else { 192 };
+            let bit = |n| if index & n != 0 { v } else { 0 };
+            (bit(1), bit(2), bit(4))
+        }
+        16..=231 => {
+            let i = index - 16;
+            (LEVELS[(i / 36) as usize], LEVELS[(i / 6 % 6) as usize], LEVELS[(i % 6) as usize])
+        }
+        _ => {
+            let v = 8 + 10 * (index - 232);
+            (v, v, v)
+        }
+    }
+}
+
+// index of the nearest of the 8 basic colors
+fn rgb_to_basic(r : u8, g : u8, b : u8) -> usize {
+    let bit = |v : u8, n| if v > 127 { n } else { 0 };
+    bit(r, 1) | bit(g, 2) | bit(b, 4)
+}
+
+impl Color {
+
+    pub fn fg_from_name(name : &str) -> Option<Color> {
+        match parse_color(name)? {
+            ColorSpec::Named(fg, _) => Some(fg),
+            ColorSpec::Indexed(i) => Some(Color::FG256(i)),
+            ColorSpec::Rgb(r, g, b) => Some(Color::FGRgb(r, g, b)),
+        }
+    }
+
+    pub fn bg_from_name(name : &str) -> Option<Color> {
+        match parse_color(name)? {
+            ColorSpec::Named(_, bg) => Some(bg),
+            ColorSpec::Indexed(i) => Some(Color::BG256(i)),
+            ColorSpec::Rgb(r, g, b) => Some(Color::BGRgb(r, g, b)),
+        }
+    }
+
+    // convert to the nearest color the terminal can show
+    pub fn for_depth(self, depth : ColorDepth) -> Color {
+        match self {
+            Color::FGRgb(r, g, b) if depth < ColorDepth::TrueColor => Color::FG256(rgb_to_256(r, g, b)).for_depth(depth),
+            Color::BGRgb(r, g, b) if depth < ColorDepth::TrueColor => Color::BG256(rgb_to_256(r, g, b)).for_depth(depth),
+            Color::FG256(i) if depth < ColorDepth::Indexed => {
+                let (r, g, b) = index_to_rgb(i);
+                BASIC_COLORS[rgb_to_basic(r, g, b)].0
+            }
+            Color::BG256(i) if depth < ColorDepth::Indexed => {
+                let (r, g, b) = index_to_rgb(i);
+                BASIC_COLORS[rgb_to_basic(r, g, b)].1
+            }
+            c => c,
+        }
+    }
+
+    fn sgr(&self) -> Option<String> {
+        let code = match *self {
+            Color::None => return None,
+            Color::FGDefault => 39,
+            Color::BGDefault => 49,
+            Color::FG256(i) => return Some(format!("38;5;{}", i)),
+            Color::BG256(i) => return Some(format!("48;5;{}", i)),
+            Color::FGRgb(r, g, b) => return Some(format!("38;2;{};{};{}", r, g, b)),
+            Color::BGRgb(r, g, b) => return Some(format!("48;2;{};{};{}", r, g, b)),
+            c => match BASIC_COLORS.iter().position(|e| e.0 == c || e.1 == c) {
+                Some(i) if BASIC_COLORS[i].0 == c => 30 + i,
+                Some(i) => 40 + i,
+                None => return None,
+            },
+        };
+        Some(code.to_string())
+    }
+    
+}
+
+pub fn detect_color_depth() -> ColorDepth {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorDepth::TrueColor;
+    }
+    let term = env::var("TERM").unwrap_or_default();
+    if term.contains("256color") {
+        ColorDepth::Indexed
+    } else {
+        ColorDepth::Basic
+    }
+}
+
+pub type ColorPair = (Color, Color);
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ByteClass {
+    Null,
+    Printable,
+    Whitespace,
+    Control,
+    HighBit,
+    Full,
+}
+
+pub fn byte_class(b : u8) -> ByteClass {
+    match b {
+        0x00 => ByteClass::Null,
+        b'\t' | b'\n' | b'\r' | b' ' | 0x0b | 0x0c => ByteClass::Whitespace,
+        0x21..=0x7e => ByteClass::Printable,
+        0x01..=0x1f | 0x7f => ByteClass::Control,
+        0xff => ByteClass::Full,
+        _ => ByteClass::HighBit,
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Theme {
+    pub header : ColorPair,
+    pub key_help : ColorPair,
+    pub message : ColorPair,
+    pub cursor : ColorPair,
+    pub cursor_inactive : ColorPair,
+    pub cursor_half_byte : ColorPair,
+    pub selection : ColorPair,
+    pub file_sel : ColorPair,
+    pub byte_null : ColorPair,
+    pub byte_printable : ColorPair,
+    pub byte_whitespace : ColorPair,
+    pub byte_control : ColorPair,
+    pub byte_high : ColorPair,
+    pub byte_full : ColorPair,
+    pub tab : ColorPair,
+    pub tab_active : ColorPair,
+    pub field : ColorPair,
+    pub field_alt : ColorPair,
+}
+
+const THEME_ELEMENTS : &[&str] = &[
+    "header", "key_help", "message", "cursor", "cursor_inactive", "cursor_half_byte",
+    "selection", "file_sel", "byte_null", "byte_printable", "byte_whitespace",
+    "byte_control", "byte_high", "byte_full", "tab", "tab_active", "field", "field_alt",
+];
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::new()
+    }
+}
+
+impl Theme {
+
+    pub fn new() -> Theme {
+        Theme {
+            header : (Color::FGBlack, Color::BGGray),
+            key_help : (Color::FGBlack, Color::BGGray),
+            message : (Color::FGBlack, Color::BGGray),
+            cursor : (Color::FGBlack, Color::BGGreen),
+            cursor_inactive : (Color::FGBlack, Color::BGGray),
+            cursor_half_byte : (Color::FGBlack, Color::BGYellow),
+            selection : (Color::None, Color::BGBlue),
+            file_sel : (Color::FGBlack, Color::BGGray),
+            byte_null : (Color::FGBlue, Color::None),
+            byte_printable : (Color::FGDefault, Color::None),
+            byte_whitespace : (Color::FGGreen, Color::None),
+            byte_control : (Color::FGRed, Color::None),
+            byte_high : (Color::FGYellow, Color::None),
+            byte_full : (Color::FGMagenta, Color::None),
+            tab : (Color::FGBlack, Color::BGGray),
+            tab_active : (Color::FGBlack, Color::BGGreen),
+            field : (Color::FGBlack, Color::BGCyan),
+            field_alt : (Color::FGBlack, Color::BGMagenta),
+        }
+    }
+
+    pub fn named(name : &str) -> Option<Theme> {
+        let mut theme = Theme::new();
+        match name {
+            "default" => (),
+            "dark" => {
+                theme.header = (Color::FG256(252), Color::BG256(238));
+                theme.key_help = (Color::FG256(252), Color::BG256(238));
+                theme.message = (Color::FG256(230), Color::BG256(24));
+                theme.cursor = (Color::FG256(16), Color::BG256(114));
+                theme.cursor_inactive = (Color::FG256(16), Color::BG256(246));
+                theme.cursor_half_byte = (Color::FG256(16), Color::BG256(221));
+                theme.selection = (Color::None, Color::BG256(24));
+                theme.file_sel = (Color::FG256(16), Color::BG256(246));
+                theme.byte_null = (Color::FG256(240), Color::None);
+                theme.byte_printable = (Color::FG256(252), Color::None);
+                theme.byte_whitespace = (Color::FG256(114), Color::None);
+                theme.byte_control = (Color::FG256(203), Color::None);
+                theme.byte_high = (Color::FG256(221), Color::None);
+                theme.byte_full = (Color::FG256(170), Color::None);
+                theme.tab = (Color::FG256(252), Color::BG256(238));
+                theme.tab_active = (Color::FG256(16), Color::BG256(114));
+                theme.field = (Color::None, Color::BG256(23));
+                theme.field_alt = (Color::None, Color::BG256(53));
+            }
+            "light" => {
+                theme.header = (Color::FG256(255), Color::BG256(25));
+                theme.key_help = (Color::FG256(255), Color::BG256(25));
+                theme.message = (Color::FG256(16), Color::BG256(153));
+                theme.cursor = (Color::FG256(255), Color::BG256(28));
+                theme.cursor_inactive = (Color::FG256(16), Color::BG256(250));
+                theme.cursor_half_byte = (Color::FG256(16), Color::BG256(214));
+                theme.selection = (Color::None, Color::BG256(153));
+                theme.file_sel = (Color::FG256(255), Color::BG256(25));
+                theme.byte_null = (Color::FG256(248), Color::None);
+                theme.byte_printable = (Color::FG256(16), Color::None);
+                theme.byte_whitespace = (Color::FG256(28), Color::None);
+                theme.byte_control = (Color::FG256(160), Color::None);
+                theme.byte_high = (Color::FG256(130), Color::None);
+                theme.byte_full = (Color::FG256(90), Color::None);
+                theme.tab = (Color::FG256(255), Color::BG256(25));
+                theme.tab_active = (Color::FG256(16), Color::BG256(153));
+                theme.field = (Color::None, Color::BG256(195));
+                theme.field_alt = (Color::None, Color::BG256(224));
+            }
+            "solarized" => {
+                theme.header = (Color::FGRgb(0xee, 0xe8, 0xd5), Color::BGRgb(0x07, 0x36, 0x42));
+                theme.key_help = (Color::FGRgb(0xee, 0xe8, 0xd5), Color::BGRgb(0x07, 0x36, 0x42));
+                theme.message = (Color::FGRgb(0xfd, 0xf6, 0xe3), Color::BGRgb(0x26, 0x8b, 0xd2));
+                theme.cursor = (Color::FGRgb(0x00, 0x2b, 0x36), Color::BGRgb(0x85, 0x99, 0x00));
+                theme.cursor_inactive = (Color::FGRgb(0x00, 0x2b, 0x36), Color::BGRgb(0x93, 0xa1, 0xa1));
+                theme.cursor_half_byte = (Color::FGRgb(0x00, 0x2b, 0x36), Color::BGRgb(0xb5, 0x89, 0x00));
+                theme.selection = (Color::None, Color::BGRgb(0x07, 0x36, 0x42));
+                theme.file_sel = (Color::FGRgb(0x00, 0x2b, 0x36), Color::BGRgb(0x93, 0xa1, 0xa1));
+                theme.byte_null = (Color::FGRgb(0x58, 0x6e, 0x75), Color::None);
+                theme.byte_printable = (Color::FGRgb(0x93, 0xa1, 0xa1), Color::None);
+                theme.byte_whitespace = (Color::FGRgb(0x2a, 0xa1, 0x98), Color::None);
+                theme.byte_control = (Color::FGRgb(0xdc, 0x32, 0x2f), Color::None);
+                theme.byte_high = (Color::FGRgb(0xb5, 0x89, 0x00), Color::None);
+                theme.byte_full = (Color::FGRgb(0xd3, 0x36, 0x82), Color::None);
+                theme.tab = (Color::FGRgb(0xee, 0xe8, 0xd5), Color::BGRgb(0x07, 0x36, 0x42));
+                theme.tab_active = (Color::FGRgb(0x00, 0x2b, 0x36), Color::BGRgb(0x85, 0x99, 0x00));
+                theme.field = (Color::None, Color::BGRgb(0x0b, 0x3d, 0x3a));
+                theme.field_alt = (Color::None, Color::BGRgb(0x3b, 0x2a, 0x45));
+            }
+            "mono" => {
+                theme.cursor = (Color::FGBlack, Color::BGGray);
+                theme.cursor_half_byte = (Color::FGBlack, Color::BGGray);
+                theme.selection = (Color::FGBlack, Color::BGGray);
+                theme.byte_null = (Color::FGDefault, Color::None);
+                theme.byte_whitespace = (Color::FGDefault, Color::None);
+                theme.byte_control = (Color::FGDefault, Color::None);
+                theme.byte_high = (Color::FGDefault, Color::None);
+                theme.byte_full = (Color::FGDefault, Color::None);
+                theme.tab_active = (Color::FGDefault, Color::BGDefault);
+                theme.field = (Color::FGDefault, Color::BGDefault);
+                theme.field_alt = (Color::FGBlack, Color::BGGray);
+            }
+            _ => return None,
+        }
+        Some(theme)
+    }
+
+    pub fn element_mut(&mut self, name : &str) -> Option<&mut ColorPair> {
+        match name {
+            "header" => Some(&mut self.header),
+            "key_help" => Some(&mut self.key_help),
+            "message" => Some(&mut self.message),
+            "cursor" => Some(&mut self.cursor),
+            "cursor_inactive" => Some(&mut self.cursor_inactive),
+            "cursor_half_byte" => Some(&mut self.cursor_half_byte),
+            "selection" => Some(&mut self.selection),
+            "file_sel" => Some(&mut self.file_sel),
+            "byte_null" => Some(&mut self.byte_null),
+            "byte_printable" => Some(&mut self.byte_printable),
+            "byte_whitespace" => Some(&mut self.byte_whitespace),
+            "byte_control" => Some(&mut self.byte_control),
+            "byte_high" => Some(&mut self.byte_high),
+            "byte_full" => Some(&mut self.byte_full),
+            "tab" => Some(&mut self.tab),
+            "tab_active" => Some(&mut self.tab_active),
+            "field" => Some(&mut self.field),
+            "field_alt" => Some(&mut self.field_alt),
+            _ => None,
+        }
+    }
+
+    pub fn byte_color(&self, b : u8) -> ColorPair {
+        match byte_class(b) {
+            ByteClass::Null => self.byte_null,
+            ByteClass::Printable => self.byte_printable,
+            ByteClass::Whitespace => self.byte_whitespace,
+            ByteClass::Control => self.byte_control,
+            ByteClass::HighBit => self.byte_high,
+            ByteClass::Full => self.byte_full,
+        }
+    }
+
+    pub fn for_depth(&self, depth : ColorDepth) -> Theme {
+        let mut theme = *self;
+        for name in THEME_ELEMENTS {
+            if let Some(pair) = theme.element_mut(name) {
+                *pair = (pair.0.for_depth(depth), pair.1.for_depth(depth));
+            }
+        }
+        theme
+    }
+
 }
 
 pub struct Screen {
@@ -34,26 +413,37 @@ pub struct Screen {
     pub redraw_needed : bool,
     pub msg : Option<String>,
     pub msg_was_set : bool,
+    pub theme : Theme,
+    pub color_depth : ColorDepth,
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen::new()
+    }
 }
 
 impl Screen {
     pub fn new() -> Screen {
         Screen {
-            w : 0,
-            h : 0,
+            w : 80,
+            h : 24,
             redraw_needed : false,
             msg : None,
             msg_was_set : false,
+            theme : Theme::new(),
+            color_depth : detect_color_depth(),
         }
     }
 
-    pub fn init(&mut self) -> bool {
-        if let Some((w,h)) = get_win_size(0) {
+    pub fn init(&mut self, fd : i32) -> bool {
+        if let Some((w,h)) = get_win_size(fd) {
             self.w = w;
             self.h = h;
             self.redraw_needed = false;
             self.msg = None;
             self.msg_was_set = false;
+            self.theme = self.theme.for_depth(self.color_depth);
             true
         } else {
             false
@@ -83,15 +473,50 @@ impl Screen {
     }
 }
 
+// (first, last) code points of zero-width combining marks
+const ZERO_WIDTH_CHARS : &[(u32, u32)] = &[
+    (0x0300, 0x036f), (0x0483, 0x0489), (0x0591, 0x05bd), (0x0610, 0x061a),
+    (0x064b, 0x065f), (0x0e31, 0x0e31), (0x0e34, 0x0e3a), (0x200b, 0x200f),
+    (0x20d0, 0x20ff), (0xfe00, 0xfe0f), (0xfe20, 0xfe2f),
+];
+
+// (first, last) code points of characters taking two columns
+const WIDE_CHARS : &[(u32, u32)] = &[
+    (0x1100, 0x115f), (0x2e80, 0x303e), (0x3041, 0x33ff), (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff), (0xa000, 0xa4cf), (0xac00, 0xd7a3), (0xf900, 0xfaff),
+    (0xfe30, 0xfe4f), (0xff00, 0xff60), (0xffe0, 0xffe6), (0x1f300, 0x1f64f),
+    (0x1f900, 0x1f9ff), (0x20000, 0x3fffd),
+];
+
+// number of terminal columns used to display a character
+pub fn char_width(c : char) -> usize {
+    let c = c as u32;
+    if ZERO_WIDTH_CHARS.iter().any(|&(first, last)| c >= first && c <= last) {
+        0
+    } else if WIDE_CHARS.iter().any(|&(first, last)| c >= first && c <= last) {
+        2
+    } else {
+        1
+    }
+}
+
+pub fn str_width<'a, I>(chars : I) -> usize where I : IntoIterator<Item = &'a char> {
+    chars.into_iter().map(|&c| char_width(c)).sum()
+}
+
 pub fn set_color(c1 : Color, c2 : Color) {
-    if c1 != Color::None {
-        print!("\x1b[{}m", c1 as i32);
+    if let Some(code) = c1.sgr() {
+        print!("\x1b[{}m", code);
     }
-    if c2 != Color::None {
-        print!("\x1b[{}m", c2 as i32);
+    if let Some(code) = c2.sgr() {
+        print!("\x1b[{}m", code);
     }
 }
 
+pub fn set_color_pair(colors : ColorPair) {
+    set_color(colors.0, colors.1);
+}
+
 pub fn reset_color() {
     print!("\x1b[0m");
 }
@@ -104,6 +529,23 @@ pub fn show_cursor(show : bool) {
     }
 }
 
+pub fn set_bracketed_paste(enable : bool) {
+    if enable {
+        print!("\x1b[?2004h");
+    } else {
+        print!("\x1b[?2004l");
+    }
+}
+
+pub fn set_mouse_reporting(enable : bool) {
+    // button events, drag events and SGR extended coordinates
+    if enable {
+        print!("\x1b[?1000h\x1b[?1002h\x1b[?1006h");
+    } else {
+        print!("\x1b[?1006l\x1b[?1002l\x1b[?1000l");
+    }
+}
+
 pub fn set_bold(bold : bool) {
     if bold {
         print!("\x1b[1m");
@@ -126,3 +568,100 @@ pub fn flush_screen()
     let stdout = io::stdout();
     stdout.lock().flush().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_names() {
+        assert_eq!(Color::fg_from_name("red"), Some(Color::FGRed));
+        assert_eq!(Color::bg_from_name("default"), Some(Color::BGDefault));
+        assert_eq!(Color::fg_from_name("#1a2B3c"), Some(Color::FGRgb(0x1a, 0x2b, 0x3c)));
+        assert_eq!(Color::bg_from_name("color255"), Some(Color::BG256(255)));
+        for name in &["", "Red", "#", "#12345", "#1234567", "#+12345", "#12345g", "#\u{e9}1234", "color256", "color", "colorx"] {
+            assert!(parse_color(name).is_none(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rgb_to_indexed() {
+        assert_eq!(rgb_to_256(0, 0, 0), 16);
+        assert_eq!(rgb_to_256(3, 3, 3), 16);
+        assert_eq!(rgb_to_256(7, 7, 7), 232);
+        assert_eq!(rgb_to_256(8, 8, 8), 232);
+        assert_eq!(rgb_to_256(128, 128, 128), 244);
+        assert_eq!(rgb_to_256(238, 238, 238), 255);
+        assert_eq!(rgb_to_256(239, 239, 239), 255);
+        assert_eq!(rgb_to_256(95, 95, 95), 59);
+        assert_eq!(rgb_to_256(250, 250, 250), 231);
+        assert_eq!(rgb_to_256(255, 0, 0), 196);
+        assert_eq!(rgb_to_256(0, 95, 135), 24);
+        assert_eq!(rgb_to_256(40, 0, 0), 16);
+        assert_eq!(rgb_to_256(60, 0, 0), 52);
+        assert_eq!(rgb_to_256(114, 114, 0), 58);
+        assert_eq!(rgb_to_256(115, 0, 0), 88);
+        // the cube and the grey ramp map back to themselves
+        for i in 16..=255 {
+            let (r, g, b) = index_to_rgb(i);
+            assert_eq!(rgb_to_256(r, g, b), i);
+        }
+    }
+
+    #[test]
+    fn indexed_to_rgb() {
+        assert_eq!(index_to_rgb(1), (192, 0, 0));
+        assert_eq!(index_to_rgb(7), (192, 192, 192));
+        assert_eq!(index_to_rgb(12), (0, 0, 255));
+        assert_eq!(index_to_rgb(16), (0, 0, 0));
+        assert_eq!(index_to_rgb(231), (255, 255, 255));
+        assert_eq!(index_to_rgb(232), (8, 8, 8));
+        assert_eq!(index_to_rgb(255), (238, 238, 238));
+        assert_eq!(rgb_to_basic(192, 0, 192), 5);
+        assert_eq!(rgb_to_basic(127, 128, 0), 2);
+    }
+
+    #[test]
+    fn colors_for_depth() {
+        let rgb = Color::FGRgb(255, 0, 0);
+        assert_eq!(rgb.for_depth(ColorDepth::TrueColor), rgb);
+        assert_eq!(rgb.for_depth(ColorDepth::Indexed), Color::FG256(196));
+        assert_eq!(rgb.for_depth(ColorDepth::Basic), Color::FGRed);
+        assert_eq!(Color::BGRgb(20, 20, 20).for_depth(ColorDepth::Basic), Color::BGBlack);
+        assert_eq!(Color::BG256(15).for_depth(ColorDepth::Basic), Color::BGGray);
+        assert_eq!(Color::FG256(15).for_depth(ColorDepth::Indexed), Color::FG256(15));
+        assert_eq!(Color::FGCyan.for_depth(ColorDepth::Basic), Color::FGCyan);
+        assert_eq!(Color::None.for_depth(ColorDepth::Basic), Color::None);
+    }
+
+    #[test]
+    fn byte_classes() {
+        assert_eq!(byte_class(0), ByteClass::Null);
+        assert_eq!(byte_class(b' '), ByteClass::Whitespace);
+        assert_eq!(byte_class(0x0c), ByteClass::Whitespace);
+        assert_eq!(byte_class(b'!'), ByteClass::Printable);
+        assert_eq!(byte_class(b'~'), ByteClass::Printable);
+        assert_eq!(byte_class(0x1f), ByteClass::Control);
+        assert_eq!(byte_class(0x7f), ByteClass::Control);
+        assert_eq!(byte_class(0x80), ByteClass::HighBit);
+        assert_eq!(byte_class(0xfe), ByteClass::HighBit);
+        assert_eq!(byte_class(0xff), ByteClass::Full);
+    }
+
+    #[test]
+    fn themes() {
+        assert!(Theme::named("nope").is_none());
+        for name in &["default", "dark", "light", "solarized", "mono"] {
+            let mut theme = Theme::named(name).unwrap().for_depth(ColorDepth::Basic);
+            for element in THEME_ELEMENTS {
+                let pair = *theme.element_mut(element).unwrap();
+                for c in &[pair.0, pair.1] {
+                    match *c {
+                        Color::FG256(_) | Color::BG256(_) | Color::FGRgb(..) | Color::BGRgb(..) => panic!("{} {}: {:?}", name, element, c),
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+}
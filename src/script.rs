@@ -0,0 +1,557 @@
+
+use std::io::Write;
+use std::ffi::{OsString, OsStr};
+
+use super::editor::Editor;
+use super::file::File;
+use super::keymap::Action;
+use super::batch::{BatchCmd, Range, parse_number, parse_bytes};
+
+// guard against runaway `while` loops
+const MAX_LOOP_ITERATIONS : usize = 1_000_000;
+
+enum Stmt {
+    Cmd(usize, Vec<String>),
+    For(usize, String, Vec<String>, Vec<Stmt>),
+    While(usize, Vec<String>, Vec<Stmt>),
+    If(usize, Vec<String>, Vec<Stmt>, Vec<Stmt>),
+}
+
+// statements up to the word that closed the block, if any
+type Block = (Vec<Stmt>, Option<(usize, String)>);
+
+pub struct Script {
+    body : Vec<Stmt>,
+}
+
+struct State<'s> {
+    vars : Vec<(String, usize)>,
+    out : &'s mut dyn Write,
+}
+
+// splits a line into words, keeping "quoted strings" (quotes included)
+// together and stopping at a '#' comment; ';' separates commands
+fn tokenize(line : &str) -> Vec<Vec<String>> {
+    let mut cmds = vec![];
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_quotes = false;
+    for c in line.chars() {
+        if in_quotes {
+            word.push(c);
+            if c == '"' {
+                in_quotes = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                word.push(c);
+                in_quotes = true;
+            }
+            '#' => break,
+            ';' => {
+                if ! word.is_empty() { words.push(word.split_off(0)); }
+                if ! words.is_empty() { cmds.push(words.split_off(0)); }
+            }
+            c if c.is_whitespace() => {
+                if ! word.is_empty() { words.push(word.split_off(0)); }
+            }
+            c => word.push(c),
+        }
+    }
+    if ! word.is_empty() { words.push(word); }
+    if ! words.is_empty() { cmds.push(words); }
+    cmds
+}
+
+fn parse_block<I>(lines : &mut I, end_words : &[&str]) -> Result<Block, String>
+    where I : Iterator<Item = (usize, Vec<String>)> {
+    let mut body = vec![];
+    while let Some((line, words)) = lines.next() {
+        let first = words[0].as_str();
+        if end_words.contains(&first) {
+            if words.len() > 1 {
+                return Err(format!("line {}: unexpected '{}' after '{}'", line, words[1], first));
+            }
+            return Ok((body, Some((line, words[0].clone()))));
+        }
+        match first {
+            "for" => {
+                if words.len() < 4 || words[2] != "in" {
+                    return Err(format!("line {}: expected 'for VAR in RANGE [step N]'", line));
+                }
+                let var = words[1].trim_start_matches('$').to_string();
+                let (block, _) = parse_body(lines, line, "for")?;
+                body.push(Stmt::For(line, var, words[3..].to_vec(), block));
+            }
+            "while" => {
+                let (block, _) = parse_body(lines, line, "while")?;
+                body.push(Stmt::While(line, words[1..].to_vec(), block));
+            }
+            "if" => {
+                let (then_block, end) = parse_block(lines, &["else", "end"])?;
+                let else_block = match end {
+                    Some((_, ref word)) if word == "else" => parse_body(lines, line, "if")?.0,
+                    Some(_) => vec![],
+                    None => return Err(format!("line {}: 'if' without 'end'", line)),
+                };
+                body.push(Stmt::If(line, words[1..].to_vec(), then_block, else_block));
+            }
+            "else" | "end" => return Err(format!("line {}: unexpected '{}'", line, first)),
+            _ => body.push(Stmt::Cmd(line, words)),
+        }
+    }
+    Ok((body, None))
+}
+
+fn parse_body<I>(lines : &mut I, line : usize, what : &str) -> Result<(Vec<Stmt>, usize), String>
+    where I : Iterator<Item = (usize, Vec<String>)> {
+    match parse_block(lines, &["end"])? {
+        (body, Some((end_line, _))) => Ok((body, end_line)),
+        (_, None) => Err(format!("line {}: '{}' without 'end'", line, what)),
+    }
+}
+
+fn check_args(words : &[String], min : usize, max : usize, usage : &str) -> Result<(), String> {
+    if words.len() < min+1 || words.len() > max+1 {
+        return Err(format!("usage: {}", usage));
+    }
+    Ok(())
+}
+
+fn is_interactive(action : Action) -> bool {
+    matches!(action, Action::Help | Action::ReadFile | Action::WriteFile
+             | Action::Find | Action::GoTo | Action::Command | Action::RecordMacro
+             | Action::RunMacro | Action::RepeatMacro | Action::SaveMacro | Action::LoadMacro
+             | Action::BufferList | Action::CloseFile | Action::ApplyTemplate
+             | Action::StructureTree | Action::GoToLocation | Action::Strings
+             | Action::Analysis | Action::SetBookmark | Action::Bookmarks)
+}
+
+impl Script {
+
+    pub fn parse(text : &str) -> Result<Script, String> {
+        let mut lines = text.lines().enumerate()
+            .flat_map(|(num, line)| tokenize(line).into_iter().map(move |words| (num+1, words)));
+        match parse_block(&mut lines, &[])? {
+            (body, None) => Ok(Script { body }),
+            (_, Some((line, word))) => Err(format!("line {}: unexpected '{}'", line, word)),
+        }
+    }
+
+    pub fn run(&self, editor : &mut Editor, out : &mut dyn Write) -> Result<(), String> {
+        let mut state = State {
+            vars : vec![("found".to_string(), 0)],
+            out,
+        };
+        state.run_block(editor, &self.body)
+    }
+
+}
+
+impl<'s> State<'s> {
+
+    fn get_var(&self, editor : &Editor, name : &str) -> Result<usize, String> {
+        match name {
+            "pos" => return Ok(editor.cursor_pos()),
+            "len" => return Ok(editor.cur_file().map_or(0, |f| f.data.len())),
+            _ => (),
+        }
+        match self.vars.iter().find(|v| v.0 == name) {
+            Some(v) => Ok(v.1),
+            None => Err(format!("unknown variable: '${}'", name)),
+        }
+    }
+
+    fn set_var(&mut self, name : &str, val : usize) -> Result<(), String> {
+        if name == "pos" || name == "len" || name.is_empty() {
+            return Err(format!("can't assign to '${}'", name));
+        }
+        match self.vars.iter_mut().find(|v| v.0 == name) {
+            Some(v) => v.1 = val,
+            None => self.vars.push((name.to_string(), val)),
+        }
+        Ok(())
+    }
+
+    // NUM or $VAR, joined with '+' and '-'
+    fn eval(&self, editor : &Editor, expr : &str) -> Result<usize, String> {
+        let mut total = 0usize;
+        let mut negate = false;
+        let mut rest = expr.trim();
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+            let val = if let Some(name) = term.strip_prefix('$') {
+                self.get_var(editor, name)?
+            } else {
+                parse_number(term)?
+            };
+            total = if negate {
+                total.checked_sub(val).ok_or_else(|| format!("negative result: '{}'", expr))?
+            } else {
+                total.checked_add(val).ok_or_else(|| format!("number out of range: '{}'", expr))?
+            };
+            if end == rest.len() {
+                return Ok(total);
+            }
+            negate = rest[end..].starts_with('-');
+            rest = &rest[end+1..];
+        }
+    }
+
+    // "START:END", "START:" or a single offset
+    fn eval_range(&self, editor : &Editor, s : &str) -> Result<Range, String> {
+        match s.find(':') {
+            Some(i) => {
+                let start = self.eval(editor, &s[..i])?;
+                let end = match s[i+1..].trim() {
+                    "" => None,
+                    end => Some(self.eval(editor, end)?),
+                };
+                Ok(Range { start, end })
+            }
+            None => {
+                let start = self.eval(editor, s)?;
+                let end = start.checked_add(1).ok_or("range out of bounds")?;
+                Ok(Range { start, end : Some(end) })
+            }
+        }
+    }
+
+    fn eval_cond(&mut self, editor : &mut Editor, words : &[String]) -> Result<bool, String> {
+        let words : Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+        match words.as_slice() {
+            ["not", rest @ ..] if ! rest.is_empty() => {
+                let rest : Vec<String> = rest.iter().map(|w| w.to_string()).collect();
+                Ok(! self.eval_cond(editor, &rest)?)
+            }
+            ["find", data] => self.find(editor, data),
+            ["at", off, data] => {
+                let off = self.eval(editor, off)?;
+                let data = parse_bytes(data)?;
+                Ok(editor.cur_file().is_some_and(|f| f.data.get(off..).is_some_and(|rest| rest.starts_with(&data))))
+            }
+            [a, op, b] => {
+                let a = self.eval(editor, a)?;
+                let b = self.eval(editor, b)?;
+                match *op {
+                    "==" => Ok(a == b),
+                    "!=" => Ok(a != b),
+                    "<" => Ok(a < b),
+                    "<=" => Ok(a <= b),
+                    ">" => Ok(a > b),
+                    ">=" => Ok(a >= b),
+                    _ => Err(format!("invalid operator: '{}'", op)),
+                }
+            }
+            _ => Err("expected 'find DATA', 'at OFF DATA', 'A OP B' or 'not COND'".to_string()),
+        }
+    }
+
+    fn find(&mut self, editor : &mut Editor, data : &str) -> Result<bool, String> {
+        let data = parse_bytes(data)?;
+        if ! editor.find_next(&data) {
+            return Ok(false);
+        }
+        let pos = self.get_var(editor, "pos")?;
+        self.set_var("found", pos)?;
+        Ok(true)
+    }
+
+    fn run_block(&mut self, editor : &mut Editor, body : &[Stmt]) -> Result<(), String> {
+        for stmt in body {
+            if editor.quit {
+                break;
+            }
+            match *stmt {
+                Stmt::Cmd(line, ref words) => {
+                    self.run_cmd(editor, words).map_err(|e| format!("line {}: {}", line, e))?;
+                }
+                Stmt::For(line, ref var, ref args, ref block) => {
+                    let (start, end, step) = self.eval_for(editor, args).map_err(|e| format!("line {}: {}", line, e))?;
+                    let mut i = start;
+                    while i < end && ! editor.quit {
+                        self.set_var(var, i).map_err(|e| format!("line {}: {}", line, e))?;
+                        self.run_block(editor, block)?;
+                        i = match i.checked_add(step) {
+                            Some(next) => next,
+                            None => break,
+                        };
+                    }
+                }
+                Stmt::While(line, ref cond, ref block) => {
+                    let mut count = 0;
+                    while self.eval_cond(editor, cond).map_err(|e| format!("line {}: {}", line, e))? {
+                        count += 1;
+                        if count > MAX_LOOP_ITERATIONS {
+                            return Err(format!("line {}: too many loop iterations", line));
+                        }
+                        self.run_block(editor, block)?;
+                        if editor.quit {
+                            break;
+                        }
+                    }
+                }
+                Stmt::If(line, ref cond, ref then_block, ref else_block) => {
+                    if self.eval_cond(editor, cond).map_err(|e| format!("line {}: {}", line, e))? {
+                        self.run_block(editor, then_block)?;
+                    } else {
+                        self.run_block(editor, else_block)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_for(&self, editor : &Editor, args : &[String]) -> Result<(usize, usize, usize), String> {
+        let step = match args.len() {
+            1 => 1,
+            3 if args[1] == "step" => self.eval(editor, &args[2])?,
+            _ => return Err("expected 'for VAR in RANGE [step N]'".to_string()),
+        };
+        if step == 0 {
+            return Err("step can't be 0".to_string());
+        }
+        let range = self.eval_range(editor, &args[0])?;
+        let len = editor.cur_file().map_or(0, |f| f.data.len());
+        let (start, end) = range.resolve(len)?;
+        Ok((start, end, step))
+    }
+
+    fn edit(&mut self, editor : &mut Editor, cmd : BatchCmd) -> Result<(), String> {
+        if editor.read_only {
+            return Err("file is read-only".to_string());
+        }
+        let file = editor.cur_file_mut().ok_or("no file")?;
+        cmd.run(file, &mut *self.out)?;
+        editor.clamp_views();
+        Ok(())
+    }
+
+    fn run_cmd(&mut self, editor : &mut Editor, words : &[String]) -> Result<(), String> {
+        let args : Vec<&str> = words[1..].iter().map(|w| w.as_str()).collect();
+        match words[0].as_str() {
+            "goto" => {
+                check_args(words, 1, 1, "goto OFF")?;
+                let pos = self.eval(editor, args[0])?;
+                editor.set_cursor_pos(pos);
+            }
+            "let" => {
+                if args.len() < 3 || args[1] != "=" {
+                    return Err("usage: let VAR = EXPR".to_string());
+                }
+                let val = self.eval(editor, &args[2..].concat())?;
+                self.set_var(args[0].trim_start_matches('$'), val)?;
+            }
+            "set" => {
+                check_args(words, 2, 2, "set OFF DATA")?;
+                let cmd = BatchCmd::Set(self.eval(editor, args[0])?, parse_bytes(args[1])?);
+                self.edit(editor, cmd)?;
+            }
+            "insert" => {
+                check_args(words, 2, 2, "insert OFF DATA")?;
+                let cmd = BatchCmd::Insert(self.eval(editor, args[0])?, parse_bytes(args[1])?);
+                self.edit(editor, cmd)?;
+            }
+            "delete" => {
+                check_args(words, 1, 1, "delete RANGE")?;
+                let cmd = BatchCmd::Delete(self.eval_range(editor, args[0])?);
+                self.edit(editor, cmd)?;
+            }
+            "fill" => {
+                check_args(words, 2, 2, "fill RANGE DATA")?;
+                let cmd = BatchCmd::Fill(self.eval_range(editor, args[0])?, parse_bytes(args[1])?);
+                self.edit(editor, cmd)?;
+            }
+            "dump" => {
+                check_args(words, 1, 1, "dump RANGE")?;
+                let cmd = BatchCmd::Dump(self.eval_range(editor, args[0])?);
+                let file = editor.cur_file_mut().ok_or("no file")?;
+                cmd.run(file, &mut *self.out)?;
+            }
+            "find" => {
+                check_args(words, 1, 1, "find DATA")?;
+                if ! self.find(editor, args[0])? {
+                    return Err(format!("not found: {}", args[0]));
+                }
+            }
+            "replace" => {
+                if args.len() < 2 || args.len() > 3 || (args.len() == 3 && args[2] != "all") {
+                    return Err("usage: replace OLD NEW [all]".to_string());
+                }
+                self.replace(editor, args[0], args[1], args.len() == 3)?;
+            }
+            "select" => {
+                check_args(words, 1, 1, "select RANGE|none")?;
+                if args[0] == "none" {
+                    editor.set_selection(None);
+                } else {
+                    let len = editor.cur_file().map_or(0, |f| f.data.len());
+                    let (start, end) = self.eval_range(editor, args[0])?.resolve(len)?;
+                    if end <= start || end > len {
+                        return Err(format!("invalid selection: 0x{:x}:0x{:x}", start, end));
+                    }
+                    editor.set_cursor_pos(end - 1);
+                    editor.set_selection(Some(start));
+                }
+            }
+            "copy" => {
+                check_args(words, 0, 0, "copy")?;
+                if ! editor.copy_selection() {
+                    return Err("nothing selected".to_string());
+                }
+            }
+            "paste" => {
+                check_args(words, 0, 0, "paste")?;
+                if editor.read_only {
+                    return Err("file is read-only".to_string());
+                }
+                editor.paste_clipboard();
+            }
+            "save" => {
+                check_args(words, 0, 1, "save [FILE]")?;
+                let path = args.first().map(|p| OsString::from(p.trim_matches('"')));
+                if path.is_none() && editor.cur_file().is_none_or(|f| f.path.is_none()) {
+                    return Err("file has no name, use 'save FILE'".to_string());
+                }
+                editor.save_cur_file(path).map_err(|e| format!("error writing file: {}", e))?;
+            }
+            "open" => {
+                check_args(words, 1, 1, "open FILE")?;
+                let file = File::new_from_file(OsString::from(args[0].trim_matches('"')))
+                    .map_err(|(name, e)| format!("error reading file {:?}: {}", name, e))?;
+                editor.add_file(file);
+                editor.screen.redraw_needed = true;
+            }
+            "template" => {
+                check_args(words, 1, 1, "template FILE")?;
+                editor.apply_template(OsStr::new(args[0].trim_matches('"')))?;
+            }
+            "echo" => {
+                let mut line = vec![];
+                for arg in &args {
+                    if arg.starts_with('"') {
+                        line.push(arg.trim_matches('"').to_string());
+                    } else if arg.starts_with('$') {
+                        line.push(format!("0x{:x}", self.eval(editor, arg)?));
+                    } else {
+                        line.push(arg.to_string());
+                    }
+                }
+                writeln!(self.out, "{}", line.join(" ")).map_err(|e| e.to_string())?;
+            }
+            name => match Action::from_name(name) {
+                Some(action) if ! is_interactive(action) => {
+                    check_args(words, 0, 0, name)?;
+                    editor.run_action(action);
+                }
+                _ => return Err(format!("unknown command: '{}'", name)),
+            },
+        }
+        Ok(())
+    }
+
+    fn replace(&mut self, editor : &mut Editor, old : &str, new : &str, all : bool) -> Result<(), String> {
+        if editor.read_only {
+            return Err("file is read-only".to_string());
+        }
+        let old = parse_bytes(old)?;
+        let new = parse_bytes(new)?;
+        let mut pos = if all { 0 } else { editor.cursor_pos() };
+        let file = editor.cur_file_mut().ok_or("no file")?;
+        let mut last = None;
+        while let Some(found) = file.find(pos, &old) {
+            if old.len() == new.len() {
+                file.set_bytes(found, &new);
+            } else {
+                file.delete(found, found + old.len());
+                file.insert(found, &new);
+            }
+            last = Some(found);
+            pos = found + new.len();
+            if ! all {
+                break;
+            }
+        }
+        editor.clamp_views();
+        let found = last.ok_or("not found")?;
+        editor.set_cursor_pos(found);
+        self.set_var("found", found)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    // runs `script` on a file with `data`, returning the new contents and
+    // the output
+    fn run(script : &str, data : &[u8]) -> Result<(Vec<u8>, String), String> {
+        let mut editor = Editor::new(Box::new(io::empty()), -1);
+        let mut file = File::new();
+        file.data = data.to_vec();
+        editor.add_file(file);
+        let mut out = vec![];
+        Script::parse(script)?.run(&mut editor, &mut out)?;
+        let data = editor.cur_file().map_or(vec![], |f| f.data.clone());
+        Ok((data, String::from_utf8_lossy(&out).into_owned()))
+    }
+
+    #[test]
+    fn edits_and_echo() {
+        let (data, out) = run("set 1 5858; insert 0 \"<\" # comment\ndelete $len-1\necho \"len\" $len", b"abcd").unwrap();
+        assert_eq!(data, b"<aXX");
+        assert_eq!(out, "len 0x4\n");
+    }
+
+    #[test]
+    fn loops_and_conditions() {
+        let script = "
+            let n = 0
+            for i in 0:$len step 2
+                if at $i 61
+                    let n = $n + 1
+                end
+            end
+            goto 0
+            while find 62
+                set $found 63
+            end
+            if not $n == 2
+                echo \"bad\"
+            else
+                echo $n
+            end
+        ";
+        let (data, out) = run(script, b"abab").unwrap();
+        assert_eq!(data, b"acac");
+        assert_eq!(out, "0x2\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(run("for i in 0:1\necho", b"").err().unwrap(), "line 1: 'for' without 'end'");
+        assert_eq!(run("end", b"").err().unwrap(), "line 1: unexpected 'end'");
+        assert_eq!(run("\nfly", b"").err().unwrap(), "line 2: unknown command: 'fly'");
+        assert_eq!(run("goto 1-2", b"").err().unwrap(), "line 1: negative result: '1-2'");
+        assert_eq!(run("echo $x", b"").err().unwrap(), "line 1: unknown variable: '$x'");
+        assert_eq!(run("let len = 1", b"").err().unwrap(), "line 1: can't assign to '$len'");
+        assert_eq!(run("while 1 == 1\nend", b"").err().unwrap(), "line 1: too many loop iterations");
+    }
+
+    #[test]
+    fn numbers_out_of_range() {
+        let max = usize::MAX;
+        assert_eq!(run(&format!("goto {}+1", max), b"").err().unwrap(), format!("line 1: number out of range: '{}+1'", max));
+        assert_eq!(run(&format!("delete {}", max), b"ab").err().unwrap(), "line 1: range out of bounds");
+        assert_eq!(run(&format!("set {} 00", max), b"ab").err().unwrap(), "line 1: range out of bounds");
+        assert_eq!(run(&format!("if at {} 00\necho\nend", max), b"ab").unwrap().1, "");
+        let script = format!("let n = 0\nfor i in 0:{} step 0x{:x}\nlet n = $n + 1\nend\necho $n", max, max / 2 + 1);
+        assert_eq!(run(&script, b"").unwrap().1, "0x2\n");
+    }
+}
@@ -0,0 +1,157 @@
+
+// runs of printable characters, like the strings utility
+
+use std::mem;
+
+// strings listed before giving up on a file
+const MAX_STRINGS : usize = 200_000;
+
+pub struct FoundString {
+    pub pos : usize,
+    // length in bytes
+    pub len : usize,
+    pub encoding : &'static str,
+    pub text : String,
+}
+
+fn is_printable(c : char) -> bool {
+    ! c.is_control() && c != '\u{fffd}'
+}
+
+// UTF-8 runs; the ones with only ASCII characters are listed as ASCII
+fn find_utf8(data : &[u8], min_len : usize, found : &mut Vec<FoundString>) {
+    let mut start = 0;
+    let mut text = String::new();
+    let mut pos = 0;
+    while pos <= data.len() {
+        let ch = if pos < data.len() { decode_utf8(&data[pos..]) } else { None };
+        match ch {
+            Some((c, n)) if is_printable(c) => {
+                if text.is_empty() {
+                    start = pos;
+                }
+                text.push(c);
+                pos += n;
+            }
+            _ => {
+                if text.chars().count() >= min_len {
+                    let encoding = if text.is_ascii() { "ascii" } else { "utf-8" };
+                    found.push(FoundString { pos : start, len : pos - start, encoding, text : mem::take(&mut text) });
+                }
+                text.clear();
+                pos += 1;
+            }
+        }
+    }
+}
+
+fn decode_utf8(data : &[u8]) -> Option<(char, usize)> {
+    let n = match data[0] {
+        0x00..=0x7f => 1,
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return None,
+    };
+    let s = std::str::from_utf8(data.get(..n)?).ok()?;
+    s.chars().next().map(|c| (c, n))
+}
+
+// UTF-16 runs starting at `first`; only Latin-1 characters count, or
+// almost any pair of bytes would be printable
+fn find_utf16(data : &[u8], first : usize, big_endian : bool, min_len : usize, found : &mut Vec<FoundString>) {
+    let encoding = if big_endian { "utf-16be" } else { "utf-16le" };
+    let mut start = first;
+    let mut text = String::new();
+    let mut pos = first;
+    loop {
+        let unit = if pos + 1 < data.len() {
+            let (hi, lo) = if big_endian { (data[pos], data[pos+1]) } else { (data[pos+1], data[pos]) };
+            Some((hi as u32) << 8 | lo as u32)
+        } else {
+            None
+        };
+        match unit.and_then(std::char::from_u32) {
+            Some(c) if (c as u32) < 0x100 && is_printable(c) => {
+                if text.is_empty() {
+                    start = pos;
+                }
+                text.push(c);
+            }
+            _ => {
+                if text.chars().count() >= min_len {
+                    found.push(FoundString { pos : start, len : pos - start, encoding, text : mem::take(&mut text) });
+                }
+                text.clear();
+                if unit.is_none() {
+                    break;
+                }
+            }
+        }
+        pos += 2;
+    }
+}
+
+// all strings of at least `min_len` characters, sorted by offset; when
+// strings overlap (like a UTF-16LE string seen as UTF-16BE one byte
+// later) only the longest is kept
+pub fn find_strings(data : &[u8], min_len : usize) -> Vec<FoundString> {
+    let min_len = min_len.max(1);
+    let mut found = vec![];
+    find_utf8(data, min_len, &mut found);
+    for &big_endian in &[false, true] {
+        for first in 0..2 {
+            find_utf16(data, first, big_endian, min_len, &mut found);
+        }
+    }
+    found.sort_by_key(|s| s.pos);
+
+    let mut strings : Vec<FoundString> = vec![];
+    for s in found {
+        match strings.last_mut() {
+            Some(last) if s.pos < last.pos + last.len => {
+                if s.len > last.len {
+                    *last = s;
+                }
+            }
+            _ => {
+                if strings.len() >= MAX_STRINGS {
+                    break;
+                }
+                strings.push(s);
+            }
+        }
+    }
+    strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(data : &[u8], min_len : usize) -> Vec<(usize, usize, &'static str, String)> {
+        find_strings(data, min_len).into_iter().map(|s| (s.pos, s.len, s.encoding, s.text)).collect()
+    }
+
+    #[test]
+    fn encodings() {
+        let mut data = b"\x00\x01hello\x00ab\x00caf\xc3\xa9!\x00\x01".to_vec();
+        data.extend_from_slice(b"w\x00i\x00d\x00e\x00\x00\x00");
+        data.extend_from_slice(b"\x00B\x00i\x00g\x00!");
+        assert_eq!(found(&data, 4), [
+            (2, 5, "ascii", "hello".to_string()),
+            (11, 6, "utf-8", "café!".to_string()),
+            (19, 8, "utf-16le", "wide".to_string()),
+            (29, 8, "utf-16be", "Big!".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn minimum_length() {
+        assert_eq!(found(b"ab\x00abc", 3), [(3, 3, "ascii", "abc".to_string())]);
+        assert_eq!(found(b"ab\x00abc", 0).len(), 2);
+        assert_eq!(found(b"", 1).len(), 0);
+        // a truncated UTF-8 sequence ends the string before it
+        assert_eq!(found(b"abcd\xe2\x82", 4), [(0, 4, "ascii", "abcd".to_string())]);
+    }
+}
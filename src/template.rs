@@ -0,0 +1,833 @@
+use std::fs;
+use std::path::Path;
+use std::ffi::OsStr;
+
+use super::config::config_dir;
+use super::overlay::{Field, Overlay};
+
+// guards against templates that never stop producing fields
+const MAX_FIELDS : usize = 1_000_000;
+const MAX_STRUCT_DEPTH : usize = 64;
+// bytes shown in the value of byte arrays
+const MAX_VALUE_BYTES : usize = 16;
+
+// binary operators by precedence, higher binds tighter
+const BINARY_OPS : &[(&str, u8)] = &[
+    ("||", 1), ("&&", 2), ("|", 3), ("^", 4), ("&", 5),
+    ("==", 6), ("!=", 6), ("<", 7), ("<=", 7), (">", 7), (">=", 7),
+    ("<<", 8), (">>", 8), ("+", 9), ("-", 9), ("*", 10), ("/", 10), ("%", 10),
+];
+
+const TWO_CHAR_SYMBOLS : &[&str] = &["==", "!=", "<=", ">=", "<<", ">>", "&&", "||"];
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(i64),
+    Sym(String),
+    // end of a statement: a newline, ';' or ','
+    End,
+}
+
+enum Expr {
+    Num(i64),
+    Name(String),
+    Unary(String, Box<Expr>),
+    Binary(String, Box<Expr>, Box<Expr>),
+}
+
+enum Count {
+    Expr(Expr),
+    // as many as fit in the rest of the file
+    Rest,
+}
+
+struct FieldDecl {
+    line : usize,
+    ty : String,
+    name : String,
+    count : Option<Count>,
+    // bitfield members with their width, starting at the lowest bit
+    bits : Vec<(String, u32)>,
+}
+
+enum Item {
+    Field(FieldDecl),
+    Endian(bool),
+    Seek(usize, Expr),
+    If(usize, Expr, Vec<Item>, Vec<Item>),
+}
+
+struct Enum {
+    name : String,
+    base : String,
+    values : Vec<(String, i64)>,
+}
+
+// a structure template: 'struct' and 'enum' definitions and the fields
+// decoded from the start of the file
+pub struct Template {
+    pub name : String,
+    structs : Vec<(String, Vec<Item>)>,
+    enums : Vec<Enum>,
+    body : Vec<Item>,
+}
+
+fn lex(text : &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = vec![];
+    for (num, line) in text.lines().enumerate() {
+        let line_num = num + 1;
+        let chars : Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '#' {
+                break;
+            } else if c.is_whitespace() {
+                i += 1;
+            } else if c == ';' || c == ',' {
+                tokens.push((line_num, Token::End));
+                i += 1;
+            } else if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word : String = chars[start..i].iter().filter(|&&c| c != '_').collect();
+                let num = match word.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => word.parse::<i64>(),
+                };
+                match num {
+                    Ok(n) => tokens.push((line_num, Token::Num(n))),
+                    Err(_) => return Err(format!("line {}: invalid number '{}'", line_num, word)),
+                }
+            } else if c.is_alphabetic() || c == '_' || c == '$' {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                    i += 1;
+                }
+                tokens.push((line_num, Token::Ident(chars[start..i].iter().collect())));
+            } else {
+                let two : String = chars[i..chars.len().min(i+2)].iter().collect();
+                if TWO_CHAR_SYMBOLS.contains(&two.as_str()) {
+                    tokens.push((line_num, Token::Sym(two)));
+                    i += 2;
+                } else if "{}[]():=<>+-*/%&|^!~".contains(c) {
+                    tokens.push((line_num, Token::Sym(c.to_string())));
+                    i += 1;
+                } else {
+                    return Err(format!("line {}: unexpected '{}'", line_num, c));
+                }
+            }
+        }
+        tokens.push((line_num, Token::End));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens : Vec<(usize, Token)>,
+    pos : usize,
+}
+
+impl Parser {
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.1)
+    }
+
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some(t) => t.0,
+            None => self.tokens.last().map_or(0, |t| t.0),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|t| t.1.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn is_sym(&self, sym : &str) -> bool {
+        match self.peek() {
+            Some(Token::Sym(s)) => s == sym,
+            _ => false,
+        }
+    }
+
+    fn skip_ends(&mut self) {
+        while self.peek() == Some(&Token::End) {
+            self.pos += 1;
+        }
+    }
+
+    fn error<T>(&self, msg : &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line(), msg))
+    }
+
+    fn expect_sym(&mut self, sym : &str) -> Result<(), String> {
+        if self.is_sym(sym) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", sym))
+        }
+    }
+
+    fn expect_ident(&mut self, what : &str) -> Result<String, String> {
+        match self.peek() {
+            Some(&Token::Ident(_)) => match self.next() {
+                Some(Token::Ident(name)) => Ok(name),
+                _ => unreachable!(),
+            },
+            _ => self.error(&format!("expected {}", what)),
+        }
+    }
+
+    fn expect_end(&mut self) -> Result<(), String> {
+        match self.peek() {
+            Some(&Token::End) => {
+                self.pos += 1;
+                Ok(())
+            }
+            None => Ok(()),
+            _ if self.is_sym("}") => Ok(()),
+            _ => self.error("expected end of line"),
+        }
+    }
+
+    fn parse_template(&mut self) -> Result<Template, String> {
+        let mut template = Template { name : "template".to_string(), structs : vec![], enums : vec![], body : vec![] };
+        loop {
+            self.skip_ends();
+            match self.peek() {
+                None => break,
+                Some(Token::Ident(word)) if word == "struct" => {
+                    self.pos += 1;
+                    let name = self.expect_ident("struct name")?;
+                    let body = self.parse_block()?;
+                    template.structs.push((name, body));
+                }
+                Some(Token::Ident(word)) if word == "enum" => {
+                    self.pos += 1;
+                    template.enums.push(self.parse_enum()?);
+                }
+                _ => {
+                    let item = self.parse_item()?;
+                    template.body.push(item);
+                }
+            }
+        }
+        Ok(template)
+    }
+
+    fn parse_enum(&mut self) -> Result<Enum, String> {
+        let name = self.expect_ident("enum name")?;
+        self.expect_sym(":")?;
+        let base = self.expect_ident("enum type")?;
+        if prim_type(&base).is_none_or(|t| t.float) {
+            return self.error(&format!("enum type must be an integer type, not '{}'", base));
+        }
+        self.skip_ends();
+        self.expect_sym("{")?;
+        let mut values = vec![];
+        loop {
+            self.skip_ends();
+            if self.is_sym("}") {
+                self.pos += 1;
+                break;
+            }
+            let value_name = self.expect_ident("enum value name")?;
+            self.expect_sym("=")?;
+            let value = match self.parse_expr()? {
+                Expr::Num(n) => n,
+                Expr::Unary(ref op, ref e) if op == "-" => match **e {
+                    Expr::Num(n) => -n,
+                    _ => return self.error("enum values must be numbers"),
+                },
+                _ => return self.error("enum values must be numbers"),
+            };
+            values.push((value_name, value));
+            self.expect_end()?;
+        }
+        Ok(Enum { name, base, values })
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Item>, String> {
+        self.skip_ends();
+        self.expect_sym("{")?;
+        let mut items = vec![];
+        loop {
+            self.skip_ends();
+            if self.is_sym("}") {
+                self.pos += 1;
+                return Ok(items);
+            }
+            if self.peek().is_none() {
+                return self.error("missing '}'");
+            }
+            items.push(self.parse_item()?);
+        }
+    }
+
+    fn parse_item(&mut self) -> Result<Item, String> {
+        let line = self.line();
+        let word = self.expect_ident("a field type or statement")?;
+        let item = match word.as_str() {
+            "endian" => match self.expect_ident("'big' or 'little'")?.as_str() {
+                "big" => Item::Endian(true),
+                "little" => Item::Endian(false),
+                _ => return self.error("expected 'big' or 'little'"),
+            },
+            "seek" => Item::Seek(line, self.parse_expr()?),
+            "if" => {
+                let cond = self.parse_expr()?;
+                let then_items = self.parse_block()?;
+                let else_items = if self.peek() == Some(&Token::Ident("else".to_string())) {
+                    self.pos += 1;
+                    if self.peek() == Some(&Token::Ident("if".to_string())) {
+                        vec![self.parse_item()?]
+                    } else {
+                        self.parse_block()?
+                    }
+                } else {
+                    vec![]
+                };
+                return Ok(Item::If(line, cond, then_items, else_items));
+            }
+            "struct" | "enum" => return self.error(&format!("'{}' is only allowed at the top level", word)),
+            _ => {
+                let name = self.expect_ident("field name")?;
+                let count = if self.is_sym("[") {
+                    self.pos += 1;
+                    let count = if self.is_sym("*") {
+                        self.pos += 1;
+                        Count::Rest
+                    } else {
+                        Count::Expr(self.parse_expr()?)
+                    };
+                    self.expect_sym("]")?;
+                    Some(count)
+                } else {
+                    None
+                };
+                let bits = if self.is_sym("{") { self.parse_bits()? } else { vec![] };
+                Item::Field(FieldDecl { line, ty : word, name, count, bits })
+            }
+        };
+        self.expect_end()?;
+        Ok(item)
+    }
+
+    fn parse_bits(&mut self) -> Result<Vec<(String, u32)>, String> {
+        self.expect_sym("{")?;
+        let mut bits = vec![];
+        loop {
+            self.skip_ends();
+            if self.is_sym("}") {
+                self.pos += 1;
+                return Ok(bits);
+            }
+            let name = self.expect_ident("bitfield name")?;
+            self.expect_sym(":")?;
+            match self.next() {
+                Some(Token::Num(n)) if n > 0 && n <= 64 => bits.push((name, n as u32)),
+                _ => return self.error("expected a bit count between 1 and 64"),
+            }
+            self.expect_end()?;
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        self.parse_binary(1)
+    }
+
+    fn parse_binary(&mut self, min_prec : u8) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Sym(s)) => BINARY_OPS.iter().find(|op| op.0 == s && op.1 >= min_prec).cloned(),
+                _ => None,
+            };
+            let (op, prec) = match op {
+                Some(op) => op,
+                None => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.parse_binary(prec + 1)?;
+            left = Expr::Binary(op.to_string(), Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Ident(name)) => Ok(Expr::Name(name)),
+            Some(Token::Sym(ref s)) if s == "(" => {
+                let expr = self.parse_expr()?;
+                self.expect_sym(")")?;
+                Ok(expr)
+            }
+            Some(Token::Sym(ref s)) if s == "-" || s == "!" || s == "~" => {
+                Ok(Expr::Unary(s.clone(), Box::new(self.parse_unary()?)))
+            }
+            _ => {
+                self.pos -= 1;
+                self.error("expected an expression")
+            }
+        }
+    }
+
+}
+
+#[derive(Copy, Clone)]
+struct PrimType {
+    size : usize,
+    signed : bool,
+    float : bool,
+    is_char : bool,
+    big_endian : Option<bool>,
+}
+
+// u8..u64, i8..i64, f32, f64 and char; integers and floats can end in
+// "be" or "le" to override the current byte order
+fn prim_type(name : &str) -> Option<PrimType> {
+    let (base, big_endian) = if let Some(base) = name.strip_suffix("be") {
+        (base, Some(true))
+    } else if let Some(base) = name.strip_suffix("le") {
+        (base, Some(false))
+    } else {
+        (name, None)
+    };
+    let (size, signed, float, is_char) = match base {
+        "u8" => (1, false, false, false),
+        "u16" => (2, false, false, false),
+        "u32" => (4, false, false, false),
+        "u64" => (8, false, false, false),
+        "i8" => (1, true, false, false),
+        "i16" => (2, true, false, false),
+        "i32" => (4, true, false, false),
+        "i64" => (8, true, false, false),
+        "f32" => (4, false, true, false),
+        "f64" => (8, false, true, false),
+        "char" if big_endian.is_none() => (1, false, false, true),
+        _ => return None,
+    };
+    Some(PrimType { size, signed, float, is_char, big_endian })
+}
+
+pub fn read_uint(data : &[u8], pos : usize, size : usize, big_endian : bool) -> u64 {
+    let bytes = &data[pos..pos+size];
+    if big_endian {
+        bytes.iter().fold(0, |v, &b| (v << 8) | b as u64)
+    } else {
+        bytes.iter().rev().fold(0, |v, &b| (v << 8) | b as u64)
+    }
+}
+
+fn format_char(b : u8) -> String {
+    if (0x20..0x7f).contains(&b) {
+        format!("'{}'", b as char)
+    } else {
+        format!("0x{:02x}", b)
+    }
+}
+
+pub fn format_int(v : i64, signed : bool) -> String {
+    if signed || (0..10).contains(&v) {
+        v.to_string()
+    } else {
+        format!("{} (0x{:x})", v as u64, v)
+    }
+}
+
+// readable value of a byte or char array
+pub fn format_bytes(bytes : &[u8], is_char : bool) -> String {
+    if is_char {
+        let text : String = bytes.iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| if (0x20..0x7f).contains(&b) { b as char } else { '.' })
+            .collect();
+        format!("\"{}\"", text)
+    } else {
+        let mut text : Vec<String> = bytes.iter().take(MAX_VALUE_BYTES).map(|b| format!("{:02x}", b)).collect();
+        if bytes.len() > MAX_VALUE_BYTES {
+            text.push("...".to_string());
+        }
+        text.join(" ")
+    }
+}
+
+// values of the decoded fields, with dotted names for struct members
+type Vars = Vec<(String, i64)>;
+
+struct Eval<'t> {
+    template : &'t Template,
+    data : &'t [u8],
+    pos : usize,
+    big_endian : bool,
+    scopes : Vec<Vars>,
+    n_fields : usize,
+}
+
+impl<'t> Eval<'t> {
+
+    fn lookup(&self, line : usize, name : &str) -> Result<i64, String> {
+        match name {
+            "$pos" => return Ok(self.pos as i64),
+            "$len" => return Ok(self.data.len() as i64),
+            _ => (),
+        }
+        for scope in self.scopes.iter().rev() {
+            if let Some(var) = scope.iter().rev().find(|v| v.0 == name) {
+                return Ok(var.1);
+            }
+        }
+        for e in &self.template.enums {
+            if let Some(value) = e.values.iter().find(|v| v.0 == name) {
+                return Ok(value.1);
+            }
+        }
+        Err(format!("line {}: unknown name '{}'", line, name))
+    }
+
+    fn eval(&self, line : usize, expr : &Expr) -> Result<i64, String> {
+        match *expr {
+            Expr::Num(n) => Ok(n),
+            Expr::Name(ref name) => self.lookup(line, name),
+            Expr::Unary(ref op, ref e) => {
+                let v = self.eval(line, e)?;
+                Ok(match op.as_str() {
+                    "-" => v.wrapping_neg(),
+                    "!" => (v == 0) as i64,
+                    _ => ! v,
+                })
+            }
+            Expr::Binary(ref op, ref a, ref b) => {
+                let a = self.eval(line, a)?;
+                let b = self.eval(line, b)?;
+                Ok(match op.as_str() {
+                    "||" => (a != 0 || b != 0) as i64,
+                    "&&" => (a != 0 && b != 0) as i64,
+                    "|" => a | b,
+                    "^" => a ^ b,
+                    "&" => a & b,
+                    "==" => (a == b) as i64,
+                    "!=" => (a != b) as i64,
+                    "<" => (a < b) as i64,
+                    "<=" => (a <= b) as i64,
+                    ">" => (a > b) as i64,
+                    ">=" => (a >= b) as i64,
+                    "<<" => a.wrapping_shl(b as u32),
+                    ">>" => ((a as u64).wrapping_shr(b as u32)) as i64,
+                    "+" => a.wrapping_add(b),
+                    "-" => a.wrapping_sub(b),
+                    "*" => a.wrapping_mul(b),
+                    "/" | "%" if b == 0 => return Err(format!("line {}: division by zero", line)),
+                    "/" => a.wrapping_div(b),
+                    _ => a.wrapping_rem(b),
+                })
+            }
+        }
+    }
+
+    fn check_len(&self, line : usize, name : &str, len : usize) -> Result<(), String> {
+        if len > self.data.len() - self.pos {
+            return Err(format!("line {}: field '{}' at 0x{:x} goes past the end of the file", line, name, self.pos));
+        }
+        Ok(())
+    }
+
+    fn add_field(&mut self, line : usize) -> Result<(), String> {
+        self.n_fields += 1;
+        if self.n_fields > MAX_FIELDS {
+            return Err(format!("line {}: too many fields", line));
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, items : &[Item], out : &mut Vec<Field>) -> Result<(), String> {
+        for item in items {
+            match *item {
+                Item::Endian(big) => self.big_endian = big,
+                Item::Seek(line, ref expr) => {
+                    let pos = self.eval(line, expr)?;
+                    if pos < 0 || pos as u64 > self.data.len() as u64 {
+                        return Err(format!("line {}: seek to 0x{:x} is outside the file", line, pos));
+                    }
+                    self.pos = pos as usize;
+                }
+                Item::If(line, ref cond, ref then_items, ref else_items) => {
+                    if self.eval(line, cond)? != 0 {
+                        self.run(then_items, out)?;
+                    } else {
+                        self.run(else_items, out)?;
+                    }
+                }
+                Item::Field(ref decl) => {
+                    let field = self.field(decl)?;
+                    out.push(field);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn field(&mut self, decl : &FieldDecl) -> Result<Field, String> {
+        let line = decl.line;
+        let count = match decl.count {
+            None => {
+                let (mut field, vars) = self.element(line, &decl.ty, &decl.name)?;
+                if ! decl.bits.is_empty() {
+                    let value = match vars.first() {
+                        Some(var) if var.0.is_empty() => var.1 as u64,
+                        _ => return Err(format!("line {}: bitfields need an integer type", line)),
+                    };
+                    let mut shift = 0;
+                    for &(ref name, width) in &decl.bits {
+                        let v = if shift >= 64 { 0 } else { value >> shift };
+                        let v = if width >= 64 { v } else { v & ((1 << width) - 1) };
+                        field.children.push(Field::new(name, format_int(v as i64, false), field.start, field.len));
+                        self.scopes.last_mut().unwrap().push((format!("{}.{}", decl.name, name), v as i64));
+                        shift += width;
+                    }
+                }
+                let scope = self.scopes.last_mut().unwrap();
+                for (name, v) in vars {
+                    let name = if name.is_empty() { decl.name.clone() } else { format!("{}.{}", decl.name, name) };
+                    scope.push((name, v));
+                }
+                return Ok(field);
+            }
+            Some(Count::Rest) => None,
+            Some(Count::Expr(ref expr)) => {
+                let n = self.eval(line, expr)?;
+                if n < 0 {
+                    return Err(format!("line {}: negative array size {}", line, n));
+                }
+                Some(n as usize)
+            }
+        };
+
+        self.add_field(line)?;
+        let start = self.pos;
+        // byte arrays are shown as a single field
+        if let Some(prim) = prim_type(&decl.ty).filter(|t| t.size == 1) {
+            let len = count.unwrap_or(self.data.len() - self.pos);
+            self.check_len(line, &decl.name, len)?;
+            self.pos += len;
+            let value = format_bytes(&self.data[start..self.pos], prim.is_char);
+            return Ok(Field::new(&decl.name, value, start, len));
+        }
+
+        let mut children = vec![];
+        let mut i = 0;
+        loop {
+            match count {
+                Some(n) if i >= n => break,
+                None if self.pos >= self.data.len() => break,
+                _ => (),
+            }
+            let elem_start = self.pos;
+            let (elem, _) = self.element(line, &decl.ty, &format!("[{}]", i))?;
+            children.push(elem);
+            i += 1;
+            if count.is_none() && self.pos == elem_start {
+                break;
+            }
+        }
+        let mut field = Field::new(&decl.name, format!("[{}]", i), start, self.pos - start);
+        field.children = children;
+        Ok(field)
+    }
+
+    // decodes one value of type `ty` at the current position
+    fn element(&mut self, line : usize, ty : &str, name : &str) -> Result<(Field, Vars), String> {
+        self.add_field(line)?;
+        let start = self.pos;
+        let template = self.template;
+
+        let (prim, enum_def) = match template.enums.iter().find(|e| e.name == ty) {
+            Some(e) => (prim_type(&e.base), Some(e)),
+            None => (prim_type(ty), None),
+        };
+        if let Some(prim) = prim {
+            self.check_len(line, name, prim.size)?;
+            let big_endian = prim.big_endian.unwrap_or(self.big_endian);
+            let raw = read_uint(self.data, start, prim.size, big_endian);
+            self.pos += prim.size;
+            let shift = 64 - 8 * prim.size as u32;
+            let v = if prim.signed { ((raw << shift) as i64) >> shift } else { raw as i64 };
+            let value = if prim.float {
+                if prim.size == 4 { f32::from_bits(raw as u32).to_string() } else { f64::from_bits(raw).to_string() }
+            } else if prim.is_char {
+                format_char(raw as u8)
+            } else if let Some(e) = enum_def {
+                match e.values.iter().find(|value| value.1 == v) {
+                    Some(value) => format!("{} ({})", value.0, v),
+                    None => format!("{} (unknown)", v),
+                }
+            } else {
+                format_int(v, prim.signed)
+            };
+            let vars = if prim.float { vec![] } else { vec![(String::new(), v)] };
+            return Ok((Field::new(name, value, start, prim.size), vars));
+        }
+
+        let body = match template.structs.iter().find(|s| s.0 == ty) {
+            Some(s) => &s.1,
+            None => return Err(format!("line {}: unknown type '{}'", line, ty)),
+        };
+        if self.scopes.len() > MAX_STRUCT_DEPTH {
+            return Err(format!("line {}: structs nested too deeply", line));
+        }
+        let big_endian = self.big_endian;
+        self.scopes.push(vec![]);
+        let mut children = vec![];
+        let result = self.run(body, &mut children);
+        let vars = self.scopes.pop().unwrap_or_default();
+        self.big_endian = big_endian;
+        result?;
+        let mut field = Field::new(name, String::new(), start, self.pos - start);
+        field.children = children;
+        Ok((field, vars))
+    }
+
+}
+
+impl Template {
+
+    pub fn parse(text : &str) -> Result<Template, String> {
+        let mut parser = Parser { tokens : lex(text)?, pos : 0 };
+        parser.parse_template()
+    }
+
+    // reads a template file; a bare name is also looked up in
+    // <config dir>/templates
+    pub fn load(name : &OsStr) -> Result<Template, String> {
+        let mut path = Path::new(name).to_path_buf();
+        if ! path.exists() && ! name.to_string_lossy().contains('/') {
+            if let Some(dir) = config_dir() {
+                path = dir.join("templates").join(name);
+            }
+        }
+        let text = fs::read_to_string(&path).map_err(|e| format!("error reading {}: {}", path.display(), e))?;
+        let mut template = Template::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(name) = path.file_name() {
+            template.name = name.to_string_lossy().into_owned();
+        }
+        Ok(template)
+    }
+
+    pub fn apply(&self, data : &[u8]) -> Result<Overlay, String> {
+        let mut eval = Eval {
+            template : self,
+            data,
+            pos : 0,
+            big_endian : false,
+            scopes : vec![vec![]],
+            n_fields : 0,
+        };
+        let mut fields = vec![];
+        eval.run(&self.body, &mut fields)?;
+        Ok(Overlay::new(&self.name, fields))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(fields : &[Field], depth : usize, out : &mut Vec<String>) {
+        for f in fields {
+            out.push(format!("{}{} {} {}+{}", "  ".repeat(depth), f.name, f.value, f.start, f.len));
+            dump(&f.children, depth + 1, out);
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let t = Template::parse("
+            enum Kind : u8 { A = 1, B = 2 }
+            struct Item { u16 id; u8 len; char text[len] }
+            char magic[4]
+            endian big
+            u32 count
+            Kind kind
+            u8 flags { ready : 1, mode : 3 }
+            Item items[count]
+            if kind == B { u8 extra } else { u16le other }
+            seek 0x20
+            i8 neg
+            u8 rest[*]
+        ").unwrap();
+        let mut data = b"HDR!\x00\x00\x00\x02\x02\x0b".to_vec();
+        data.extend_from_slice(b"\x00\x01\x02hi\x00\x02\x01x\x07");
+        data.resize(0x20, 0);
+        data.extend_from_slice(b"\xff\x01\x02");
+        let o = t.apply(&data).unwrap();
+        let mut out = vec![];
+        dump(&o.fields, 0, &mut out);
+        assert_eq!(out, [
+            "magic \"HDR!\" 0+4",
+            "count 2 4+4",
+            "kind B (2) 8+1",
+            "flags 11 (0xb) 9+1",
+            "  ready 1 9+1",
+            "  mode 5 9+1",
+            "items [2] 10+9",
+            "  [0]  10+5",
+            "    id 1 10+2",
+            "    len 2 12+1",
+            "    text \"hi\" 13+2",
+            "  [1]  15+4",
+            "    id 2 15+2",
+            "    len 1 17+1",
+            "    text \"x\" 18+1",
+            "extra 7 19+1",
+            "neg -1 32+1",
+            "rest 01 02 33+2",
+        ]);
+    }
+
+    fn apply_error(template : &str, data : &[u8]) -> String {
+        match Template::parse(template) {
+            Ok(t) => t.apply(data).err().unwrap_or_default(),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let cases : &[(&str, &str)] = &[
+            ("u8 a\nu24 b", "line 2: unknown type 'u24'"),
+            ("u32 a", "line 1: field 'a' at 0x0 goes past the end of the file"),
+            ("u8 a[b]", "line 1: unknown name 'b'"),
+            ("u8 a\nu8 b[a / 0]", "line 2: division by zero"),
+            ("struct S { S s }\nS s", "line 1: structs nested too deeply"),
+            ("u8 a[", "line 1: expected an expression"),
+            ("u8 a = 1", "line 1: expected end of line"),
+            ("u8 0x1g", "line 1: invalid number '0x1g'"),
+            ("seek 5", "line 1: seek to 0x5 is outside the file"),
+            ("if 1 { u8 a", "line 1: missing '}'"),
+        ];
+        for &(template, error) in cases {
+            assert_eq!(apply_error(template, b"ab"), error, "{}", template);
+        }
+    }
+
+    // templates reading past the end stop with an error, never a panic
+    #[test]
+    fn truncated_data() {
+        let t = Template::parse("u32 n\nstruct S { u8 len; u8 data[len] }\nS items[n]\nu8 rest[*]").unwrap();
+        let data = b"\x03\x00\x00\x00\x01a\x02bc\x00tail";
+        assert!(t.apply(data).is_ok());
+        for len in 0..data.len() - 4 {
+            assert!(t.apply(&data[..len]).is_err());
+        }
+    }
+}
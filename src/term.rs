@@ -1,5 +1,6 @@
 
 use libc::{c_ushort, ioctl, TIOCGWINSZ};
+use std::fs;
 use std::io;
 use std::io::Read;
 use termios::*;
@@ -12,66 +13,73 @@ struct winsize {
     ws_ypixel : c_ushort,
 }
 
-#[macro_export]
-macro_rules! ctrl_key {
-    ($x:expr) => (($x as u32) & 0x1f);
+pub type Mods = u8;
+
+pub const MOD_NONE             : Mods = 0;
+pub const MOD_SHIFT            : Mods = 1;
+pub const MOD_ALT              : Mods = 2;
+pub const MOD_CTRL             : Mods = 4;
+pub const MOD_CTRL_SHIFT       : Mods = MOD_CTRL | MOD_SHIFT;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Special {
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Ins,
+    Del,
+    PageUp,
+    PageDown,
+    F(u8),
 }
 
-#[macro_export]
-macro_rules! alt_key {
-    ($x:expr) => ((($x as u32) & 0x1f) + KEY_ALT_MIN);
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
 }
 
-pub const KEY_ALT_MIN          : u32 = 3000;
-
-pub const KEY_ARROW_UP         : u32 = 1001;
-pub const KEY_ARROW_DOWN       : u32 = 1002;
-pub const KEY_ARROW_LEFT       : u32 = 1003;
-pub const KEY_ARROW_RIGHT      : u32 = 1004;
-
-pub const KEY_HOME             : u32 = 1005;
-pub const KEY_END              : u32 = 1006;
-pub const KEY_INS              : u32 = 1007;
-pub const KEY_DEL              : u32 = 1008;
-pub const KEY_PAGE_UP          : u32 = 1009;
-pub const KEY_PAGE_DOWN        : u32 = 1010;
-
-pub const KEY_CTRL_HOME        : u32 = 1011;
-pub const KEY_CTRL_END         : u32 = 1012;
-pub const KEY_CTRL_INS         : u32 = 1013;
-pub const KEY_CTRL_DEL         : u32 = 1014;
-pub const KEY_CTRL_PAGE_UP     : u32 = 1015;
-pub const KEY_CTRL_PAGE_DOWN   : u32 = 1016;
-
-pub const KEY_F1               : u32 = 1021;
-pub const KEY_F2               : u32 = 1022;
-pub const KEY_F3               : u32 = 1023;
-pub const KEY_F4               : u32 = 1024;
-pub const KEY_F5               : u32 = 1025;
-pub const KEY_F6               : u32 = 1026;
-pub const KEY_F7               : u32 = 1027;
-pub const KEY_F8               : u32 = 1028;
-pub const KEY_F9               : u32 = 1029;
-pub const KEY_F10              : u32 = 1030;
-pub const KEY_F11              : u32 = 1031;
-pub const KEY_F12              : u32 = 1032;
-
-pub const KEY_SHIFT_F1         : u32 = 1041;
-pub const KEY_SHIFT_F2         : u32 = 1042;
-pub const KEY_SHIFT_F3         : u32 = 1043;
-pub const KEY_SHIFT_F4         : u32 = 1044;
-pub const KEY_SHIFT_F5         : u32 = 1045;
-pub const KEY_SHIFT_F6         : u32 = 1046;
-pub const KEY_SHIFT_F7         : u32 = 1047;
-pub const KEY_SHIFT_F8         : u32 = 1048;
-pub const KEY_SHIFT_F9         : u32 = 1049;
-pub const KEY_SHIFT_F10        : u32 = 1050;
-pub const KEY_SHIFT_F11        : u32 = 1051;
-pub const KEY_SHIFT_F12        : u32 = 1052;
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MouseAction {
+    Press(MouseButton),
+    DoubleClick(MouseButton),
+    Drag(MouseButton),
+    Release,
+    WheelUp,
+    WheelDown,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MouseEvent {
+    pub action : MouseAction,
+    pub x : i32,
+    pub y : i32,
+    pub mods : Mods,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Alt(char),
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Esc,
+    Special(Special, Mods),
+    Paste(Vec<u8>),
+    Mouse(MouseEvent),
+    Unknown,
+}
 
 pub fn setup_term(fd : i32) -> io::Result<Termios> {
     let orig = Termios::from_fd(fd)?;
-    let mut termios = orig.clone();
+    let mut termios = orig;
 
     termios.c_iflag &= !(IGNBRK | BRKINT | PARMRK | ISTRIP | INLCR | IGNCR | ICRNL | IXON);
     termios.c_oflag &= !OPOST;
@@ -81,7 +89,7 @@ pub fn setup_term(fd : i32) -> io::Result<Termios> {
     termios.c_cc[VMIN] = 0;
     termios.c_cc[VTIME] = 1;
     
-    tcsetattr(fd, TCSANOW, &mut termios)?;
+    tcsetattr(fd, TCSANOW, &termios)?;
 
     Ok(orig)
 }
@@ -90,6 +98,26 @@ pub fn restore_term(fd : i32, termios : &mut Termios) -> io::Result<()> {
     tcsetattr(fd, TCSANOW, termios)
 }
 
+pub fn is_tty(fd : i32) -> bool {
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+pub fn open_tty() -> io::Result<fs::File> {
+    fs::OpenOptions::new().read(true).write(true).open("/dev/tty")
+}
+
+// make stdout point to `fd`, returning a duplicate of the original stdout
+pub fn redirect_stdout(fd : i32) -> io::Result<i32> {
+    let saved = unsafe { libc::dup(1) };
+    if saved < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::dup2(fd, 1) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(saved)
+}
+
 pub fn get_win_size(fd : i32) -> Option<(i32, i32)> {
     let w = winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
     let r = unsafe { ioctl(fd, TIOCGWINSZ, &w) };
@@ -100,13 +128,76 @@ pub fn get_win_size(fd : i32) -> Option<(i32, i32)> {
     }
 } 
 
-fn is_letter(b : u8) -> bool {
-    b >= b'A' && b <= b'Z'
-}
-
-fn is_digit(b : u8) -> bool {
-    b >= b'0' && b <= b'9'
-}
+use self::Special::*;
+
+// CSI <n> ~ (and the rxvt variants ending in $ ^ @)
+const TILDE_KEYS : &[(u32, Special, Mods)] = &[
+    (1,  Home,     MOD_NONE),
+    (2,  Ins,      MOD_NONE),
+    (3,  Del,      MOD_NONE),
+    (4,  End,      MOD_NONE),
+    (5,  PageUp,   MOD_NONE),
+    (6,  PageDown, MOD_NONE),
+    (7,  Home,     MOD_NONE),
+    (8,  End,      MOD_NONE),
+
+    (11, F(1),     MOD_NONE),
+    (12, F(2),     MOD_NONE),
+    (13, F(3),     MOD_NONE),
+    (14, F(4),     MOD_NONE),
+    (15, F(5),     MOD_NONE),
+    (17, F(6),     MOD_NONE),
+    (18, F(7),     MOD_NONE),
+    (19, F(8),     MOD_NONE),
+    (20, F(9),     MOD_NONE),
+    (21, F(10),    MOD_NONE),
+    (23, F(11),    MOD_NONE),
+    (24, F(12),    MOD_NONE),
+
+    (25, F(1),     MOD_SHIFT),
+    (26, F(2),     MOD_SHIFT),
+    (28, F(3),     MOD_SHIFT),
+    (29, F(4),     MOD_SHIFT),
+    (31, F(5),     MOD_SHIFT),
+    (32, F(6),     MOD_SHIFT),
+    (33, F(7),     MOD_SHIFT),
+    (34, F(8),     MOD_SHIFT),
+];
+
+// CSI <letter> and SS3 <letter>
+const LETTER_KEYS : &[(u8, Special, Mods)] = &[
+    (b'A', Up,       MOD_NONE),
+    (b'B', Down,     MOD_NONE),
+    (b'C', Right,    MOD_NONE),
+    (b'D', Left,     MOD_NONE),
+    (b'H', Home,     MOD_NONE),
+    (b'F', End,      MOD_NONE),
+    (b'P', F(1),     MOD_NONE),
+    (b'Q', F(2),     MOD_NONE),
+    (b'R', F(3),     MOD_NONE),
+    (b'S', F(4),     MOD_NONE),
+];
+
+// rxvt: CSI <letter> is shift+arrow, SS3 <letter> is ctrl+arrow
+const RXVT_LETTER_KEYS : &[(u8, Special)] = &[
+    (b'a', Up),
+    (b'b', Down),
+    (b'c', Right),
+    (b'd', Left),
+];
+
+// Linux console: CSI [ <letter>
+const LINUX_LETTER_KEYS : &[(u8, Special)] = &[
+    (b'A', F(1)),
+    (b'B', F(2)),
+    (b'C', F(3)),
+    (b'D', F(4)),
+    (b'E', F(5)),
+];
+
+const PASTE_START : u32 = 200;
+const PASTE_END : &[u8] = b"\x1b[201~";
+const PASTE_TIMEOUT : u32 = 50;  // in units of VTIME (1/10s)
 
 /*
 pub fn debug(s : &str) {
@@ -116,167 +207,457 @@ pub fn debug(s : &str) {
 }
 */
 
-fn parse_key(s : &[u8]) -> ::std::io::Result<u32> {
-    /*
-    debug("key: <ESC>");
-    for b in s {
-        if *b >= 32u8 && *b < 127u8 {
-            print!("{}", *b as char);
-        } else if *b == 0x1b {
-            print!("<ESC>");
-        } else {
-            print!("<\\x{:x}>", b);
+fn read_byte(reader : &mut dyn Read) -> io::Result<Option<u8>> {
+    let mut buffer = [0u8; 1];
+    match reader.read(&mut buffer)? {
+        0 => Ok(None),
+        _ => Ok(Some(buffer[0])),
+    }
+}
+
+// xterm encodes modifiers as 1 + (shift|alt<<1|ctrl<<2|meta<<3)
+fn xterm_mods(param : Option<&u32>) -> Mods {
+    match param {
+        Some(&m) if m > 1 => {
+            let m = (m - 1) as u8;
+            let mut mods = m & (MOD_SHIFT | MOD_ALT | MOD_CTRL);
+            if m & 8 != 0 {
+                mods |= MOD_ALT;
+            }
+            mods
         }
+        _ => MOD_NONE,
     }
-    super::screen::flush();
-    */
+}
+
+fn find_letter(table : &[(u8, Special, Mods)], b : u8) -> Option<(Special, Mods)> {
+    table.iter().find(|e| e.0 == b).map(|e| (e.1, e.2))
+}
+
+fn find_plain(table : &[(u8, Special)], b : u8) -> Option<Special> {
+    table.iter().find(|e| e.0 == b).map(|e| e.1)
+}
 
-    if s.len() == 0 {
-        return Ok(0x1b);
+fn parse_params(s : &[u8]) -> Vec<u32> {
+    s.split(|&b| b == b';')
+        .map(|p| p.iter()
+             .filter(|b| b.is_ascii_digit())
+             .fold(0u32, |n, &b| n.saturating_mul(10).saturating_add((b - b'0') as u32)))
+        .collect()
+}
+
+fn parse_csi(params : &[u32], fin : u8, mods : Mods) -> Key {
+    match fin {
+        b'~' | b'$' | b'^' | b'@' => {
+            let extra = match fin {
+                b'$' => MOD_SHIFT,
+                b'^' => MOD_CTRL,
+                b'@' => MOD_CTRL_SHIFT,
+                _ => MOD_NONE,
+            };
+            let code = params.first().cloned().unwrap_or(0);
+            match TILDE_KEYS.iter().find(|e| e.0 == code) {
+                Some(e) => Key::Special(e.1, e.2 | extra | mods | xterm_mods(params.get(1))),
+                None => Key::Unknown,
+            }
+        }
+        b'Z' => Key::BackTab,
+        _ => {
+            if let Some((key, key_mods)) = find_letter(LETTER_KEYS, fin) {
+                Key::Special(key, key_mods | mods | xterm_mods(params.get(1)))
+            } else if let Some(key) = find_plain(RXVT_LETTER_KEYS, fin) {
+                Key::Special(key, MOD_SHIFT | mods)
+            } else {
+                Key::Unknown
+            }
+        }
     }
-    
-    if s.len() == 1 && s[0] >= 32 && s[0] < 127 {
-        return Ok(alt_key!(s[0]));
+}
+
+fn parse_ss3(s : &[u8], mods : Mods) -> Key {
+    let fin = match s.last() {
+        Some(&b) => b,
+        None => return Key::Unknown,
+    };
+    // some terminals send SS3 <mod> <letter> or SS3 1;<mod> <letter> for modified keys
+    let mods = mods | xterm_mods(parse_params(&s[..s.len()-1]).last());
+    if fin == b'M' {
+        return Key::Enter;
     }
+    if let Some((key, key_mods)) = find_letter(LETTER_KEYS, fin) {
+        Key::Special(key, key_mods | mods)
+    } else if let Some(key) = find_plain(RXVT_LETTER_KEYS, fin) {
+        Key::Special(key, MOD_CTRL | mods)
+    } else {
+        Key::Unknown
+    }
+}
 
-    if s.len() == 2 && s[0] == b'[' && is_letter(s[1]) {
-        let key = match s[1] {
-            b'A' => KEY_ARROW_UP,
-            b'B' => KEY_ARROW_DOWN,
-            b'C' => KEY_ARROW_RIGHT,
-            b'D' => KEY_ARROW_LEFT,
-            b'H' => KEY_HOME,
-            b'F' => KEY_END,
-            _ => 0xffff_ffff,
-        };
-        return Ok(key);
+fn read_paste(reader : &mut dyn Read) -> io::Result<Key> {
+    let mut data = Vec::new();
+    let mut idle = 0;
+    while ! data.ends_with(PASTE_END) {
+        match read_byte(reader)? {
+            Some(b) => {
+                data.push(b);
+                idle = 0;
+            }
+            // give up if the terminal never sends the end marker
+            None if idle >= PASTE_TIMEOUT => return Ok(Key::Paste(data)),
+            None => idle += 1,
+        }
     }
+    let len = data.len() - PASTE_END.len();
+    data.truncate(len);
+    Ok(Key::Paste(data))
+}
 
-    if s.len() == 3 && s[0] == b'[' && is_digit(s[1]) && s[2] == b'~' {
-        let key = match s[1] {
-            b'1' => KEY_HOME,
-            b'2' => KEY_INS,
-            b'3' => KEY_DEL,
-            b'4' => KEY_END,
-            b'5' => KEY_PAGE_UP,
-            b'6' => KEY_PAGE_DOWN,
-            b'7' => KEY_HOME,
-            b'8' => KEY_END,
-            _ => 0xffff_ffff,
-        };
-        return Ok(key);
+// xterm mouse button byte: low 2 bits are the button (3 = release),
+// then shift/meta/ctrl flags, motion and wheel
+fn parse_mouse(b : u32, x : u32, y : u32, release : bool) -> Key {
+    let mut mods = MOD_NONE;
+    if b & 4 != 0 { mods |= MOD_SHIFT; }
+    if b & 8 != 0 { mods |= MOD_ALT; }
+    if b & 16 != 0 { mods |= MOD_CTRL; }
+
+    let button = match b & 3 {
+        0 => Some(MouseButton::Left),
+        1 => Some(MouseButton::Middle),
+        2 => Some(MouseButton::Right),
+        _ => None,
+    };
+    let action = if b & 64 != 0 {
+        match b & 3 {
+            0 => MouseAction::WheelUp,
+            1 => MouseAction::WheelDown,
+            _ => return Key::Unknown,
+        }
+    } else {
+        match button {
+            Some(_) if release => MouseAction::Release,
+            Some(button) if b & 32 != 0 => MouseAction::Drag(button),
+            Some(button) => MouseAction::Press(button),
+            None if b & 32 != 0 => return Key::Unknown,
+            None => MouseAction::Release,
+        }
+    };
+    Key::Mouse(MouseEvent {
+        action,
+        x : x as i32,
+        y : y as i32,
+        mods,
+    })
+}
+
+// legacy X10 encoding: CSI M <b+32> <x+32> <y+32>
+fn read_x10_mouse(reader : &mut dyn Read) -> io::Result<Key> {
+    let mut vals = [0u32; 3];
+    for v in vals.iter_mut() {
+        match read_byte(reader)? {
+            Some(b) if b >= 32 => *v = (b - 32) as u32,
+            _ => return Ok(Key::Unknown),
+        }
     }
+    Ok(parse_mouse(vals[0], vals[1], vals[2], false))
+}
 
-    if s.len() == 3 && s[0] == b'[' && is_digit(s[1]) && s[2] == b'^' {
-        let key = match s[1] {
-            b'1' => KEY_CTRL_HOME,
-            b'2' => KEY_CTRL_INS,
-            b'3' => KEY_CTRL_DEL,
-            b'4' => KEY_CTRL_END,
-            b'5' => KEY_CTRL_PAGE_UP,
-            b'6' => KEY_CTRL_PAGE_DOWN,
-            b'7' => KEY_CTRL_HOME,
-            b'8' => KEY_CTRL_END,
-            _ => 0xffff_ffff,
+fn read_csi(reader : &mut dyn Read, mods : Mods) -> io::Result<Key> {
+    let mut buffer = Vec::with_capacity(16);
+    loop {
+        let b = match read_byte(reader)? {
+            Some(b) => b,
+            None => return Ok(Key::Unknown),
         };
-        return Ok(key);
+        if buffer.is_empty() && b == b'[' {
+            return Ok(match read_byte(reader)? {
+                Some(b) => find_plain(LINUX_LETTER_KEYS, b).map_or(Key::Unknown, |k| Key::Special(k, mods)),
+                None => Key::Unknown,
+            });
+        }
+        if buffer.is_empty() && b == b'M' {
+            return read_x10_mouse(reader);
+        }
+        if (0x40..=0x7e).contains(&b) || b == b'$' {
+            let params = parse_params(&buffer);
+            if buffer.first() == Some(&b'<') && (b == b'M' || b == b'm') && params.len() == 3 {
+                // SGR mouse: CSI < b ; x ; y M (press) or m (release)
+                return Ok(parse_mouse(params[0], params[1], params[2], b == b'm'));
+            }
+            if b == b'~' && params.first() == Some(&PASTE_START) {
+                return read_paste(reader);
+            }
+            return Ok(parse_csi(&params, b, mods));
+        }
+        if buffer.len() >= 32 {
+            return Ok(Key::Unknown);
+        }
+        buffer.push(b);
     }
+}
 
-    if s.len() == 5 && s[0] == b'[' && is_digit(s[1]) && s[2] == b';' && is_digit(s[3]) {
-        if s[1] == b'1' && s[3] == b'5' && s[4] == b'H' { return Ok(KEY_CTRL_HOME); }
-        if s[1] == b'1' && s[3] == b'5' && s[4] == b'F' { return Ok(KEY_CTRL_END); }
+fn read_ss3(reader : &mut dyn Read, mods : Mods) -> io::Result<Key> {
+    let mut buffer = Vec::with_capacity(8);
+    while buffer.len() < 8 {
+        match read_byte(reader)? {
+            Some(b) => {
+                buffer.push(b);
+                if ! (b.is_ascii_digit() || b == b';') {
+                    break;
+                }
+            }
+            None => break,
+        }
     }
+    Ok(parse_ss3(&buffer, mods))
+}
 
-    if s.len() == 2 && s[0] == b'O' {
-        let key = match s[1] {
-            b'F' => KEY_HOME,
-            b'H' => KEY_END,
-            b'P' => KEY_F1,
-            b'Q' => KEY_F2,
-            b'R' => KEY_F3,
-            b'S' => KEY_F4,
-            _ => 0xffff_ffff,
-        };
-        return Ok(key);
+fn read_utf8(reader : &mut dyn Read, first : u8) -> io::Result<Key> {
+    let len = if first & 0xe0 == 0xc0 {
+        2
+    } else if first & 0xf0 == 0xe0 {
+        3
+    } else if first & 0xf8 == 0xf0 {
+        4
+    } else {
+        return Ok(Key::Unknown);
+    };
+    let mut buffer = vec![first];
+    while buffer.len() < len {
+        match read_byte(reader)? {
+            Some(b) if b & 0xc0 == 0x80 => buffer.push(b),
+            _ => return Ok(Key::Unknown),
+        }
     }
+    Ok(match ::std::str::from_utf8(&buffer).ok().and_then(|s| s.chars().next()) {
+        Some(c) => Key::Char(c),
+        None => Key::Unknown,
+    })
+}
+
+fn decode_byte(reader : &mut dyn Read, b : u8) -> io::Result<Key> {
+    Ok(match b {
+        13 => Key::Enter,
+        9 => Key::Tab,
+        8 | 127 => Key::Backspace,
+        0x1b => Key::Esc,
+        0 => Key::Ctrl(' '),
+        1..=26 => Key::Ctrl((b'a' + b - 1) as char),
+        28..=31 => Key::Ctrl((b + 0x40) as char),
+        32..=126 => Key::Char(b as char),
+        _ => return read_utf8(reader, b),
+    })
+}
+
+pub fn read_key(reader : &mut dyn Read) -> io::Result<Key> {
+    let b = loop {
+        if let Some(b) = read_byte(reader)? {
+            break b;
+        }
+    };
+    if b != 0x1b {
+        return decode_byte(reader, b);
+    }
+
+    match read_byte(reader)? {
+        None => Ok(Key::Esc),
+        Some(b'[') => read_csi(reader, MOD_NONE),
+        Some(b'O') => read_ss3(reader, MOD_NONE),
+        Some(0x1b) => match read_byte(reader)? {
+            // rxvt sends ESC before the sequence when alt is pressed
+            Some(b'[') => read_csi(reader, MOD_ALT),
+            Some(b'O') => read_ss3(reader, MOD_ALT),
+            _ => Ok(Key::Esc),
+        },
+        Some(b) => Ok(match decode_byte(reader, b)? {
+            Key::Char(c) => Key::Alt(c),
+            Key::Special(key, mods) => Key::Special(key, mods | MOD_ALT),
+            _ => Key::Unknown,
+        }),
+    }
+}
 
-    if s.len() == 3 && s[0] == b'[' && s[1] == b'[' && is_letter(s[2]) {
-        let key = match s[1] {
-            b'A' => KEY_F1,
-            b'B' => KEY_F2,
-            b'C' => KEY_F3,
-            b'D' => KEY_F4,
-            b'E' => KEY_F5,
-            _ => 0xffff_ffff,
+const KEY_NAMES : &[(&str, Key)] = &[
+    ("Enter",     Key::Enter),
+    ("Tab",       Key::Tab),
+    ("BackTab",   Key::BackTab),
+    ("Backspace", Key::Backspace),
+    ("Esc",       Key::Esc),
+    ("Space",     Key::Char(' ')),
+];
+
+const SPECIAL_NAMES : &[(&str, Special)] = &[
+    ("Up",       Up),
+    ("Down",     Down),
+    ("Left",     Left),
+    ("Right",    Right),
+    ("Home",     Home),
+    ("End",      End),
+    ("Ins",      Ins),
+    ("Del",      Del),
+    ("PageUp",   PageUp),
+    ("PageDown", PageDown),
+];
+
+// name of a key in the format read by parse_key_name(); pastes, mouse
+// events and unknown keys have no name
+pub fn key_name(key : &Key) -> Option<String> {
+    if let Some(&(name, _)) = KEY_NAMES.iter().find(|e| e.1 == *key) {
+        return Some(name.to_string());
+    }
+    match *key {
+        Key::Char(c) => Some(c.to_string()),
+        Key::Ctrl(c) => Some(format!("C-{}", c)),
+        Key::Alt(' ') => Some("M-Space".to_string()),
+        Key::Alt(c) => Some(format!("M-{}", c)),
+        Key::Special(special, mods) => {
+            let mut name = String::new();
+            if mods & MOD_CTRL != 0 { name.push_str("C-"); }
+            if mods & MOD_ALT != 0 { name.push_str("M-"); }
+            if mods & MOD_SHIFT != 0 { name.push_str("S-"); }
+            match special {
+                F(n) => name.push_str(&format!("F{}", n)),
+                _ => name.push_str(SPECIAL_NAMES.iter().find(|e| e.1 == special)?.0),
+            }
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+// parse key names like "C-x", "M-,", "S-Up", "C-Home", "F5", "Enter"
+pub fn parse_key_name(name : &str) -> Option<Key> {
+    let mut mods = MOD_NONE;
+    let mut rest = name;
+    while rest.len() > 2 && rest.as_bytes()[1] == b'-' {
+        mods |= match rest.as_bytes()[0] {
+            b'C' => MOD_CTRL,
+            b'M' | b'A' => MOD_ALT,
+            b'S' => MOD_SHIFT,
+            _ => return None,
         };
-        return Ok(key);
+        rest = &rest[2..];
     }
 
-    if s.len() == 4 && s[0] == b'[' && is_digit(s[1]) && is_digit(s[2]) && s[3] == b'~' {
-        let key = match (s[1], s[2]) {
-            (b'1', b'5') => KEY_F5,
-            (b'1', b'7') => KEY_F6,
-            (b'1', b'8') => KEY_F7,
-            (b'1', b'9') => KEY_F8,
-            (b'2', b'0') => KEY_F9,
-            (b'2', b'1') => KEY_F10,
-            (b'2', b'3') => KEY_F11,
-            (b'2', b'4') => KEY_F12,
-
-            (b'2', b'5') => KEY_SHIFT_F1,
-            (b'2', b'6') => KEY_SHIFT_F2,
-            (b'2', b'8') => KEY_SHIFT_F3,
-            (b'2', b'9') => KEY_SHIFT_F4,
-            (b'3', b'1') => KEY_SHIFT_F5,
-            (b'3', b'2') => KEY_SHIFT_F6,
-            (b'3', b'3') => KEY_SHIFT_F7,
-            (b'3', b'4') => KEY_SHIFT_F8,
-
-            (_, _) => 0xffff_ffff,
+    if let Some(&(_, special)) = SPECIAL_NAMES.iter().find(|e| e.0.eq_ignore_ascii_case(rest)) {
+        return Some(Key::Special(special, mods));
+    }
+    if rest.len() > 1 && (rest.starts_with('F') || rest.starts_with('f')) {
+        if let Ok(n) = rest[1..].parse::<u8>() {
+            if (1..=12).contains(&n) {
+                return Some(Key::Special(F(n), mods));
+            }
+        }
+    }
+    if let Some((_, key)) = KEY_NAMES.iter().find(|e| e.0.eq_ignore_ascii_case(rest)) {
+        return match (key, mods) {
+            (_, MOD_NONE) => Some(key.clone()),
+            (&Key::Tab, MOD_SHIFT) => Some(Key::BackTab),
+            (&Key::Char(c), MOD_ALT) => Some(Key::Alt(c)),
+            _ => None,
         };
-        return Ok(key);
     }
-    
-    return Ok(0xffff_ffff)
+
+    let mut chars = rest.chars();
+    let c = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    match mods {
+        MOD_NONE => Some(Key::Char(c)),
+        MOD_CTRL if c.is_ascii_alphabetic() => Some(Key::Ctrl(c.to_ascii_lowercase())),
+        MOD_CTRL if "\\]^_".contains(c) => Some(Key::Ctrl(c)),
+        MOD_ALT => Some(Key::Alt(c)),
+        _ => None,
+    }
 }
 
-pub fn read_key(reader : &mut Read) -> io::Result<u32> {
-    //let mut reader = unsafe {
-    //    use std::os::unix::io::FromRawFd;
-    //    ::std::fs::File::from_raw_fd(::libc::STDOUT_FILENO)
-    //};
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut buffer = [0u8;32];
-    while reader.read(&mut buffer[0..1])? == 0 {
+    fn key(mut input : &[u8]) -> Key {
+        read_key(&mut input).unwrap()
     }
-    if buffer[0] != 0x1b {
-        return Ok(buffer[0] as u32);
+
+    fn mouse(action : MouseAction, x : i32, y : i32, mods : Mods) -> Key {
+        Key::Mouse(MouseEvent { action, x, y, mods })
     }
 
-    let mut n = 0_usize;
-    if reader.read(&mut buffer[n..n+1])? != 1 {
-        return Ok(0x1b);
+    #[test]
+    fn plain_keys() {
+        assert_eq!(key(b"a"), Key::Char('a'));
+        assert_eq!(key(b"\r"), Key::Enter);
+        assert_eq!(key(b"\t"), Key::Tab);
+        assert_eq!(key(b"\x7f"), Key::Backspace);
+        assert_eq!(key(b"\x18"), Key::Ctrl('x'));
+        assert_eq!(key(b"\x00"), Key::Ctrl(' '));
+        assert_eq!(key(b"\x1f"), Key::Ctrl('_'));
+        assert_eq!(key(b"\x1b"), Key::Esc);
+        assert_eq!(key(b"\x1bx"), Key::Alt('x'));
+        assert_eq!(key("é".as_bytes()), Key::Char('é'));
+        assert_eq!(key(b"\xc3"), Key::Unknown);
     }
-    n += 1;
 
-    macro_rules! next_byte {
-        () => {
-            if reader.read(&mut buffer[n..n+1])? != 1 {
-                return parse_key(&buffer[0..n]);
-            }
-            n += 1;
-        }
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(key(b"\x1b[A"), Key::Special(Up, MOD_NONE));
+        assert_eq!(key(b"\x1bOD"), Key::Special(Left, MOD_NONE));
+        assert_eq!(key(b"\x1b[1;5C"), Key::Special(Right, MOD_CTRL));
+        assert_eq!(key(b"\x1b[3;2~"), Key::Special(Del, MOD_SHIFT));
+        assert_eq!(key(b"\x1b[6~"), Key::Special(PageDown, MOD_NONE));
+        assert_eq!(key(b"\x1b[15~"), Key::Special(F(5), MOD_NONE));
+        assert_eq!(key(b"\x1b[Z"), Key::BackTab);
+        assert_eq!(key(b"\x1b[[B"), Key::Special(F(2), MOD_NONE));
+        assert_eq!(key(b"\x1b\x1b[5~"), Key::Special(PageUp, MOD_ALT));
+        // rxvt
+        assert_eq!(key(b"\x1b[a"), Key::Special(Up, MOD_SHIFT));
+        assert_eq!(key(b"\x1bOd"), Key::Special(Left, MOD_CTRL));
+        assert_eq!(key(b"\x1b[7^"), Key::Special(Home, MOD_CTRL));
+        assert_eq!(key(b"\x1b[99~"), Key::Unknown);
+        assert_eq!(key(b"\x1b[1;5"), Key::Unknown);
+        assert_eq!(key(b"\x1b[200~ab\x1b[201~"), Key::Paste(b"ab".to_vec()));
+        assert_eq!(key(b"\x1b[200~ab"), Key::Paste(b"ab".to_vec()));
     }
 
-    while n < buffer.len()-3 {
-        next_byte!();
-        let b = buffer[n-1];
-        if b == b'~' || b == b'^' || is_letter(b) {
-            return parse_key(&buffer[0..n]);
-        }
-        if b == b';' {
-            next_byte!();
+    #[test]
+    fn mouse_events() {
+        assert_eq!(key(b"\x1b[<0;10;5M"), mouse(MouseAction::Press(MouseButton::Left), 10, 5, MOD_NONE));
+        assert_eq!(key(b"\x1b[<0;10;5m"), mouse(MouseAction::Release, 10, 5, MOD_NONE));
+        assert_eq!(key(b"\x1b[<34;1;2M"), mouse(MouseAction::Drag(MouseButton::Right), 1, 2, MOD_NONE));
+        assert_eq!(key(b"\x1b[<65;3;4M"), mouse(MouseAction::WheelDown, 3, 4, MOD_NONE));
+        assert_eq!(key(b"\x1b[<16;3;4M"), mouse(MouseAction::Press(MouseButton::Left), 3, 4, MOD_CTRL));
+        assert_eq!(key(b"\x1b[M *+"), mouse(MouseAction::Press(MouseButton::Left), 10, 11, MOD_NONE));
+    }
+
+    #[test]
+    fn parse_key_names() {
+        assert_eq!(parse_key_name("C-x"), Some(Key::Ctrl('x')));
+        assert_eq!(parse_key_name("M-,"), Some(Key::Alt(',')));
+        assert_eq!(parse_key_name("S-Up"), Some(Key::Special(Up, MOD_SHIFT)));
+        assert_eq!(parse_key_name("C-M-Home"), Some(Key::Special(Home, MOD_CTRL | MOD_ALT)));
+        assert_eq!(parse_key_name("pageup"), Some(Key::Special(PageUp, MOD_NONE)));
+        assert_eq!(parse_key_name("F5"), Some(Key::Special(F(5), MOD_NONE)));
+        assert_eq!(parse_key_name("S-Tab"), Some(Key::BackTab));
+        assert_eq!(parse_key_name("M-Space"), Some(Key::Alt(' ')));
+        assert_eq!(parse_key_name("Enter"), Some(Key::Enter));
+        assert_eq!(parse_key_name("F13"), None);
+        assert_eq!(parse_key_name("X-a"), None);
+        assert_eq!(parse_key_name("C-Enter"), None);
+    }
+
+    // macros are saved by name, so every key they record must come back
+    #[test]
+    fn key_names_round_trip() {
+        let keys = [
+            Key::Char('x'), Key::Char(' '), Key::Char('é'), Key::Ctrl('x'), Key::Alt(','), Key::Alt(' '),
+            Key::Enter, Key::Tab, Key::BackTab, Key::Backspace, Key::Esc,
+            Key::Special(Up, MOD_SHIFT), Key::Special(Del, MOD_CTRL | MOD_ALT | MOD_SHIFT), Key::Special(F(12), MOD_NONE),
+        ];
+        for key in &keys {
+            let name = key_name(key).unwrap();
+            assert_eq!(parse_key_name(&name).as_ref(), Some(key), "{}", name);
         }
+        assert_eq!(key_name(&Key::Paste(vec![])), None);
+        assert_eq!(key_name(&Key::Unknown), None);
     }
-    return parse_key(&buffer[0..n]);
 }
@@ -0,0 +1,219 @@
+
+use super::screen::*;
+use super::term::*;
+use super::editor::{Editor, HEADER_LINES, FOOTER_LINES};
+use super::overlay::{Field, Overlay};
+
+// width of the hex and text columns with 16 bytes per line
+const HEX_VIEW_WIDTH : i32 = 80;
+const MIN_PANEL_WIDTH : i32 = 30;
+const NARROW_PANEL_WIDTH : i32 = 48;
+
+// index paths of the visible fields, in display order
+fn flatten(fields : &[Field], path : &mut Vec<usize>, expanded : &[Vec<usize>], rows : &mut Vec<Vec<usize>>) {
+    for (i, field) in fields.iter().enumerate() {
+        path.push(i);
+        rows.push(path.clone());
+        if ! field.children.is_empty() && expanded.contains(path) {
+            flatten(&field.children, path, expanded, rows);
+        }
+        path.pop();
+    }
+}
+
+// panel at the right of the hex view showing the fields of the structure
+// overlay as a tree; moving in the tree moves the cursor to the field
+pub struct TreePanel<'a, 'b : 'a> {
+    editor : &'a mut Editor<'b>,
+    quit : bool,
+    expanded : Vec<Vec<usize>>,
+    rows : Vec<Vec<usize>>,
+    sel_index : usize,
+    top_index : usize,
+}
+
+impl<'a, 'b> TreePanel<'a, 'b> {
+
+    pub fn new(editor : &'a mut Editor<'b>) -> TreePanel<'a, 'b> {
+        let mut panel = TreePanel {
+            editor,
+            quit : false,
+            expanded : vec![],
+            rows : vec![],
+            sel_index : 0,
+            top_index : 0,
+        };
+        // start at the field under the cursor
+        let pos = panel.editor.cursor_pos();
+        let path = panel.overlay().map_or(vec![], |o| o.path_at(pos));
+        for n in 1..path.len() {
+            panel.expanded.push(path[..n].to_vec());
+        }
+        panel.update_rows();
+        if let Some(i) = panel.rows.iter().position(|row| *row == path) {
+            panel.sel_index = i;
+        }
+        panel
+    }
+
+    fn overlay(&self) -> Option<&Overlay> {
+        self.editor.cur_file().and_then(|f| f.overlay.as_ref())
+    }
+
+    fn update_rows(&mut self) {
+        let mut rows = vec![];
+        if let Some(overlay) = self.overlay() {
+            flatten(&overlay.fields, &mut vec![], &self.expanded, &mut rows);
+        }
+        self.rows = rows;
+        if self.sel_index >= self.rows.len() {
+            self.sel_index = self.rows.len().saturating_sub(1);
+        }
+    }
+
+    // position and size of the panel: (x, y, w, h), h counting only list lines
+    fn geometry(&self) -> (i32, i32, i32, i32) {
+        let screen = &self.editor.screen;
+        let w = if screen.w - HEX_VIEW_WIDTH >= MIN_PANEL_WIDTH {
+            screen.w - HEX_VIEW_WIDTH
+        } else {
+            screen.w.min(NARROW_PANEL_WIDTH)
+        };
+        let h = (screen.h - HEADER_LINES - FOOTER_LINES - 1).max(1);
+        (screen.w - w + 1, HEADER_LINES + 1, w, h)
+    }
+
+    fn row_text(&self, index : usize) -> String {
+        let path = &self.rows[index];
+        let field = match self.overlay().and_then(|o| o.field(path)) {
+            Some(field) => field,
+            None => return String::new(),
+        };
+        let marker = if field.children.is_empty() { ' ' }
+                     else if self.expanded.contains(path) { '-' }
+                     else { '+' };
+        let mut text = format!("{:1$}{2} {3}", "", 2 * (path.len() - 1), marker, field.name);
+        if ! field.value.is_empty() {
+            text.push_str(" = ");
+            text.push_str(&field.value);
+        }
+        text
+    }
+
+    fn draw(&mut self) {
+        if self.editor.screen.redraw_needed {
+            self.editor.draw_main_screen();
+        }
+        let (x, y, w, h) = self.geometry();
+        let theme = self.editor.screen.theme;
+        let width = (w - 1).max(0) as usize;
+
+        set_color_pair(theme.header);
+        self.editor.screen.move_cursor(x, y);
+        let title = format!(" {}", self.overlay().map_or("", |o| o.name.as_str()));
+        let title : String = title.chars().take(width).collect();
+        print!("{:1$} ", title, width);
+        for line in 0..h {
+            let index = self.top_index + line as usize;
+            let text = if index < self.rows.len() { self.row_text(index) } else { String::new() };
+            let text : String = text.chars().take(width).collect();
+            set_color_pair(if index == self.sel_index { theme.tab_active } else { theme.message });
+            self.editor.screen.move_cursor(x, y + 1 + line);
+            print!("{:1$} ", text, width);
+        }
+        reset_color();
+        flush_screen();
+    }
+
+    fn ensure_sel_visible(&mut self) {
+        let (_, _, _, h) = self.geometry();
+        let h = h as usize;
+        if self.sel_index < self.top_index {
+            self.top_index = self.sel_index;
+        } else if self.sel_index >= self.top_index + h {
+            self.top_index = self.sel_index + 1 - h;
+        }
+    }
+
+    // moves the cursor to the selected field and selects its bytes
+    fn show_field(&mut self) {
+        let (start, len) = match self.rows.get(self.sel_index).and_then(|path| self.overlay()?.field(path)) {
+            Some(field) => (field.start, field.len),
+            None => return,
+        };
+        self.editor.set_cursor_pos(start);
+        self.editor.set_selection(if len > 1 { Some(start + len - 1) } else { None });
+    }
+
+    fn has_children(&self, index : usize) -> bool {
+        self.overlay().and_then(|o| o.field(&self.rows[index])).is_some_and(|f| ! f.children.is_empty())
+    }
+
+    fn set_expanded(&mut self, expand : bool) {
+        let path = self.rows[self.sel_index].clone();
+        if expand {
+            self.expanded.push(path);
+        } else {
+            self.expanded.retain(|p| ! p.starts_with(&path));
+        }
+        self.update_rows();
+    }
+
+    fn process_input(&mut self) {
+        if self.rows.is_empty() {
+            self.quit = true;
+            return;
+        }
+        let (_, _, _, h) = self.geometry();
+        let page = (h as usize).max(2) - 1;
+        let n_rows = self.rows.len();
+        let old_index = self.sel_index;
+        let is_expanded = self.expanded.contains(&self.rows[self.sel_index]);
+        match self.editor.read_key() {
+            Key::Ctrl('c') | Key::Esc | Key::Tab => self.quit = true,
+            Key::Special(Special::Up, MOD_NONE) if self.sel_index > 0 => self.sel_index -= 1,
+            Key::Special(Special::Down, MOD_NONE) if self.sel_index + 1 < n_rows => self.sel_index += 1,
+            Key::Special(Special::PageUp, MOD_NONE) => self.sel_index = self.sel_index.saturating_sub(page),
+            Key::Special(Special::PageDown, MOD_NONE) => self.sel_index = (self.sel_index + page).min(n_rows - 1),
+            Key::Special(Special::Home, MOD_NONE) => self.sel_index = 0,
+            Key::Special(Special::End, MOD_NONE) => self.sel_index = n_rows - 1,
+            Key::Special(Special::Right, MOD_NONE) if self.has_children(self.sel_index) => {
+                if is_expanded {
+                    self.sel_index += 1;
+                } else {
+                    self.set_expanded(true);
+                }
+            }
+            Key::Special(Special::Left, MOD_NONE) => {
+                if is_expanded {
+                    self.set_expanded(false);
+                } else {
+                    // go to the parent
+                    let path = &self.rows[self.sel_index];
+                    let parent = &path[..path.len()-1];
+                    if let Some(i) = self.rows.iter().position(|row| row == parent) {
+                        self.sel_index = i;
+                    }
+                }
+            }
+            Key::Enter | Key::Char(' ') if self.has_children(self.sel_index) => self.set_expanded(! is_expanded),
+            Key::Enter => self.quit = true,
+            _ => (),
+        }
+        self.ensure_sel_visible();
+        if self.sel_index != old_index {
+            self.show_field();
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.ensure_sel_visible();
+        self.show_field();
+        while ! self.quit && ! self.editor.quit {
+            self.draw();
+            self.process_input();
+        }
+        self.editor.screen.redraw_needed = true;
+    }
+
+}
@@ -0,0 +1,61 @@
+
+use super::editor::EditorPane;
+
+// a window onto one of the open buffers; several views can show
+// different regions of the same buffer
+#[derive(Copy, Clone)]
+pub struct View {
+    pub file : usize,
+    pub cursor_pos : usize,
+    pub top_line : usize,
+    pub sel_anchor : Option<usize>,
+    // offset shown as 0 in the address column, like a partition start
+    pub base : usize,
+    pub pane : EditorPane,
+}
+
+impl View {
+
+    pub fn new(file : usize, pane : EditorPane) -> View {
+        View {
+            file,
+            cursor_pos : 0,
+            top_line : 0,
+            sel_anchor : None,
+            base : 0,
+            pane,
+        }
+    }
+
+    // inclusive range between the selection anchor and the cursor
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        self.sel_anchor.map(|anchor| {
+            if anchor <= self.cursor_pos {
+                (anchor, self.cursor_pos)
+            } else {
+                (self.cursor_pos, anchor)
+            }
+        })
+    }
+
+    pub fn is_selected(&self, pos : usize) -> bool {
+        match self.selection() {
+            Some((start, end)) => pos >= start && pos <= end,
+            None => false,
+        }
+    }
+
+    // keep the cursor and selection inside a buffer of `len` bytes
+    pub fn clamp(&mut self, len : usize) {
+        if self.cursor_pos >= len {
+            self.cursor_pos = len.saturating_sub(1);
+        }
+        if self.sel_anchor.is_some_and(|anchor| anchor >= len) {
+            self.sel_anchor = None;
+        }
+        if self.base >= len {
+            self.base = 0;
+        }
+    }
+
+}